@@ -1,7 +1,8 @@
 use std::{
     fs,
-    collections::VecDeque,
-    io::{Read, Write}
+    collections::{VecDeque, HashSet},
+    io::{Read, Write},
+    sync::{Mutex, PoisonError}
 };
 use nonempty::NonEmpty;
 use rocket::Either;
@@ -12,6 +13,25 @@ use super::*;
 
 pub static GAMES_PATH: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("./routes/games/"));
 pub static PLATFORM_PREFIX: &str = "plat-";
+/// The games whose virtual files (see [`GameInfo::create_file_locations()`]) are up to date.
+/// [`None`] while the [`watcher`](super::watcher) is not running, since then there is no way to know that a game didn't change.
+static FILES_CREATED: Lazy<Mutex<Option<HashSet<String>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Sets whether the [`watcher`](super::watcher) is running, which will tell when the files of a game change.
+pub fn set_watched(watched: bool) {
+    // Also called while the watcher's thread panics, which must not panic again
+    *FILES_CREATED.lock().unwrap_or_else(PoisonError::into_inner) = watched.then(HashSet::new);
+}
+/// The virtual files of the game in **dir_name** will be created again the next time they are needed.
+/// [`None`] is for all games.
+pub fn invalidate_files(dir_name: Option<&str>) {
+    if let Some(created) = FILES_CREATED.lock().unwrap().as_mut() {
+        match dir_name {
+            Some(dir_name) => { created.remove(dir_name); },
+            None => created.clear()
+        }
+    }
+}
 
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -152,6 +172,8 @@ pub struct GameInfo {
     pub platforms: NonEmpty<String>,
     pub store_urls: Option<NonEmpty<String>>,
     pub ost_dir_name: Option<String>,
+    /// Overrides the `games` archive's [`Access`] for this game.
+    pub access: Option<Access>,
     #[serde(skip)]
     pub dir_name: String,
     #[serde(skip)]
@@ -164,6 +186,10 @@ impl GameInfo {
     pub fn path(&self) -> PathBuf {
         GAMES_PATH.join(&self.dir_name)
     }
+    /// The [`Access`] of this game: its own, or the archive's if it doesn't have one.
+    pub fn access<'a>(&'a self, config: &'a AccessConfig) -> &'a Access {
+        self.access.as_ref().unwrap_or(&config.games)
+    }
     pub fn url(&self) -> Url {
         Url::from(self.path())
     }
@@ -224,6 +250,7 @@ impl GameInfo {
     /// Create files and directories in `"{Self::VIRTUAL_FILES_DIR}/{game}/"` for easier iteration in [`Self::files()`].
     /// Writes the information of each file in the respective directory, using **Breadth-First** traversal of the filesystem.
     /// Returns the path of the directory written to.
+    /// While the [`watcher`](super::watcher) is running, the files are only written again after the game changes.
     /// 
     /// Format of files in *virtual directory*:
     ///  - A file with a single line `normal` is not for a platform (i.e. the actual file is not inside a folder like "plat-linux").
@@ -237,14 +264,26 @@ impl GameInfo {
     /// Panics if can't write there.
     fn create_file_locations(&self) -> Result<PathBuf, Conflict> {
         let target_path = PathBuf::from(Self::VIRTUAL_FILES_DIR).join(&self.dir_name);
-        // TODO: return if no files have changed in self.path() since last call.
-        // if target_path.exists() {
-        //     return;
-        // }
+        // Return if no files have changed in self.path() since last call.
+        // Marked before creating the files, so that a change while they are created makes them be created again.
+        if let Some(created) = FILES_CREATED.lock().unwrap().as_mut() {
+            if !created.insert(self.dir_name.clone()) {
+                return Ok(target_path);
+            }
+        }
+
+        let result = self.write_file_locations(&target_path);
+        if result.is_err() {
+            invalidate_files(Some(&self.dir_name));
+        }
+        result.map(|()| target_path)
+    }
+
+    fn write_file_locations(&self, target_path: &Path) -> Result<(), Conflict> {
         let bins = self.binaries();
 
         // Remove target dir. if doesnt exist, its better (hence drop)
-        drop(fs::remove_dir_all(&target_path));
+        drop(fs::remove_dir_all(target_path));
 
         // Add non-platformed files first.
         for path in FsBfs::new_skip_entries(&self.path(), |path| {
@@ -319,7 +358,7 @@ impl GameInfo {
             }
         }
 
-        Ok(target_path)
+        Ok(())
     }
 
     /// Get the **directories (`0`)** that contain files specific to a **platform (`1`)** or **architecture (`2`)**.
@@ -385,25 +424,50 @@ impl_error_response!(GameReadError);
 
 
 #[get("/")]
-fn index(user: Option<auth::User>) -> Html<TextStream![String]> {
-    Html(TextStream(render_component::<components::GamesBrowser>(user.into())))
+fn index(user: Option<auth::User>, uri: &Origin<'_>, access: &State<AccessConfig>) -> Result<Html<TextStream![String]>, Denied> {
+    access.games.check(user.as_ref())?;
+
+    let (games, errors) = read_all_dirs::<GameInfo>(&GAMES_PATH);
+    Ok(Html(TextStream(render_component::<components::GamesBrowser>(components::GamesBrowserProps {
+        // Hide the games the user can't see
+        games: games.into_iter()
+            .filter(|game| game.access(access).allows(user.as_ref()))
+            .collect(),
+        errors: errors.into_iter()
+            .map(|(dir_name, error)| (dir_name, error.to_string()))
+            .collect(),
+        user: UserInfo::from(user).at(uri),
+    }))))
+}
+
+/// Reads the game and checks that the **user** can see it.
+fn read_game(user: Option<&auth::User>, access: &AccessConfig, game: &str) -> ArchiveResult<GameInfo, GameReadError> {
+    let game = GameInfo::read_dir(&GAMES_PATH.join(game)).map_err(Either::Right)?;
+    game.access(access).check(user).map_err(Either::Left)?;
+    Ok(game)
 }
+
 #[get("/<game>", rank=1)]
-fn game(user: Option<auth::User>, game: String) -> Result<Html<TextStream![String]>, GameReadError> {
+fn game(user: Option<auth::User>, uri: &Origin<'_>, access: &State<AccessConfig>, game: String) -> ArchiveResult<Html<TextStream![String]>, GameReadError> {
+    let game = read_game(user.as_ref(), access, &game)?;
+
     Ok(Html(TextStream(render_component::<components::Game>(components::GameProps {
-        user: user.into(),
-        game: GameInfo::read_dir(&GAMES_PATH.join(game))?
+        user: UserInfo::from(user).at(uri),
+        game
     }))))
 }
 
 /// GET path is done this way because `/<game>/<file..> seems to precede [`crate::sass::serve_css`].
 #[get("/<game>/<first>/<rest..>")]
-fn file(game: String, first: String, rest: PathBuf) -> io::Result<std::fs::File> {
+fn file(user: Option<auth::User>, access: &State<AccessConfig>, game: String, first: String, rest: PathBuf) -> ArchiveResult<std::fs::File, Either<GameReadError, io::Error>> {
+    read_game(user.as_ref(), access, &game)
+        .map_err(|error| error.map_right(Either::Left))?;
+
     if rest.as_os_str().is_empty() {
         std::fs::File::open(GAMES_PATH.join(game).join(first))
     } else {
         std::fs::File::open(GAMES_PATH.join(game).join(first).join(rest))
-    }
+    }.map_err(|error| Either::Right(Either::Right(error)))
 }
 
 pub fn routes() -> Vec<Route> {
@@ -0,0 +1,188 @@
+use std::{
+    io,
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use once_cell::sync::Lazy;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use super::{FromDir, FromFile, INFO_FILE_NAME};
+
+/// Where the [`Index`]es are stored. Like the exported song covers, they can be deleted at any time.
+pub static INDEX_PATH: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("./target/index/"));
+
+
+/// The modification time and size of a file, which change when the file is changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+    modified: SystemTime,
+    size: u64,
+}
+impl Stamp {
+    /// [`None`] if there is no file at **path**.
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            size: metadata.len(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    /// Of the files that the item was read from, when it was read.
+    stamps: Vec<Option<Stamp>>,
+    /// Items that couldn't be read are also kept, so that they are not read again until they change.
+    item: Result<T, String>,
+}
+
+/// The items read from a directory, and the errors of the ones that couldn't be read, by name.
+pub type Listing<T> = (Vec<T>, Vec<(String, String)>);
+
+/// Items (e.g. [`AlbumInfo`](super::osts::AlbumInfo)s) read from the files of an archive, stored on disk as JSON.
+/// An item is only read again when the [`Stamp`] of one of its files changes,
+/// so listing the items of a directory doesn't need to read all of them.
+///
+/// A directory's item is read from the directory and its `info.json`, and a file's item from only the file.
+///
+/// Clones share the same items, so that the [`watcher`](super::watcher) can [`invalidate()`](Self::invalidate) them.
+pub struct Index<T> {
+    path: PathBuf,
+    entries: Arc<Mutex<HashMap<PathBuf, Entry<T>>>>,
+}
+impl<T> Clone for Index<T> {
+    fn clone(&self) -> Self {
+        Self { path: self.path.clone(), entries: Arc::clone(&self.entries) }
+    }
+}
+impl<T: Serialize + DeserializeOwned + Clone + Ord> Index<T> {
+    /// Loads the index stored at **path**, or starts an empty one if it doesn't exist or is from an older version of the server.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|error| {
+                eprintln!("Index {path:?} is invalid, it will be built again: {error}");
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new()
+        };
+        Self { path, entries: Arc::new(Mutex::new(entries)) }
+    }
+
+    /// Forgets the item read from **path** and the items in it, so that they are read again the next time they are listed,
+    /// even if their [`Stamp`]s didn't change (e.g. a file replaced by one with the same size and modification time).
+    pub fn invalidate(&self, path: &Path) {
+        self.entries.lock().unwrap()
+            .retain(|entry_path, _| !entry_path.starts_with(path));
+    }
+
+    /// Like [`read_all_dirs()`](super::read_all_dirs), but only reads the directories that changed since they were last read.
+    /// Fails if **dir** can't be read, e.g. it was removed while the server was running.
+    pub fn read_all_dirs(&self, dir: &Path) -> io::Result<Listing<T>>
+    where T: FromDir, T::Error: Display {
+        let paths = std::fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.metadata().ok().is_some_and(|m| m.is_dir() || m.is_symlink()))
+            .map(|entry| entry.path());
+
+        Ok(self.read_all(dir, paths,
+            |path| vec![Stamp::of(path), Stamp::of(&path.join(INFO_FILE_NAME))],
+            |path| T::read_dir(path).map_err(|error| error.to_string())
+        ))
+    }
+
+    /// Reads all *files* in **dir** into [`T`]s, but only the files that changed since they were last read.
+    /// Fails if **dir** can't be read.
+    pub fn read_all_files(&self, dir: &Path) -> io::Result<Listing<T>>
+    where T: FromFile, T::Error: Display {
+        let paths = std::fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.metadata().ok().is_some_and(|m| m.is_file() || m.is_symlink()))
+            .filter(T::filter_file)
+            .map(|entry| entry.path());
+
+        Ok(self.read_all(dir, paths,
+            |path| vec![Stamp::of(path)],
+            |path| T::read_file(path).map_err(|error| error.to_string())
+        ))
+    }
+
+    fn read_all(
+        &self,
+        dir: &Path,
+        paths: impl Iterator<Item = PathBuf>,
+        stamps: impl Fn(&Path) -> Vec<Option<Stamp>>,
+        read: impl Fn(&Path) -> Result<T, String>
+    ) -> Listing<T> {
+        let paths = paths
+            .map(|path| {
+                let stamps = stamps(&path);
+                (path, stamps)
+            })
+            .collect::<Vec<_>>();
+
+        // Read the items outside of the lock, since it can take a while (e.g. a new album with many songs)
+        let outdated = {
+            let entries = self.entries.lock().unwrap();
+            paths.iter()
+                .filter(|(path, stamps)| entries.get(path).is_none_or(|entry| &entry.stamps != stamps))
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>()
+        };
+        let read = outdated.into_iter()
+            .map(|path| {
+                let item = read(&path);
+                (path, item)
+            })
+            .collect::<Vec<_>>();
+
+        let mut entries = self.entries.lock().unwrap();
+        let mut changed = !read.is_empty();
+        let current = paths.iter().cloned().collect::<HashMap<_, _>>();
+        for (path, item) in read {
+            let stamps = current[&path].clone();
+            entries.insert(path, Entry { stamps, item });
+        }
+        // Forget the items that were deleted
+        let len = entries.len();
+        entries.retain(|path, _| path.parent() != Some(dir) || current.contains_key(path));
+        changed |= entries.len() != len;
+
+        let mut items = Vec::new();
+        let mut errors = Vec::new();
+        // Another request could have removed some entries while the items were read
+        for (path, entry) in paths.iter().filter_map(|(path, _)| Some((path, entries.get(path)?))) {
+            match &entry.item {
+                Ok(item) => items.push(item.clone()),
+                Err(error) => errors.push((path.file_name().unwrap().to_string_lossy().to_string(), error.clone()))
+            }
+        }
+
+        if changed {
+            self.save(&entries);
+        }
+        items.sort();
+        (items, errors)
+    }
+
+    /// Writes the index to its file. Failing to write is only reported,
+    /// since the index would just be built again the next time the server starts.
+    fn save(&self, entries: &HashMap<PathBuf, Entry<T>>) {
+        let result = serde_json::to_vec(entries)
+            .map_err(io::Error::from)
+            .and_then(|json| {
+                if let Some(dir) = self.path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                // Written to another file first, so that the index is not left half written if the server stops
+                let temp = self.path.with_extension("tmp");
+                std::fs::write(&temp, json)?;
+                std::fs::rename(temp, &self.path)
+            });
+        if let Err(error) = result {
+            eprintln!("Can't save index {:?}: {error}", self.path);
+        }
+    }
+}
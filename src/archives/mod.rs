@@ -1,17 +1,133 @@
 pub mod osts;
 pub mod games;
+pub mod tags;
+pub mod index;
+pub mod watcher;
 
-use std::{path::Path, fs::DirEntry, fmt::Display, rc::Rc};
+use std::{path::{Path, Component}, fs::DirEntry, fmt::Display, rc::Rc};
+use nonempty::NonEmpty;
 use rocket::{
-    Route,
+    Route, Either,
     response::content::RawHtml as Html
 };
+use serde::{Serialize, Deserialize};
+use crate::components::UserInfo;
 use super::*;
 
 pub static INFO_FILE_NAME: &str = "info.json";
 pub static THUMB_NAME: &str = "thumbnail";
 
 
+/// Who can see an archive, or an item (album, game) in an archive.
+/// 
+/// In JSON, is one of `"public"`, `"logged-in"`, or `{ "groups": ["group", ...] }`.
+/// Admins can always see everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Access {
+    /// Anyone, even visitors that are not logged in.
+    #[default]
+    Public,
+    /// Only users that are logged in.
+    LoggedIn,
+    /// Only users in at least one of these groups.
+    Groups(NonEmpty<String>)
+}
+impl Access {
+    pub fn allows(&self, user: Option<&auth::User>) -> bool {
+        match self {
+            Self::Public => true,
+            Self::LoggedIn => user.is_some(),
+            Self::Groups(groups) => user.is_some_and(|user|
+                user.is_admin() || groups.iter().any(|group| user.in_group(group))
+            )
+        }
+    }
+
+    /// Like [`Self::allows()`], but returns the response to give to a user that is not allowed.
+    pub fn check(&self, user: Option<&auth::User>) -> Result<(), Denied> {
+        if self.allows(user) {
+            Ok(())
+        } else if user.is_none() {
+            Err(Denied::Login)
+        } else {
+            Err(Denied::Forbidden)
+        }
+    }
+}
+
+/// The [`Access`] of each archive, used for the items that don't have their own.
+/// Read from the `access` table of the server's config (e.g. `Rocket.toml`).
+/// 
+/// An item (album or game directory) can have its own [`Access`] with the `"access"` key in its `info.json`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AccessConfig {
+    pub osts: Access,
+    pub games: Access,
+    /// Browsing the server's files in `/files`.
+    pub files: Access
+}
+impl AccessConfig {
+    /// Find the [`Access`] of a file or directory in the server's files.
+    /// Files inside an archive must also be allowed by the archive's (or the item's) [`Access`].
+    pub fn check_path(&self, path: &Path, user: Option<&auth::User>) -> Result<(), Denied> {
+        self.files.check(user)?;
+
+        let path = without_cur_dir(path);
+        for (archive, access) in [(&*osts::ALBUMS_PATH, &self.osts), (&*games::GAMES_PATH, &self.games)] {
+            if let Ok(rest) = path.strip_prefix(without_cur_dir(archive)) {
+                return match rest.components().next() {
+                    Some(item) => read_item_access(&archive.join(item))
+                        .as_ref()
+                        .unwrap_or(access)
+                        .check(user),
+                    None => access.check(user)
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read only the `"access"` key of an item's `info.json`, without reading the rest of the item.
+pub fn read_item_access(dir: &Path) -> Option<Access> {
+    #[derive(Deserialize)]
+    struct AccessJson {
+        access: Option<Access>
+    }
+
+    serde_json::from_str::<AccessJson>(&std::fs::read_to_string(dir.join(INFO_FILE_NAME)).ok()?)
+        .ok()?
+        .access
+}
+
+fn without_cur_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|comp| comp != &Component::CurDir)
+        .collect()
+}
+
+/// Response when a user can't see an archive or an item.
+#[derive(Debug)]
+pub enum Denied {
+    /// Visitors that are not logged in are sent to log in first.
+    Login,
+    Forbidden
+}
+impl<'r> rocket::response::Responder<'r, 'static> for Denied {
+    fn respond_to(self, req: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::Login => auth::login_redirect(req.uri()).respond_to(req),
+            Self::Forbidden => Err(Status::Forbidden)
+        }
+    }
+}
+/// The result of a route that shows an item of an archive.
+pub type ArchiveResult<T, E> = Result<T, Either<Denied, E>>;
+
+
 /// Represents the URL of a file or resource in `./routes` that is given to the client.
 #[derive(Debug)]
 pub struct Url(PathBuf);
@@ -80,31 +196,6 @@ pub fn read_all_dirs<T: FromDir + Ord>(dir: &Path) -> (Vec<T>, Vec<(String, T::E
     (items, errors)
 }
 
-/// Tries to read all *files* in **path** and initialize [`T`]s from each *file*'s content.
-/// The files that couldn't be read into [`T`]s are put in the **error Vec**,
-/// along with the [`T::Error`] itself.
-pub fn read_all_files<T: FromFile + Ord>(file: &Path) -> (Vec<T>, Vec<(String, T::Error)>) {
-    let mut errors = Vec::new();
-    let mut items = Vec::new();
-    
-    for entry in std::fs::read_dir(file)
-        .expect("Can't read dir")
-        .filter_map(Result::ok)
-        .filter(|entry| entry.metadata().ok().is_some_and(|m| m.is_file() || m.is_symlink()))
-    {
-        if !T::filter_file(&entry) {
-            continue
-        }
-        match T::read_file(&entry.path()) {
-            Ok(album) => items.push(album),
-            Err(error) => errors.push((entry.path().file_name().unwrap().to_string_lossy().to_string(), error))
-        }
-    }
-
-    items.sort();
-    (items, errors)
-}
-
 pub trait FromDir: Sized {
     type Error;
     /// path must be a directory, relative to server root.
@@ -1,17 +1,18 @@
 use nonempty::NonEmpty;
-use serde_json::Value;
 use std::io::{BufReader, BufRead, Write};
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 use thiserror::Error;
 use super::*;
 use crate::components::osts as components;
+use super::tags::{Tags, TagError};
+use super::index::Index;
 
 pub static ALBUMS_PATH: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("./routes/osts/albums/"));
 static COVER_EXPORTS_PATH: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("./target/song-covers/"));
 static SKIP_EXPORT_PATH: Lazy<PathBuf> = Lazy::new(|| COVER_EXPORTS_PATH.join("skip-cover-export.txt"));
 
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 // TODO: in album page, have a player for each song next to the entry
 // ALso have equalizer animation when song plays
 // User can upload song
@@ -24,9 +25,16 @@ pub struct AlbumInfo {
     pub release_year: Option<u32>,
     pub dir_name: String,
     pub size: u32,
-    pub complete: bool
+    pub complete: bool,
+    /// Overrides the `osts` archive's [`Access`] for this album.
+    pub access: Option<Access>
 }
 impl AlbumInfo {
+    /// The [`Access`] of this album: its own, or the archive's if it doesn't have one.
+    pub fn access<'a>(&'a self, config: &'a AccessConfig) -> &'a Access {
+        self.access.as_ref().unwrap_or(&config.osts)
+    }
+
     pub fn find_cover_file(album_dir_name: &str) -> Option<PathBuf> {
         find_files_start(ALBUMS_PATH.join(album_dir_name), THUMB_NAME, true).into_iter().next()
     }
@@ -42,7 +50,8 @@ impl FromDir for AlbumInfo {
             artists: Option<NonEmpty<String>>,
             remixes: Option<NonEmpty<String>>,
             release_year: Option<u32>,
-            complete: Option<bool>
+            complete: Option<bool>,
+            access: Option<Access>
         }
 
         let mut thumbnail_path = None;
@@ -80,7 +89,8 @@ impl FromDir for AlbumInfo {
             remixes: info.remixes,
             dir_name: path.file_name().unwrap().to_string_lossy().to_string(),
             size,
-            complete: info.complete.is_some_and(|c| c)
+            complete: info.complete.is_some_and(|c| c),
+            access: info.access
         })
     }
 }
@@ -98,7 +108,7 @@ pub enum AlbumReadError {
 impl_error_response!(AlbumReadError);
 
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SongInfo {
     pub title: String,
     pub cover: SongCover,
@@ -119,9 +129,7 @@ impl FromFile for SongInfo {
     }
     fn read_file(path: &Path) -> Result<Self, Self::Error> {
         let album_dir_name = path.parent().unwrap().file_name().unwrap().to_string_lossy().to_string();
-        let mut command = Command::new("./audio-tag.py");
-        command.arg("--json")
-            .arg(&path);
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
 
         // Find if Song uses album's cover
         let use_album_cover = match std::fs::File::open(&*SKIP_EXPORT_PATH) {
@@ -135,16 +143,13 @@ impl FromFile for SongInfo {
             Err(_) => false
         };
 
-        // Do not export Song's Cover if it uses Album's cover
-        if !use_album_cover {
-            command.arg("--update-covers")
-                .arg("--export-covers-dir").arg(&*COVER_EXPORTS_PATH);
-        };
-
-        let output = command.output()
-            .map_err(|err| SongReadError::Command(err))?;
-        if !output.status.success() {
-            return Err(SongReadError::AudioTag(command_output(output.stderr)))
+        // Only read the Song's Cover if it has changed since it was exported
+        let export_cover = !use_album_cover && !cover_exported(path, &file_name);
+        let tags = Tags::read(path, export_cover)?;
+        if let Some(picture) = tags.cover().filter(|_| export_cover) {
+            std::fs::create_dir_all(&*COVER_EXPORTS_PATH)
+                .and_then(|()| std::fs::write(COVER_EXPORTS_PATH.join(format!("{file_name}.{}", picture.extension())), &picture.data))
+                .map_err(SongReadError::CoverExport)?;
         }
 
         // Don't waste time looking for exported files that don't exist
@@ -152,11 +157,7 @@ impl FromFile for SongInfo {
             SongCover::UseAlbum
         } else {
             // Find if a Cover Images was exported (if any)
-            let mut exports = find_files_start(
-                &*COVER_EXPORTS_PATH,
-                &path.file_name().unwrap().to_string_lossy().to_string(),
-                true
-            ).into_iter();
+            let mut exports = find_files_start(&*COVER_EXPORTS_PATH, &file_name, true).into_iter();
             // Note: Song could have multiple covers
             match exports.next() {
                 Some(cover) => match AlbumInfo::find_cover_file(&album_dir_name) {
@@ -193,62 +194,51 @@ impl FromFile for SongInfo {
             }
         };
 
-        let mut json = serde_json::from_str::<serde_json::Map<String, Value>>(&command_output(output.stdout))
-            .map_err(|err| SongReadError::Json(err))?;
-
         Ok(Self {
-            title: match json.remove("title") {
-                Some(Value::String(title)) => title,
-                _ => path.with_extension("").file_name().unwrap().to_string_lossy().to_string()
-            },
-            artists: match json.remove("artist") {
-                Some(Value::String(artist)) => Some(
-                    NonEmpty::collect(
-                        artist.split(',').map(|artist| artist.trim().to_string())
-                    )
-                    .unwrap_or(NonEmpty::new(artist))
-                ),
-                _ => None
-            },
-            release_year: match json.remove("release-year") {
-                Some(Value::Number(year)) if year.is_u64() => Some(year.as_u64().unwrap() as u32),
-                _ => None
-            },
-            track_num: match json.remove("track-number") {
-                Some(Value::String(track_num)) =>
-                    match track_num.split_once('/') {
-                        Some((num, _)) => num,
-                        None => track_num.as_str()
-                    }
-                    .parse().ok(),
-                _ => None
-            },
-            length: match json.remove("length") {
-                Some(Value::Number(length)) if length.is_u64() => helpers::display_duration(length.as_u64().unwrap()),
-                _ => return Err(SongReadError::NoLength)
+            title: tags.title.clone()
+                .unwrap_or_else(|| path.with_extension("").file_name().unwrap().to_string_lossy().to_string()),
+            artists: tags.artist.as_ref().map(|artist|
+                NonEmpty::collect(
+                    artist.split(',').map(|artist| artist.trim().to_string())
+                )
+                .unwrap_or(NonEmpty::new(artist.clone()))
+            ),
+            release_year: tags.release_year(),
+            track_num: tags.track_number(),
+            length: match tags.duration {
+                Some(length) => helpers::display_duration(length.as_secs_f64().round() as u64),
+                None => return Err(SongReadError::NoLength)
             },
             cover,
-            file_name: path.file_name().unwrap().to_string_lossy().to_string(),
+            file_name,
             album_dir_name,
         })
     }
 }
+/// Whether the cover of the song at **path** was exported after the song last changed.
+fn cover_exported(path: &Path, file_name: &str) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified());
+    let song = match modified(path) {
+        Ok(song) => song,
+        Err(_) => return false
+    };
+    find_files_start(&*COVER_EXPORTS_PATH, file_name, true).into_iter()
+        .any(|export| modified(&export).is_ok_and(|export| export >= song))
+}
 impl_ord!(SongInfo, title);
 
 #[derive(Debug, Error)]
 pub enum SongReadError {
-    #[error("Cannot run audio-tag.py: {0:?}")]
-    Command(io::Error),
-    #[error("audio-tag.py exited with error: {0}")]
-    AudioTag(String),
-    #[error("Could not parse JSON: {0:?}")]
-    Json(serde_json::Error),
-    #[error("Output does not contain Song's length")]
+    #[error("Cannot read tags: {0}")]
+    Tags(#[from] TagError),
+    #[error("Cannot export cover: {0:?}")]
+    CoverExport(io::Error),
+    #[error("File does not contain Song's length")]
     NoLength
 }
 impl_error_response!(SongReadError);
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SongCover {
     Some(PathBuf),
     UseAlbum,
@@ -261,28 +251,59 @@ fn index() -> Redirect {
     Redirect::to(uri!("/osts/albums"))
 }
 #[get("/albums")]
-fn albums(user: Option<auth::User>) -> Html<TextStream![String]> {
-    Html(TextStream(render_component::<components::AlbumBrowser>(user.into())))
+fn albums(user: Option<auth::User>, uri: &Origin<'_>, access: &State<AccessConfig>, index: &State<Index<AlbumInfo>>) -> ArchiveResult<Html<TextStream![String]>, io::Error> {
+    access.osts.check(user.as_ref()).map_err(Either::Left)?;
+
+    let (albums, errors) = index.read_all_dirs(&ALBUMS_PATH).map_err(Either::Right)?;
+    Ok(Html(TextStream(render_component::<components::AlbumBrowser>(components::AlbumBrowserProps {
+        // Hide the albums the user can't see
+        albums: albums.into_iter()
+            .filter(|album| album.access(access).allows(user.as_ref()))
+            .collect(),
+        errors,
+        user: UserInfo::from(user).at(uri),
+    }))))
+}
+
+/// Reads the album and checks that the **user** can see it.
+fn read_album(user: Option<&auth::User>, access: &AccessConfig, album_dir_name: &str) -> ArchiveResult<AlbumInfo, AlbumReadError> {
+    let album = AlbumInfo::read_dir(&ALBUMS_PATH.join(album_dir_name)).map_err(Either::Right)?;
+    album.access(access).check(user).map_err(Either::Left)?;
+    Ok(album)
 }
 
 #[get("/albums/<album_dir_name>")]
-fn view_album(user: Option<auth::User>, album_dir_name: String) -> Result<Html<TextStream![String]>, AlbumReadError> {
+fn view_album(user: Option<auth::User>, uri: &Origin<'_>, access: &State<AccessConfig>, index: &State<Index<SongInfo>>, album_dir_name: String) -> ArchiveResult<Html<TextStream![String]>, AlbumReadError> {
+    let album = read_album(user.as_ref(), access, &album_dir_name)?;
+    let (songs, errors) = index.read_all_files(&ALBUMS_PATH.join(&album_dir_name))
+        .map_err(|error| Either::Right(AlbumReadError::Io(error)))?;
+
     Ok(Html(TextStream(render_component::<components::Album>(components::AlbumProps {
-        user: user.into(),
-        album: AlbumInfo::read_dir(&ALBUMS_PATH.join(album_dir_name))?
+        user: UserInfo::from(user).at(uri),
+        album,
+        songs,
+        errors
     }))))
 }
 
 #[get("/albums/<album_dir_name>/<song_file_name>", format = "text/html")]
-fn view_song(user: Option<auth::User>, album_dir_name: String, song_file_name: String) -> Result<Html<TextStream![String]>, SongReadError> {
+fn view_song(user: Option<auth::User>, uri: &Origin<'_>, access: &State<AccessConfig>, album_dir_name: String, song_file_name: String) -> ArchiveResult<Html<TextStream![String]>, Either<AlbumReadError, SongReadError>> {
+    read_album(user.as_ref(), access, &album_dir_name)
+        .map_err(|error| error.map_right(Either::Left))?;
+
     Ok(Html(TextStream(render_component::<components::Song>(components::SongProps {
-        user: user.into(),
-        song: SongInfo::read_file(&ALBUMS_PATH.join(album_dir_name).join(song_file_name))?
+        user: UserInfo::from(user).at(uri),
+        song: SongInfo::read_file(&ALBUMS_PATH.join(album_dir_name).join(song_file_name))
+            .map_err(|error| Either::Right(Either::Right(error)))?
     }))))
 }
 #[get("/albums/<album_dir_name>/<song_file_name>", format = "audio/webm", rank = 1)]
-async fn song_file(album_dir_name: String, song_file_name: String) -> io::Result<File> {
+async fn song_file(user: Option<auth::User>, access: &State<AccessConfig>, album_dir_name: String, song_file_name: String) -> ArchiveResult<File, Either<AlbumReadError, io::Error>> {
+    read_album(user.as_ref(), access, &album_dir_name)
+        .map_err(|error| error.map_right(Either::Left))?;
+
     File::open(ALBUMS_PATH.join(album_dir_name).join(song_file_name)).await
+        .map_err(|error| Either::Right(Either::Right(error)))
 }
 
 pub fn routes() -> Vec<Route> {
@@ -0,0 +1,45 @@
+//! *FLAC* files, which start with metadata blocks.
+use super::*;
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+const PICTURE: u8 = 6;
+
+
+/// Reads the metadata blocks after the `fLaC` at the **reader**'s position.
+pub(super) fn read<R: Read + Seek>(reader: &mut R, tags: &mut Tags, pictures: bool) -> Result<(), TagError> {
+    reader.seek(SeekFrom::Current(4))?;
+
+    loop {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7F;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+
+        match kind {
+            STREAMINFO => {
+                let block = read_vec(reader, len, "FLAC")?;
+                let mut block = Bytes::new(&block, "FLAC");
+                // Minimum and maximum block and frame sizes
+                block.take(10)?;
+                // 20 bits of sample rate, 3 of channels, 5 of bits per sample and 36 of total samples
+                let bits = block.u64_be()?;
+                let sample_rate = bits >> 44;
+                let samples = bits & 0xF_FFFF_FFFF;
+                if sample_rate != 0 && samples != 0 {
+                    tags.duration = Some(Duration::from_secs_f64(samples as f64 / sample_rate as f64));
+                }
+            },
+            VORBIS_COMMENT => vorbis::read_comments(&read_vec(reader, len, "FLAC")?, tags, pictures)?,
+            PICTURE if pictures => tags.pictures.push(vorbis::read_picture(&read_vec(reader, len, "FLAC")?)?),
+            _ => {
+                reader.seek(SeekFrom::Current(len as i64))?;
+            }
+        }
+
+        if last {
+            return Ok(())
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! *ID3v2* tags, at the start of *MP3* files. Versions `2.2`, `2.3` and `2.4` are supported.
+use std::borrow::Cow;
+use super::*;
+
+const HEADER_LEN: u64 = 10;
+
+/// The id and data of a frame.
+type Frame<'a> = (String, Cow<'a, [u8]>);
+
+
+/// Reads the tag at the start of the **reader**, and returns where the audio after it starts.
+pub(super) fn read<R: Read + Seek>(reader: &mut R, tags: &mut Tags, pictures: bool) -> Result<u64, TagError> {
+    let header = read_vec(reader, HEADER_LEN, "ID3")?;
+    let mut header = Bytes::new(&header, "ID3");
+    header.take(3)?;
+    let version = header.u8()?;
+    let _revision = header.u8()?;
+    let flags = header.u8()?;
+    let size = syncsafe(header.take(4)?) as u64;
+
+    let footer = if version == 4 && flags & 0x10 != 0 { HEADER_LEN } else { 0 };
+    let end = HEADER_LEN + size + footer;
+    if !(2..=4).contains(&version) {
+        return Ok(end)
+    }
+
+    let body = read_vec(reader, size, "ID3")?;
+    // Version 2.4 marks unsynchronisation in each frame instead
+    let body = if flags & 0x80 != 0 && version < 4 {
+        Cow::Owned(unsync(&body))
+    } else {
+        Cow::Borrowed(&body[..])
+    };
+    let mut frames = Bytes::new(&body, "ID3");
+
+    if flags & 0x40 != 0 {
+        match version {
+            // Means that the tag is compressed, which no one supports
+            2 => return Ok(end),
+            3 => {
+                let len = frames.u32_be()?;
+                frames.take(len as usize)?;
+            },
+            _ => {
+                // Includes the size itself
+                let len = syncsafe(frames.take(4)?);
+                frames.take((len as usize).saturating_sub(4))?;
+            }
+        }
+    }
+
+    while let Ok(frame) = next_frame(&mut frames, version) {
+        match frame {
+            Some((id, data)) => read_frame(tags, &id, &data, pictures),
+            // Padding
+            None => break
+        }
+    }
+
+    Ok(end)
+}
+
+/// Reads the header of the next frame in **frames**, and returns its id and data.
+/// Returns [`None`] when it reaches the padding, which ends the frames.
+fn next_frame<'a>(frames: &mut Bytes<'a>, version: u8) -> Result<Option<Frame<'a>>, TagError> {
+    let (id, size, format_flags) = if version == 2 {
+        let id = frames.take(3)?;
+        let size = frames.take(3)?;
+        (id, u32::from_be_bytes([0, size[0], size[1], size[2]]), 0)
+    } else {
+        let id = frames.take(4)?;
+        let size = match version {
+            3 => frames.u32_be()?,
+            _ => syncsafe(frames.take(4)?)
+        };
+        let [_status_flags, format_flags] = frames.array()?;
+        (id, size, format_flags)
+    };
+    if id[0] == 0 {
+        return Ok(None)
+    }
+    let id = String::from_utf8_lossy(id).to_string();
+    let mut data = Bytes::new(frames.take(size as usize)?, "ID3");
+
+    let data = match version {
+        3 => {
+            // Compressed or encrypted
+            if format_flags & 0xC0 != 0 {
+                return Ok(Some((id, Cow::Borrowed(&[]))))
+            }
+            // Grouping identity
+            if format_flags & 0x20 != 0 {
+                data.u8()?;
+            }
+            Cow::Borrowed(data.rest())
+        },
+        4 => {
+            if format_flags & 0x0C != 0 {
+                return Ok(Some((id, Cow::Borrowed(&[]))))
+            }
+            if format_flags & 0x40 != 0 {
+                data.u8()?;
+            }
+            // Data length indicator
+            if format_flags & 0x01 != 0 {
+                data.take(4)?;
+            }
+            if format_flags & 0x02 != 0 {
+                Cow::Owned(unsync(data.rest()))
+            } else {
+                Cow::Borrowed(data.rest())
+            }
+        },
+        _ => Cow::Borrowed(data.rest())
+    };
+
+    Ok(Some((id, data)))
+}
+
+fn read_frame(tags: &mut Tags, id: &str, data: &[u8], pictures: bool) {
+    // Version 2.2 has 3 letter ids
+    let field = match id {
+        "TIT2" | "TT2" => &mut tags.title,
+        "TPE1" | "TP1" => &mut tags.artist,
+        "TALB" | "TAL" => &mut tags.album,
+        "TPE2" | "TP2" => &mut tags.album_artist,
+        "TDRC" | "TYER" | "TYE" => &mut tags.date,
+        "TRCK" | "TRK" => &mut tags.track,
+        "APIC" | "PIC" => {
+            if pictures {
+                if let Ok(picture) = picture(data, id == "PIC") {
+                    tags.pictures.push(picture)
+                }
+            }
+            return
+        },
+        _ => return
+    };
+
+    let mut data = Bytes::new(data, "ID3");
+    if let Ok(encoding) = data.u8() {
+        // Version 2.4 can have multiple values separated by null
+        if let Some(value) = decode(encoding, data.rest()).split('\0').find(|value| !value.trim().is_empty()) {
+            set(field, value)
+        }
+    }
+}
+
+/// Reads an attached picture frame. **v2** is for the `PIC` frame of version 2.2, which has an image format instead of a mime type.
+fn picture(data: &[u8], v2: bool) -> Result<Picture, TagError> {
+    let mut data = Bytes::new(data, "ID3 picture");
+    let encoding = data.u8()?;
+
+    let mime = if v2 {
+        match data.take(3)? {
+            b"PNG" => "image/png".to_string(),
+            _ => "image/jpeg".to_string()
+        }
+    } else {
+        let (mime, rest) = terminated(0, data.rest()).ok_or(TagError::Invalid("ID3 picture"))?;
+        data = Bytes::new(rest, "ID3 picture");
+        let mime = String::from_utf8_lossy(mime).to_ascii_lowercase();
+        // Some programs write only the format
+        match mime.as_str() {
+            "" | "jpg" | "jpeg" => "image/jpeg".to_string(),
+            mime if !mime.contains('/') => format!("image/{mime}"),
+            _ => mime
+        }
+    };
+
+    let kind = data.u8()?;
+    let (_description, image) = terminated(encoding, data.rest()).ok_or(TagError::Invalid("ID3 picture"))?;
+    Ok(Picture { mime, kind, data: image.to_vec() })
+}
+
+/// Splits the string that ends with a null in the **encoding** from the rest of the **data**.
+fn terminated(encoding: u8, data: &[u8]) -> Option<(&[u8], &[u8])> {
+    match encoding {
+        1 | 2 => {
+            let end = data.chunks_exact(2).position(|ch| ch == [0, 0])? * 2;
+            Some((&data[..end], &data[end + 2..]))
+        },
+        _ => {
+            let end = data.iter().position(|&byte| byte == 0)?;
+            Some((&data[..end], &data[end + 1..]))
+        }
+    }
+}
+
+/// Decodes text in one of the encodings of *ID3*: *Latin-1*, *UTF-16* with or without BOM, or *UTF-8*.
+fn decode(encoding: u8, data: &[u8]) -> String {
+    let utf16 = |data: &[u8], little_endian: bool| {
+        let units = data.chunks_exact(2)
+            .map(|unit| if little_endian {
+                u16::from_le_bytes([unit[0], unit[1]])
+            } else {
+                u16::from_be_bytes([unit[0], unit[1]])
+            });
+        char::decode_utf16(units)
+            .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    };
+
+    match encoding {
+        0 => data.iter().map(|&byte| byte as char).collect(),
+        1 => match data {
+            [0xFE, 0xFF, rest @ ..] => utf16(rest, false),
+            [0xFF, 0xFE, rest @ ..] => utf16(rest, true),
+            _ => utf16(data, true)
+        },
+        2 => utf16(data, false),
+        _ => String::from_utf8_lossy(data).to_string()
+    }
+}
+
+/// An integer stored in 7 bits of each byte, so that it never contains the `0xFF` of an *MPEG* frame sync.
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |int, &byte| int << 7 | (byte & 0x7F) as u32)
+}
+
+/// Undoes the *unsynchronisation* scheme, which adds a `0x00` after every `0xFF`.
+fn unsync(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut prev = 0;
+    for &byte in data {
+        if !(prev == 0xFF && byte == 0x00) {
+            result.push(byte);
+        }
+        prev = byte;
+    }
+    result
+}
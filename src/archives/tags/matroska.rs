@@ -0,0 +1,224 @@
+//! *Matroska* files (`.mka`, and `.webm` which is a subset), made of nested *EBML* elements.
+use super::*;
+
+const EBML: u32 = 0x1A45DFA3;
+const DOC_TYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x18538067;
+
+const INFO: u32 = 0x1549A966;
+const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const DURATION: u32 = 0x4489;
+const TITLE: u32 = 0x7BA9;
+
+const TAGS: u32 = 0x1254C367;
+const TAG: u32 = 0x7373;
+const TARGETS: u32 = 0x63C0;
+const TARGET_TYPE_VALUE: u32 = 0x68CA;
+const SIMPLE_TAG: u32 = 0x67C8;
+const TAG_NAME: u32 = 0x45A3;
+const TAG_STRING: u32 = 0x4487;
+
+const ATTACHMENTS: u32 = 0x1941A469;
+const ATTACHED_FILE: u32 = 0x61A7;
+const FILE_NAME: u32 = 0x466E;
+const FILE_MIME_TYPE: u32 = 0x4660;
+const FILE_DATA: u32 = 0x465C;
+
+/// Tags with this *target type* or higher are about the whole album instead of the song.
+const ALBUM_TARGET: u64 = 50;
+/// Nanoseconds in each unit of the `Duration` of the segment, if it has no `TimestampScale`.
+const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+
+
+/// Reads an element id, which keeps the bits that mark its length.
+fn read_id<R: Read>(reader: &mut R) -> Result<u32, TagError> {
+    let mut first = [0];
+    reader.read_exact(&mut first)?;
+    let len = first[0].leading_zeros() as usize + 1;
+    if len > 4 {
+        return Err(TagError::Invalid("EBML"))
+    }
+    let mut rest = [0; 3];
+    reader.read_exact(&mut rest[..len - 1])?;
+    Ok(rest[..len - 1].iter().fold(first[0] as u32, |id, &byte| id << 8 | byte as u32))
+}
+
+/// Reads the size of an element's data. [`None`] means that the size is unknown, e.g. for live streams.
+fn read_size<R: Read>(reader: &mut R) -> Result<Option<u64>, TagError> {
+    let mut first = [0];
+    reader.read_exact(&mut first)?;
+    let len = first[0].leading_zeros() as usize + 1;
+    if len > 8 {
+        return Err(TagError::Invalid("EBML"))
+    }
+    let mut rest = [0; 7];
+    reader.read_exact(&mut rest[..len - 1])?;
+
+    let marker = 0x80 >> (len - 1);
+    let size = rest[..len - 1].iter().fold((first[0] & !marker) as u64, |size, &byte| size << 8 | byte as u64);
+    // All ones
+    if size == (1u64 << (7 * len)) - 1 {
+        Ok(None)
+    } else {
+        Ok(Some(size))
+    }
+}
+
+pub(super) fn read<R: Read + Seek>(reader: &mut R, tags: &mut Tags, pictures: bool) -> Result<(), TagError> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    if read_id(reader)? != EBML {
+        return Err(TagError::Invalid("EBML"))
+    }
+    let size = read_size(reader)?.ok_or(TagError::Invalid("EBML"))?;
+    let header = read_vec(reader, size, "EBML")?;
+    let doc_type = elements(&header)
+        .find(|&(id, _)| id == DOC_TYPE)
+        .map(|(_, doc_type)| String::from_utf8_lossy(doc_type).trim_end_matches('\0').to_string());
+    if !matches!(doc_type.as_deref(), Some("matroska" | "webm")) {
+        return Err(TagError::UnknownFormat)
+    }
+
+    if read_id(reader)? != SEGMENT {
+        return Err(TagError::Invalid("Matroska"))
+    }
+    let segment_end = match read_size(reader)? {
+        Some(size) => (reader.stream_position()? + size).min(file_end),
+        None => file_end
+    };
+
+    // Go through the top level elements. The audio is in clusters, which are skipped without being read.
+    let mut title = None;
+    while reader.stream_position()? < segment_end {
+        let id = read_id(reader)?;
+        let size = match read_size(reader)? {
+            Some(size) => size,
+            // Can't know where the element ends to skip it
+            None => break
+        };
+
+        match id {
+            INFO => read_info(&read_vec(reader, size, "Matroska")?, tags, &mut title),
+            TAGS => read_tags(&read_vec(reader, size, "Matroska")?, tags),
+            ATTACHMENTS if pictures => read_attachments(&read_vec(reader, size, "Matroska")?, tags),
+            // E.g. clusters
+            _ => {
+                reader.seek(SeekFrom::Current(size as i64))?;
+            }
+        }
+    }
+
+    // The segment's title is usually the same as the TITLE tag
+    if let Some(title) = title {
+        set(&mut tags.title, &title)
+    }
+    Ok(())
+}
+
+fn read_info(info: &[u8], tags: &mut Tags, title: &mut Option<String>) {
+    let mut scale = DEFAULT_TIMESTAMP_SCALE;
+    let mut duration = None;
+    for (id, data) in elements(info) {
+        match id {
+            TIMESTAMP_SCALE => scale = uint(data),
+            DURATION => duration = float(data),
+            TITLE => *title = Some(String::from_utf8_lossy(data).to_string()),
+            _ => {}
+        }
+    }
+
+    // Invalid durations (e.g. negative or too long) are left unknown
+    if let Some(duration) = duration {
+        tags.duration = Duration::try_from_secs_f64(duration * scale as f64 / 1e9).ok();
+    }
+}
+
+fn read_tags(data: &[u8], tags: &mut Tags) {
+    for (_, tag) in elements(data).filter(|&(id, _)| id == TAG) {
+        // Tags without a target are about the whole file, which is the song
+        let target = elements(tag)
+            .find(|&(id, _)| id == TARGETS)
+            .and_then(|(_, targets)| elements(targets).find(|&(id, _)| id == TARGET_TYPE_VALUE))
+            .map(|(_, value)| uint(value));
+        let album = target.is_some_and(|target| target >= ALBUM_TARGET);
+
+        for (_, simple_tag) in elements(tag).filter(|&(id, _)| id == SIMPLE_TAG) {
+            let mut name = None;
+            let mut value = None;
+            for (id, data) in elements(simple_tag) {
+                match id {
+                    TAG_NAME => name = Some(String::from_utf8_lossy(data).to_ascii_uppercase()),
+                    TAG_STRING => value = Some(String::from_utf8_lossy(data)),
+                    _ => {}
+                }
+            }
+            let (name, value) = match (name, value) {
+                (Some(name), Some(value)) => (name, value),
+                _ => continue
+            };
+
+            match (album, name.as_str()) {
+                (true, "TITLE") => set(&mut tags.album, &value),
+                (true, "ARTIST") => set(&mut tags.album_artist, &value),
+                (_, "DATE_RELEASED" | "DATE_RECORDED") => set(&mut tags.date, &value),
+                (false, "PART_NUMBER") => set(&mut tags.track, &value),
+                // Names like Vorbis comments, as written by ffmpeg
+                (false, name) => { tags.set_comment(name, &value); },
+                _ => {}
+            }
+        }
+    }
+}
+
+fn read_attachments(data: &[u8], tags: &mut Tags) {
+    for (_, file) in elements(data).filter(|&(id, _)| id == ATTACHED_FILE) {
+        let mut name = "";
+        let mut mime = String::new();
+        let mut content = None;
+        for (id, data) in elements(file) {
+            match id {
+                FILE_NAME => name = std::str::from_utf8(data).unwrap_or_default(),
+                FILE_MIME_TYPE => mime = String::from_utf8_lossy(data).to_ascii_lowercase(),
+                FILE_DATA => content = Some(data),
+                _ => {}
+            }
+        }
+
+        if let (true, Some(content)) = (mime.starts_with("image/"), content) {
+            tags.pictures.push(Picture {
+                // Covers are recognized by their name, e.g. "cover.jpg"
+                kind: if name.to_ascii_lowercase().starts_with("cover") { FRONT_COVER } else { 0 },
+                mime,
+                data: content.to_vec(),
+            })
+        }
+    }
+}
+
+/// The elements in some **data** that is already in memory, with their ids and data.
+fn elements(mut data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    std::iter::from_fn(move || {
+        let id = read_id(&mut data).ok()?;
+        let size = read_size(&mut data).ok()??;
+        if size > data.len() as u64 {
+            return None
+        }
+        let (element, rest) = data.split_at(size as usize);
+        data = rest;
+        Some((id, element))
+    })
+}
+
+/// An unsigned integer element, which can have 0 to 8 bytes.
+fn uint(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |int, &byte| int << 8 | byte as u64)
+}
+
+fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().unwrap()) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().unwrap())),
+        _ => None
+    }
+}
@@ -0,0 +1,245 @@
+//! Reads the tags (title, artist, cover art, etc.) and duration of the songs in the [`osts`](super::osts) archive.
+//!
+//! Supports *MP3* (with *ID3v2* tags), *FLAC*, *Ogg* (*Vorbis* and *Opus*), *MP4* (`.m4a`) and *Matroska*/*WebM*.
+mod id3;
+mod mpeg;
+mod vorbis;
+mod flac;
+mod ogg;
+mod mp4;
+mod matroska;
+
+use std::{
+    io::{self, Read, Seek, SeekFrom, BufReader},
+    fs::File,
+    path::Path,
+    time::Duration,
+};
+use thiserror::Error;
+
+/// Tags larger than this are rejected instead of read into memory, since their size could be corrupted.
+const MAX_TAG_SIZE: u64 = 64 << 20;
+/// *Picture type* (from *ID3*, also used by *FLAC*) of the front cover of an album.
+pub const FRONT_COVER: u8 = 3;
+
+
+/// The metadata of an audio file. Fields that the file doesn't have are [`None`].
+///
+/// When a file has the same field more than once (e.g. multiple `ARTIST` comments), only the first one is kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    /// Release date, usually `"YYYY"` or `"YYYY-MM-DD"`.
+    pub date: Option<String>,
+    /// Position of the song in the album, like `"2"` or `"2/12"`.
+    pub track: Option<String>,
+    pub duration: Option<Duration>,
+    /// Embedded cover art. Only read when asked for in [`Self::read()`].
+    pub pictures: Vec<Picture>,
+}
+impl Tags {
+    /// Reads the tags of the audio file at **path**.
+    /// Embedded **pictures** can be large, so they are skipped unless needed.
+    pub fn read(path: &Path, pictures: bool) -> Result<Self, TagError> {
+        Self::read_from(&mut BufReader::new(File::open(path)?), pictures)
+    }
+
+    /// Like [`Self::read()`], but for a file that is already open.
+    /// The format is found from the content, not from the file's extension.
+    pub fn read_from<R: Read + Seek>(reader: &mut R, pictures: bool) -> Result<Self, TagError> {
+        let mut tags = Self::default();
+
+        // ID3 tags are usually in MP3 files, but some programs also add them to other formats
+        let start = match magic(reader, 0)? {
+            [b'I', b'D', b'3', ..] => id3::read(reader, &mut tags, pictures)?,
+            _ => 0
+        };
+
+        match magic(reader, start)? {
+            [b'f', b'L', b'a', b'C', ..] => flac::read(reader, &mut tags, pictures)?,
+            [b'O', b'g', b'g', b'S', ..] => ogg::read(reader, &mut tags, pictures)?,
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => mp4::read(reader, &mut tags, pictures)?,
+            [0x1A, 0x45, 0xDF, 0xA3, ..] => matroska::read(reader, &mut tags, pictures)?,
+            _ => if !mpeg::read(reader, &mut tags, start)? && start == 0 {
+                return Err(TagError::UnknownFormat)
+            }
+        }
+
+        Ok(tags)
+    }
+
+    /// The year of the [`Self::date`].
+    pub fn release_year(&self) -> Option<u32> {
+        let date = self.date.as_deref()?.trim();
+        date.split(|ch: char| !ch.is_ascii_digit())
+            .next()
+            .filter(|year| year.len() == 4)?
+            .parse().ok()
+    }
+
+    /// The number of the [`Self::track`], without the number of tracks.
+    pub fn track_number(&self) -> Option<u32> {
+        let track = self.track.as_deref()?;
+        track.split_once('/')
+            .map_or(track, |(number, _)| number)
+            .trim()
+            .parse().ok()
+    }
+
+    /// The picture that best represents the song: its front cover, or else the first picture.
+    pub fn cover(&self) -> Option<&Picture> {
+        self.pictures.iter()
+            .find(|picture| picture.kind == FRONT_COVER)
+            .or_else(|| self.pictures.first())
+    }
+
+    /// Sets the field with the **key** of a *Vorbis comment*, which are also used by other formats.
+    /// Returns `false` if the **key** is not for a known field.
+    fn set_comment(&mut self, key: &str, value: &str) -> bool {
+        let field = match key.to_ascii_uppercase().as_str() {
+            "TITLE" => &mut self.title,
+            "ARTIST" => &mut self.artist,
+            "ALBUM" => &mut self.album,
+            "ALBUMARTIST" | "ALBUM ARTIST" | "ALBUM_ARTIST" => &mut self.album_artist,
+            "DATE" | "YEAR" => &mut self.date,
+            "TRACKNUMBER" => &mut self.track,
+            _ => return false
+        };
+        set(field, value);
+        true
+    }
+}
+
+/// Sets the **field** if it doesn't have a value yet, so that the first value in the file is kept.
+fn set(field: &mut Option<String>, value: &str) {
+    let value = value.trim_matches(|ch: char| ch.is_whitespace() || ch == '\0');
+    if field.is_none() && !value.is_empty() {
+        *field = Some(value.to_string())
+    }
+}
+
+/// An image embedded in an audio file, usually the album's cover.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Picture {
+    /// E.g. `"image/jpeg"`.
+    pub mime: String,
+    /// The *picture type* of *ID3*, e.g. [`FRONT_COVER`].
+    pub kind: u8,
+    pub data: Vec<u8>,
+}
+impl Picture {
+    /// Extension for a file with this picture.
+    pub fn extension(&self) -> &'static str {
+        match self.mime.as_str() {
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/webp" => "webp",
+            "image/bmp" => "bmp",
+            _ => "jpg"
+        }
+    }
+}
+impl std::fmt::Debug for Picture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The data is too long to print
+        f.debug_struct("Picture")
+            .field("mime", &self.mime)
+            .field("kind", &self.kind)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TagError {
+    #[error("IO Error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Unknown audio format")]
+    UnknownFormat,
+    #[error("Invalid {0} data")]
+    Invalid(&'static str),
+}
+
+
+/// The first bytes at **offset**, which tell the format of the file.
+fn magic<R: Read + Seek>(reader: &mut R, offset: u64) -> io::Result<[u8; 12]> {
+    let mut magic = [0; 12];
+    reader.seek(SeekFrom::Start(offset))?;
+    // The file could be shorter than the magic
+    let mut len = 0;
+    while len < magic.len() {
+        match reader.read(&mut magic[len..])? {
+            0 => break,
+            read => len += read
+        }
+    }
+    reader.seek(SeekFrom::Start(offset))?;
+    Ok(magic)
+}
+
+/// Reads the next **len** bytes, or fails if they are more than [`MAX_TAG_SIZE`].
+fn read_vec<R: Read>(reader: &mut R, len: u64, format: &'static str) -> Result<Vec<u8>, TagError> {
+    if len > MAX_TAG_SIZE {
+        return Err(TagError::Invalid(format))
+    }
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+    if (data.len() as u64) < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+    Ok(data)
+}
+
+/// Reads numbers and slices from the start of some data,
+/// failing with [`TagError::Invalid`] instead of panicking when the data is too short.
+struct Bytes<'a> {
+    data: &'a [u8],
+    format: &'static str,
+}
+impl<'a> Bytes<'a> {
+    fn new(data: &'a [u8], format: &'static str) -> Self {
+        Self { data, format }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], TagError> {
+        if len > self.data.len() {
+            return Err(TagError::Invalid(self.format))
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], TagError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, TagError> {
+        Ok(self.array::<1>()?[0])
+    }
+    fn u16_le(&mut self) -> Result<u16, TagError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+    fn u16_be(&mut self) -> Result<u16, TagError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+    fn u32_le(&mut self) -> Result<u32, TagError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    fn u32_be(&mut self) -> Result<u32, TagError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+    fn u64_le(&mut self) -> Result<u64, TagError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    fn u64_be(&mut self) -> Result<u64, TagError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    /// Everything that has not been read.
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+}
@@ -0,0 +1,172 @@
+//! *MP4* files (`.m4a`), made of nested boxes (or *atoms*). The tags are in `moov.udta.meta.ilst`, as *iTunes* writes them.
+use super::*;
+
+// Types of the value in a `data` box
+const UTF8: u32 = 1;
+const JPEG: u32 = 13;
+const PNG: u32 = 14;
+const BMP: u32 = 27;
+
+
+/// Reads the header of the box at the **reader**'s position.
+/// Returns its name and where it ends, which is **parent_end** for a box that extends to the end of its parent.
+fn read_box<R: Read + Seek>(reader: &mut R, parent_end: u64) -> Result<([u8; 4], u64), TagError> {
+    let start = reader.stream_position()?;
+    let header = read_vec(reader, 8, "MP4")?;
+    let mut header = Bytes::new(&header, "MP4");
+    let size = header.u32_be()? as u64;
+    let name = header.array()?;
+
+    let (end, header_len) = match size {
+        0 => (parent_end, 8),
+        1 => (start.checked_add(u64::from_be_bytes(read_vec(reader, 8, "MP4")?.try_into().unwrap()))
+            .ok_or(TagError::Invalid("MP4"))?, 16),
+        size => (start + size, 8)
+    };
+    // The box can't end before its own header
+    if end < start + header_len || end > parent_end {
+        return Err(TagError::Invalid("MP4"))
+    }
+    Ok((name, end))
+}
+
+/// Finds the child box with **name** in the box that ends at **end**, and returns where it ends.
+fn find_box<R: Read + Seek>(reader: &mut R, name: &[u8; 4], end: u64) -> Result<Option<u64>, TagError> {
+    while reader.stream_position()? < end {
+        let (child, child_end) = read_box(reader, end)?;
+        if &child == name {
+            return Ok(Some(child_end))
+        }
+        reader.seek(SeekFrom::Start(child_end))?;
+    }
+    Ok(None)
+}
+
+pub(super) fn read<R: Read + Seek>(reader: &mut R, tags: &mut Tags, pictures: bool) -> Result<(), TagError> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let moov_end = find_box(reader, b"moov", file_end)?.ok_or(TagError::Invalid("MP4"))?;
+    let moov_start = reader.stream_position()?;
+
+    if let Some(end) = find_box(reader, b"mvhd", moov_end)? {
+        let start = reader.stream_position()?;
+        read_duration(&read_vec(reader, end - start, "MP4")?, tags)?;
+    }
+
+    reader.seek(SeekFrom::Start(moov_start))?;
+    let meta = match find_box(reader, b"udta", moov_end)? {
+        Some(end) => find_box(reader, b"meta", end)?,
+        None => None
+    };
+    if let Some(meta_end) = meta {
+        // `meta` is a full box, with 4 bytes of version and flags before its children.
+        // Files from QuickTime don't have them though, and start with the `hdlr` child instead.
+        let start = reader.stream_position()?;
+        let mut peek = [0; 8];
+        reader.read_exact(&mut peek)?;
+        if &peek[4..] == b"hdlr" {
+            reader.seek(SeekFrom::Start(start))?;
+        } else {
+            reader.seek(SeekFrom::Start(start + 4))?;
+        }
+
+        if let Some(end) = find_box(reader, b"ilst", meta_end)? {
+            let start = reader.stream_position()?;
+            read_items(&read_vec(reader, end - start, "MP4")?, tags, pictures);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the content of the `mvhd` (movie header) box.
+fn read_duration(mvhd: &[u8], tags: &mut Tags) -> Result<(), TagError> {
+    let mut mvhd = Bytes::new(mvhd, "MP4");
+    let version = mvhd.u8()?;
+    // Flags
+    mvhd.take(3)?;
+    let (timescale, duration) = if version == 1 {
+        // Creation and modification times
+        mvhd.take(16)?;
+        (mvhd.u32_be()?, mvhd.u64_be()?)
+    } else {
+        mvhd.take(8)?;
+        (mvhd.u32_be()?, mvhd.u32_be()? as u64)
+    };
+
+    if timescale != 0 && duration != u64::MAX && duration != u32::MAX as u64 {
+        tags.duration = Duration::try_from_secs_f64(duration as f64 / timescale as f64).ok();
+    }
+    Ok(())
+}
+
+/// Reads the content of the `ilst` box, which has a box for each item with `data` boxes inside.
+fn read_items(ilst: &[u8], tags: &mut Tags, pictures: bool) {
+    for (name, item) in children(ilst) {
+        for (_, data) in children(item).filter(|(name, _)| name == b"data") {
+            let mut data = Bytes::new(data, "MP4");
+            let (kind, value) = match (data.u32_be(), data.take(4)) {
+                // The first byte is a version
+                (Ok(kind), Ok(_locale)) => (kind & 0xFF_FFFF, data.rest()),
+                _ => continue
+            };
+
+            match &name {
+                b"\xA9nam" => set(&mut tags.title, &text(kind, value)),
+                b"\xA9ART" => set(&mut tags.artist, &text(kind, value)),
+                b"\xA9alb" => set(&mut tags.album, &text(kind, value)),
+                b"aART" => set(&mut tags.album_artist, &text(kind, value)),
+                b"\xA9day" => set(&mut tags.date, &text(kind, value)),
+                b"trkn" => {
+                    // 2 bytes of padding, then the track number and the number of tracks
+                    let mut value = Bytes::new(value, "MP4");
+                    if let (Ok(_), Ok(number), total) = (value.take(2), value.u16_be(), value.u16_be()) {
+                        let track = match total {
+                            Ok(total) if total != 0 => format!("{number}/{total}"),
+                            _ => number.to_string()
+                        };
+                        if number != 0 {
+                            set(&mut tags.track, &track)
+                        }
+                    }
+                },
+                b"covr" if pictures => tags.pictures.push(Picture {
+                    mime: match kind {
+                        PNG => "image/png",
+                        BMP => "image/bmp",
+                        JPEG => "image/jpeg",
+                        _ if value.starts_with(b"\x89PNG") => "image/png",
+                        _ => "image/jpeg"
+                    }.to_string(),
+                    kind: FRONT_COVER,
+                    data: value.to_vec(),
+                }),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn text(kind: u32, value: &[u8]) -> String {
+    match kind {
+        UTF8 => String::from_utf8_lossy(value).to_string(),
+        _ => String::new()
+    }
+}
+
+/// The boxes in some **data** that is already in memory, with their names and contents.
+fn children(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let mut header = Bytes::new(data, "MP4");
+        let size = header.u32_be().ok()? as usize;
+        let name = header.array().ok()?;
+        let size = match size {
+            0 => data.len(),
+            size if (8..=data.len()).contains(&size) => size,
+            _ => return None
+        };
+        let (child, rest) = data.split_at(size);
+        data = rest;
+        Some((name, &child[8..]))
+    })
+}
@@ -0,0 +1,163 @@
+//! Duration of *MPEG* audio streams (*MP3* files), which have no tags of their own.
+use super::*;
+
+/// How far after the start of the audio a frame is searched for, since some files have junk before it.
+const SEARCH_LEN: u64 = 64 << 10;
+
+/// Bitrates in kbps, by bitrate index.
+const BITRATES: [[u32; 15]; 5] = [
+    // MPEG 1 layers 1, 2 and 3
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    // MPEG 2 and 2.5 layer 1, then layers 2 and 3
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+/// Sample rates for MPEG 1, with the sample rate index.
+/// MPEG 2 has half of these, and MPEG 2.5 a quarter.
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+#[derive(Debug)]
+struct FrameHeader {
+    version: Version,
+    layer: u8,
+    /// In bits per second.
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    mono: bool,
+}
+impl FrameHeader {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let &[sync, b1, b2, b3] = bytes.get(..4)? else { return None };
+        if sync != 0xFF || b1 & 0xE0 != 0xE0 {
+            return None
+        }
+
+        let version = match (b1 >> 3) & 0b11 {
+            0 => Version::Mpeg25,
+            2 => Version::Mpeg2,
+            3 => Version::Mpeg1,
+            _ => return None
+        };
+        let layer = match (b1 >> 1) & 0b11 {
+            0 => return None,
+            bits => 4 - bits
+        };
+        let bitrate_index = (b2 >> 4) as usize;
+        // 0 is for "free" bitrates, which don't have a fixed frame length
+        if bitrate_index == 0 || bitrate_index == 15 {
+            return None
+        }
+        let sample_rate = *SAMPLE_RATES.get(((b2 >> 2) & 0b11) as usize)?;
+
+        let table = match (version, layer) {
+            (Version::Mpeg1, layer) => layer as usize - 1,
+            (_, 1) => 3,
+            _ => 4
+        };
+        Some(Self {
+            version,
+            layer,
+            bitrate: BITRATES[table][bitrate_index] * 1000,
+            sample_rate: match version {
+                Version::Mpeg1 => sample_rate,
+                Version::Mpeg2 => sample_rate / 2,
+                Version::Mpeg25 => sample_rate / 4,
+            },
+            padding: b2 & 0b10 != 0,
+            mono: b3 >> 6 == 0b11,
+        })
+    }
+
+    fn samples(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (3, Version::Mpeg2 | Version::Mpeg25) => 576,
+            _ => 1152
+        }
+    }
+
+    /// Length of the whole frame in bytes, including this header.
+    fn len(&self) -> usize {
+        if self.layer == 1 {
+            ((12 * self.bitrate / self.sample_rate) as usize + self.padding as usize) * 4
+        } else {
+            (self.samples() / 8 * self.bitrate / self.sample_rate) as usize + self.padding as usize
+        }
+    }
+
+    /// Where the *Xing* header is in the frame, after the side information.
+    fn xing_offset(&self) -> usize {
+        4 + match (self.version, self.mono) {
+            (Version::Mpeg1, false) => 32,
+            (Version::Mpeg1, true) => 17,
+            (_, false) => 17,
+            (_, true) => 9,
+        }
+    }
+}
+
+/// Finds the first frame of the audio at **start**, and sets the duration of the stream in the **tags**.
+/// Returns `false` if there is no *MPEG* audio.
+pub(super) fn read<R: Read + Seek>(reader: &mut R, tags: &mut Tags, start: u64) -> Result<bool, TagError> {
+    let mut end = reader.seek(SeekFrom::End(0))?;
+    // ID3v1 tag
+    if end >= 128 {
+        reader.seek(SeekFrom::Start(end - 128))?;
+        let mut magic = [0; 3];
+        reader.read_exact(&mut magic)?;
+        if &magic == b"TAG" {
+            end -= 128
+        }
+    }
+
+    reader.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    reader.take(SEARCH_LEN).read_to_end(&mut buf)?;
+
+    // A frame is only accepted if the next one is right after it, so that random bytes are not mistaken for a frame
+    let frame = (0..buf.len()).find_map(|pos| {
+        let header = FrameHeader::parse(&buf[pos..])?;
+        match buf.get(pos + header.len()..) {
+            Some(next) if next.len() >= 4 => FrameHeader::parse(next).map(|_| (pos, header)),
+            // The file ends after the frame
+            _ => Some((pos, header))
+        }
+    });
+    let (pos, header) = match frame {
+        Some(frame) => frame,
+        None => return Ok(false)
+    };
+    let frame = &buf[pos..];
+
+    // VBR files have the number of frames in a Xing (or VBRI) header in the first frame
+    let frames = {
+        let mut xing = Bytes::new(frame.get(header.xing_offset()..).unwrap_or_default(), "Xing");
+        let mut vbri = Bytes::new(frame.get(4 + 32..).unwrap_or_default(), "VBRI");
+        match xing.take(4) {
+            Ok(b"Xing" | b"Info") if xing.u32_be().is_ok_and(|flags| flags & 1 != 0) => xing.u32_be().ok(),
+            _ => match vbri.take(4) {
+                Ok(b"VBRI") => vbri.take(10).and_then(|_| vbri.u32_be()).ok(),
+                _ => None
+            }
+        }
+    };
+
+    let seconds = match frames {
+        Some(frames) => frames as f64 * header.samples() as f64 / header.sample_rate as f64,
+        // Constant bitrate
+        None => (end.saturating_sub(start + pos as u64) * 8) as f64 / header.bitrate as f64
+    };
+    tags.duration = Some(Duration::from_secs_f64(seconds));
+    Ok(true)
+}
@@ -0,0 +1,114 @@
+//! *Ogg* files with *Vorbis* or *Opus* audio.
+use super::*;
+
+/// How much of the end of the file is searched for the last page. Pages are at most about 64KiB.
+const LAST_PAGE_SEARCH: u64 = 128 << 10;
+/// *Opus* always counts samples at 48kHz, whatever the rate of the input was.
+const OPUS_RATE: u64 = 48000;
+
+
+/// A page of the *Ogg* container, which has the pieces of some packets of a stream.
+struct Page {
+    serial: u32,
+    /// Length of each segment. A packet ends with a segment shorter than 255.
+    segments: Vec<u8>,
+    data: Vec<u8>,
+}
+impl Page {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, TagError> {
+        let header = read_vec(reader, 27, "Ogg")?;
+        let mut header = Bytes::new(&header, "Ogg");
+        if header.take(4)? != b"OggS" {
+            return Err(TagError::Invalid("Ogg"))
+        }
+        // Version, header type and granule position
+        header.take(10)?;
+        let serial = header.u32_le()?;
+        // Sequence number and checksum
+        header.take(8)?;
+        let segment_count = header.u8()?;
+
+        let segments = read_vec(reader, segment_count as u64, "Ogg")?;
+        let len = segments.iter().map(|&len| len as u64).sum();
+        Ok(Self {
+            serial,
+            data: read_vec(reader, len, "Ogg")?,
+            segments,
+        })
+    }
+}
+
+/// Reads the headers of the first stream in the file.
+pub(super) fn read<R: Read + Seek>(reader: &mut R, tags: &mut Tags, pictures: bool) -> Result<(), TagError> {
+    // The identification header, then the comment header
+    let mut packets = vec![Vec::new()];
+    let mut serial = None;
+
+    while packets.len() <= 2 {
+        let page = Page::read(reader)?;
+        if *serial.get_or_insert(page.serial) != page.serial {
+            continue
+        }
+
+        let mut data = &page.data[..];
+        for &len in &page.segments {
+            let (segment, rest) = data.split_at(len as usize);
+            data = rest;
+            packets.last_mut().unwrap().extend_from_slice(segment);
+            if len < 255 {
+                packets.push(Vec::new())
+            }
+            if packets.iter().map(Vec::len).sum::<usize>() as u64 > MAX_TAG_SIZE {
+                return Err(TagError::Invalid("Ogg"))
+            }
+        }
+    }
+
+    let (identification, comments) = (&packets[0], &packets[1]);
+    let (rate, pre_skip) = if let Some(header) = identification.strip_prefix(b"\x01vorbis") {
+        let comments = comments.strip_prefix(b"\x03vorbis").ok_or(TagError::Invalid("Vorbis"))?;
+        vorbis::read_comments(comments, tags, pictures)?;
+
+        let mut header = Bytes::new(header, "Vorbis");
+        // Version and channels
+        header.take(5)?;
+        (header.u32_le()? as u64, 0)
+    } else if let Some(header) = identification.strip_prefix(b"OpusHead") {
+        let comments = comments.strip_prefix(b"OpusTags").ok_or(TagError::Invalid("Opus"))?;
+        vorbis::read_comments(comments, tags, pictures)?;
+
+        let mut header = Bytes::new(header, "Opus");
+        // Version and channels
+        header.take(2)?;
+        (OPUS_RATE, header.u16_le()? as u64)
+    } else {
+        return Err(TagError::UnknownFormat)
+    };
+
+    // The granule position of the last page is the number of samples in the stream
+    if let Some(samples) = last_granule_position(reader, serial.unwrap())? {
+        if rate != 0 {
+            tags.duration = Duration::try_from_secs_f64(samples.saturating_sub(pre_skip) as f64 / rate as f64).ok();
+        }
+    }
+
+    Ok(())
+}
+
+fn last_granule_position<R: Read + Seek>(reader: &mut R, serial: u32) -> Result<Option<u64>, TagError> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let start = len.saturating_sub(LAST_PAGE_SEARCH);
+    reader.seek(SeekFrom::Start(start))?;
+    let mut end = Vec::new();
+    reader.read_to_end(&mut end)?;
+
+    let granule = (0..end.len().saturating_sub(27)).rev()
+        .filter(|&pos| end[pos..].starts_with(b"OggS"))
+        .find_map(|pos| {
+            let mut header = Bytes::new(&end[pos + 6..], "Ogg");
+            let granule = header.u64_le().ok()?;
+            // Pages where no packet ends have no granule position
+            (header.u32_le().ok()? == serial && granule != u64::MAX).then_some(granule)
+        });
+    Ok(granule)
+}
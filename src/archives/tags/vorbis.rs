@@ -0,0 +1,54 @@
+//! *Vorbis comments*, the tags of *FLAC* and *Ogg* files.
+use data_encoding::BASE64;
+use super::*;
+
+
+/// Reads the list of `KEY=value` comments in **data**.
+pub(super) fn read_comments(data: &[u8], tags: &mut Tags, pictures: bool) -> Result<(), TagError> {
+    let mut data = Bytes::new(data, "Vorbis comment");
+    let vendor_len = data.u32_le()?;
+    data.take(vendor_len as usize)?;
+
+    let count = data.u32_le()?;
+    for _ in 0..count {
+        let len = data.u32_le()?;
+        let comment = String::from_utf8_lossy(data.take(len as usize)?);
+        let (key, value) = match comment.split_once('=') {
+            Some(comment) => comment,
+            None => continue
+        };
+
+        if !tags.set_comment(key, value)
+            && pictures
+            && key.eq_ignore_ascii_case("METADATA_BLOCK_PICTURE")
+        {
+            // Ogg files have the same picture blocks as FLAC, in base64
+            if let Some(picture) = BASE64.decode(value.trim().as_bytes()).ok()
+                .and_then(|block| read_picture(&block).ok())
+            {
+                tags.pictures.push(picture)
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a `PICTURE` metadata block of *FLAC*.
+pub(super) fn read_picture(block: &[u8]) -> Result<Picture, TagError> {
+    let mut block = Bytes::new(block, "FLAC picture");
+    let kind = block.u32_be()?;
+    let mime_len = block.u32_be()?;
+    let mime = String::from_utf8_lossy(block.take(mime_len as usize)?).to_ascii_lowercase();
+    let description_len = block.u32_be()?;
+    block.take(description_len as usize)?;
+    // Width, height, color depth and number of colors
+    block.take(16)?;
+    let len = block.u32_be()?;
+
+    Ok(Picture {
+        mime,
+        kind: kind.try_into().unwrap_or(0),
+        data: block.take(len as usize)?.to_vec(),
+    })
+}
@@ -0,0 +1,136 @@
+//! Watches the archives' directories (with *inotify* on Linux), so that what is cached about their files
+//! is updated as soon as albums, songs and games are added, changed or removed, without restarting the server.
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf, Component},
+    sync::mpsc,
+    time::Duration,
+};
+use notify::{Watcher, RecursiveMode, Event};
+use super::{
+    index::Index,
+    osts::{AlbumInfo, SongInfo, ALBUMS_PATH},
+    games::{self, GAMES_PATH},
+};
+
+/// How long to wait for more changes before handling them, since e.g. copying an album changes many files at once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+
+/// What is cached about the files of the archives.
+pub struct Caches {
+    pub albums: Index<AlbumInfo>,
+    pub songs: Index<SongInfo>,
+}
+impl Caches {
+    /// Invalidates what was read from the changed **paths**, then reads the albums and songs again,
+    /// so that the next request doesn't have to.
+    fn update(&self, paths: &HashSet<PathBuf>) {
+        let mut albums = HashSet::new();
+        let mut all_albums = false;
+
+        for path in paths {
+            if let Some(rest) = relative(path, &ALBUMS_PATH) {
+                match rest.components().next() {
+                    Some(Component::Normal(album)) => {
+                        let album = ALBUMS_PATH.join(album);
+                        // Any file changes the album, e.g. its cover or number of songs
+                        self.albums.invalidate(&album);
+                        // The song, or all the songs if it is the album that changed
+                        self.songs.invalidate(&ALBUMS_PATH.join(rest));
+                        albums.insert(album);
+                    },
+                    // The whole archive, e.g. events were missed
+                    _ => {
+                        self.albums.invalidate(&ALBUMS_PATH);
+                        self.songs.invalidate(&ALBUMS_PATH);
+                        all_albums = true;
+                    }
+                }
+            } else if let Some(rest) = relative(path, &GAMES_PATH) {
+                match rest.components().next() {
+                    Some(Component::Normal(game)) => games::invalidate_files(Some(&game.to_string_lossy())),
+                    _ => games::invalidate_files(None)
+                }
+            }
+        }
+
+        if all_albums {
+            albums.extend(std::fs::read_dir(&*ALBUMS_PATH)
+                .into_iter()
+                .flatten()
+                .filter_map(Result::ok)
+                .map(|entry| entry.path()));
+        }
+        // A directory can still be removed while it is read, which the next events will tell
+        if !albums.is_empty() && ALBUMS_PATH.is_dir() {
+            if let Err(error) = self.albums.read_all_dirs(&ALBUMS_PATH) {
+                eprintln!("Can't read the albums after they changed: {error}");
+            }
+        }
+        // Removed albums were already forgotten
+        for album in albums.iter().filter(|album| album.is_dir()) {
+            if let Err(error) = self.songs.read_all_files(album) {
+                eprintln!("Can't read the songs of {album:?} after they changed: {error}");
+            }
+        }
+    }
+}
+
+/// Marks the games' files as no longer watched when the watcher stops for any reason, including a panic,
+/// so that they are checked again instead of going stale (see [`games::set_watched()`]).
+struct StopGuard;
+impl Drop for StopGuard {
+    fn drop(&mut self) {
+        games::set_watched(false);
+    }
+}
+
+/// Starts watching [`ALBUMS_PATH`] and [`GAMES_PATH`] in another thread.
+/// Failing to watch is only reported, since the [`Index`]es still notice most changes by the [`Stamp`](super::index::Stamp)s of the files.
+pub fn spawn(caches: Caches) {
+    std::thread::spawn(move || {
+        let _guard = StopGuard;
+        if let Err(error) = watch(&caches) {
+            eprintln!("Can't watch the archives for changes: {error}");
+        }
+    });
+}
+
+fn watch(caches: &Caches) -> notify::Result<()> {
+    let (sender, receiver) = mpsc::channel::<notify::Result<Event>>();
+    // Stops watching when dropped
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&ALBUMS_PATH, RecursiveMode::Recursive)?;
+    watcher.watch(&GAMES_PATH, RecursiveMode::Recursive)?;
+    games::set_watched(true);
+
+    while let Ok(event) = receiver.recv() {
+        let mut paths = HashSet::new();
+        let mut add = |event: notify::Result<Event>| match event {
+            // Files are also opened when they are read, e.g. by the update
+            Ok(event) if event.kind.is_access() => {},
+            // Too many events happened at once, and some were lost
+            Ok(event) if event.need_rescan() => {
+                paths.insert(ALBUMS_PATH.clone());
+                paths.insert(GAMES_PATH.clone());
+            },
+            Ok(event) => paths.extend(event.paths),
+            Err(error) => eprintln!("Error while watching the archives: {error}")
+        };
+
+        add(event);
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            add(event);
+        }
+        caches.update(&paths);
+    }
+
+    Ok(())
+}
+
+/// **path** relative to **dir**. The paths of events are absolute, even if the watched path was relative.
+fn relative<'a>(path: &'a Path, dir: &Path) -> Option<&'a Path> {
+    path.strip_prefix(dir).ok()
+        .or_else(|| path.strip_prefix(std::env::current_dir().ok()?.join(dir)).ok())
+}
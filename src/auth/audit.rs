@@ -0,0 +1,310 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    fs::File,
+    net::IpAddr,
+    path::PathBuf,
+};
+use chrono::{DateTime, Utc};
+use async_std::sync::Mutex as AsyncMutex;
+use rocket::{
+    Request,
+    request::{FromRequest, Outcome},
+    tokio::{fs::OpenOptions, io::AsyncWriteExt},
+    http::Status,
+};
+use serde::{Serialize, Deserialize};
+use super::db::Users;
+
+
+/// A security-relevant thing that happened, recorded in the [`AuditLog`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// The actor got a session, after passing 2FA if they have it enabled.
+    Login,
+    /// Someone failed to log in as **username**.
+    LoginFailed { username: String, reason: String },
+    Logout,
+    /// The actor registered **username** (admins), or registered themselves with an invite.
+    Register { username: String, invited: bool },
+    /// The `admin` user was created.
+    AdminRegister,
+    PasswordChanged,
+    TotpEnabled,
+    TotpDisabled,
+    TokenCreated { name: String },
+    TokenRevoked { name: String },
+    /// The actor logged out one of their sessions, which was used from **user_agent**.
+    SessionRevoked { user_agent: Option<String> },
+    /// The actor logged out all their sessions except the current one.
+    OtherSessionsRevoked,
+    /// The actor logged out all their sessions.
+    AllSessionsRevoked,
+    // Admin actions
+    GroupsChanged { username: String, groups: Vec<String> },
+    PasswordReset { username: String },
+    UserDisabled { username: String },
+    UserEnabled { username: String },
+    /// An admin logged **username** out everywhere.
+    UserLoggedOut { username: String },
+    UserDeleted { username: String },
+    InviteCreated { group: Option<String> },
+    InviteRevoked,
+}
+impl AuditEvent {
+    /// Names of the events, as in the log's `event` field. Used to filter the log.
+    pub const NAMES: [&'static str; 21] = [
+        "login", "login_failed", "logout", "register", "admin_register", "password_changed",
+        "totp_enabled", "totp_disabled", "token_created", "token_revoked",
+        "session_revoked", "other_sessions_revoked", "all_sessions_revoked",
+        "groups_changed", "password_reset", "user_disabled", "user_enabled", "user_logged_out", "user_deleted",
+        "invite_created", "invite_revoked",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::LoginFailed { .. } => "login_failed",
+            Self::Logout => "logout",
+            Self::Register { .. } => "register",
+            Self::AdminRegister => "admin_register",
+            Self::PasswordChanged => "password_changed",
+            Self::TotpEnabled => "totp_enabled",
+            Self::TotpDisabled => "totp_disabled",
+            Self::TokenCreated { .. } => "token_created",
+            Self::TokenRevoked { .. } => "token_revoked",
+            Self::SessionRevoked { .. } => "session_revoked",
+            Self::OtherSessionsRevoked => "other_sessions_revoked",
+            Self::AllSessionsRevoked => "all_sessions_revoked",
+            Self::GroupsChanged { .. } => "groups_changed",
+            Self::PasswordReset { .. } => "password_reset",
+            Self::UserDisabled { .. } => "user_disabled",
+            Self::UserEnabled { .. } => "user_enabled",
+            Self::UserLoggedOut { .. } => "user_logged_out",
+            Self::UserDeleted { .. } => "user_deleted",
+            Self::InviteCreated { .. } => "invite_created",
+            Self::InviteRevoked => "invite_revoked",
+        }
+    }
+
+    /// The user that the event happened to, if it's not the actor.
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            Self::LoginFailed { username, .. }
+            | Self::Register { username, .. }
+            | Self::GroupsChanged { username, .. }
+            | Self::PasswordReset { username }
+            | Self::UserDisabled { username }
+            | Self::UserEnabled { username }
+            | Self::UserLoggedOut { username }
+            | Self::UserDeleted { username } => Some(username),
+            _ => None
+        }
+    }
+
+    /// Description of the event's fields, for the admin page.
+    pub fn details(&self) -> String {
+        match self {
+            Self::LoginFailed { reason, .. } => reason.clone(),
+            Self::Register { invited: true, .. } => "With an invite".to_string(),
+            Self::TokenCreated { name } | Self::TokenRevoked { name } => format!("Token {name:?}"),
+            Self::SessionRevoked { user_agent } => format!("Session of {}", user_agent.as_deref().unwrap_or("an unknown browser")),
+            Self::GroupsChanged { groups, .. } => format!("Groups: {}", groups.join(", ")),
+            Self::InviteCreated { group: Some(group) } => format!("To group {group:?}"),
+            _ => String::new()
+        }
+    }
+}
+
+/// A line of the [`AuditLog`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    /// The user that did it. [`None`] if the client was not logged in.
+    pub actor: Option<String>,
+    pub ip: Option<IpAddr>,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// Selects entries of the [`AuditLog`]. Empty fields match every entry.
+#[derive(Debug, Clone, Default, FromForm)]
+pub struct AuditFilter {
+    /// One of the [`AuditEvent::NAMES`].
+    pub event: Option<String>,
+    /// Matches the actor or the [target](AuditEvent::target()) of the event.
+    pub user: Option<String>,
+}
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let event = self.event.as_deref().filter(|event| !event.is_empty());
+        let user = self.user.as_deref().filter(|user| !user.is_empty());
+
+        event.is_none_or(|event| entry.event.name() == event)
+        && user.is_none_or(|user|
+            entry.actor.as_deref() == Some(user) || entry.event.target() == Some(user)
+        )
+    }
+}
+
+/// Append-only log of the [`AuditEvent`]s, stored as *JSON lines* in a file next to the users database
+/// (see [`Users::new()`]).
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    /// So that concurrent lines are not interleaved.
+    write_lock: AsyncMutex<()>,
+}
+impl AuditLog {
+    /// Number of entries in each page of [`Self::page()`].
+    pub const PAGE_SIZE: usize = 50;
+    /// How much of the log [`Self::page()`] reads at a time, from the end.
+    const BLOCK_SIZE: u64 = 4 * 1024;
+
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, write_lock: AsyncMutex::new(()) }
+    }
+
+    /// Appends the **entry** to the log.
+    /// Failing to write is only reported in the server's output, so that it doesn't stop what is being recorded.
+    pub async fn record(&self, entry: AuditEntry) {
+        if let Err(error) = self.append(&entry).await {
+            eprintln!("Can't write to audit log {:?}: {error}. Entry: {entry:?}", self.path);
+        }
+    }
+    async fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let _lock = self.write_lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path).await?;
+        file.write_all(line.as_bytes()).await?;
+        // The file writes in the background, so the line could still be unwritten (or come after the next one) when it is dropped
+        file.flush().await
+    }
+
+    /// The entries that match the **filter**, newest first, skipping **page** pages of [`Self::PAGE_SIZE`].
+    /// Also returns whether there are more entries after this page.
+    ///
+    /// The log is read from the end, and only until the page is full, so that the first pages don't get slower as the log grows.
+    pub async fn page(&self, filter: &AuditFilter, page: usize) -> io::Result<(Vec<AuditEntry>, bool)> {
+        let path = self.path.clone();
+        let filter = filter.clone();
+
+        rocket::tokio::task::spawn_blocking(move || {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), false)),
+                Err(error) => return Err(error)
+            };
+
+            // One more entry than needed, to know if there are more
+            let needed = (page + 1) * Self::PAGE_SIZE + 1;
+            let mut entries = Vec::new();
+            for line in RevLines::new(file)? {
+                // A line could be cut short if the server was stopped while writing it
+                let entry = match serde_json::from_slice::<AuditEntry>(&line?) {
+                    Ok(entry) => entry,
+                    Err(_) => continue
+                };
+                if filter.matches(&entry) {
+                    entries.push(entry);
+                    if entries.len() == needed {
+                        break
+                    }
+                }
+            }
+
+            let more = entries.len() == needed;
+            let entries = entries.into_iter()
+                .skip(page * Self::PAGE_SIZE)
+                .take(Self::PAGE_SIZE)
+                .collect();
+            Ok((entries, more))
+        }).await?
+    }
+}
+
+/// The lines of a file from last to first, read in blocks of [`AuditLog::BLOCK_SIZE`] from the end.
+struct RevLines {
+    file: File,
+    /// Where the part of the file that wasn't read yet ends.
+    end: u64,
+    /// The start of the file that was read, which doesn't have a whole line yet.
+    buf: Vec<u8>,
+}
+impl RevLines {
+    fn new(mut file: File) -> io::Result<Self> {
+        let end = file.seek(SeekFrom::End(0))?;
+        Ok(Self { file, end, buf: Vec::new() })
+    }
+
+    /// Reads the block before what was read, in front of the **buf**.
+    fn read_block(&mut self) -> io::Result<()> {
+        let len = self.end.min(AuditLog::BLOCK_SIZE);
+        self.end -= len;
+        self.file.seek(SeekFrom::Start(self.end))?;
+
+        let mut block = vec![0; len as usize];
+        self.file.read_exact(&mut block)?;
+        block.append(&mut self.buf);
+        self.buf = block;
+        Ok(())
+    }
+}
+impl Iterator for RevLines {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(newline) = self.buf.iter().rposition(|&byte| byte == b'\n') {
+                let line = self.buf.split_off(newline + 1);
+                self.buf.truncate(newline);
+                return Some(Ok(line))
+            }
+            if self.end == 0 {
+                // The first line
+                return (!self.buf.is_empty()).then(|| Ok(std::mem::take(&mut self.buf)))
+            }
+            if let Err(error) = self.read_block() {
+                self.end = 0;
+                self.buf.clear();
+                return Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Request Guard that records [`AuditEvent`]s with the client's IP. Never fails if the server has its [`Users`].
+pub struct Audit<'r> {
+    log: &'r AuditLog,
+    ip: Option<IpAddr>,
+}
+impl Audit<'_> {
+    /// Records that the **actor** (if any) caused the **event**.
+    pub async fn record(&self, actor: Option<&str>, event: AuditEvent) {
+        self.log.record(AuditEntry {
+            time: Utc::now(),
+            actor: actor.map(str::to_string),
+            ip: self.ip,
+            event
+        }).await
+    }
+}
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Audit<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.rocket().state::<Users>() {
+            Some(users) => Outcome::Success(Self {
+                log: users.audit(),
+                ip: req.client_ip()
+            }),
+            None => Outcome::Failure((Status::InternalServerError, ()))
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use rocket::{
+    Request, Data,
+    http::{Cookie, ContentType, Method, SameSite, Status},
+    request::{FromRequest, Outcome},
+    fairing::{Fairing, Info, Kind},
+};
+use rand::{distributions::Alphanumeric, Rng};
+use super::{db::Users, SESSION_COOKIE};
+
+
+/// Holds the client's CSRF token, which its forms must send back in the [`FIELD`].
+pub static CSRF_COOKIE: &str = "csrf_token";
+/// Name of the hidden form field with the CSRF token.
+pub static FIELD: &str = "csrf_token";
+const TOKEN_LEN: usize = 32;
+/// How much of a request's body is searched for the [`FIELD`].
+/// [`CsrfField`](crate::components::CsrfField) is the first field of every form, so it is always within this.
+const PEEK_LEN: usize = 512;
+
+/// The token given to the forms of the page, cached in the request.
+struct PageToken(String);
+/// Whether the request sent a valid CSRF token, cached in the request.
+struct Checked(bool);
+
+/// Protects the server's forms from cross-site request forgery with a *double-submit* token:
+/// the token is stored in the client's private [`CSRF_COOKIE`], and must also be sent in the body of each `POST` request.
+/// Another site can make the client send the cookie, but can't read it to also put it in the body.
+///
+/// Pages get the token with the [`CsrfToken`] guard, and put it in their forms.
+/// The fairing checks the token in the body of `POST` requests, and routes reject the requests that failed the check with the [`Csrf`] guard.
+pub struct CsrfFairing;
+#[rocket::async_trait]
+impl Fairing for CsrfFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF tokens",
+            kind: Kind::Request
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, data: &mut Data<'_>) {
+        if req.method() != Method::Post {
+            return
+        }
+
+        // Browsers never add the Authorization header by themselves, so requests authenticated by a token can't be forged.
+        // A request that also has a session could be authenticated by it instead, so it is checked like the others.
+        let by_token = req.cookies().get_private(SESSION_COOKIE).is_none()
+            && match (super::bearer_token(req), req.rocket().state::<Users>()) {
+                (Some(token), Some(users)) => users.validate_token(token).await.is_some(),
+                _ => false
+            };
+
+        let valid = by_token
+            || match (req.cookies().get_private(CSRF_COOKIE), req.content_type()) {
+                (Some(token), Some(content_type)) => sent_token(content_type, data.peek(PEEK_LEN).await)
+                    .is_some_and(|sent| sent == token.value().as_bytes()),
+                _ => false
+            };
+        req.local_cache(|| Checked(valid));
+    }
+}
+
+/// Request Guard with the client's CSRF token, which pages put in each of their `POST` forms
+/// with a [`CsrfField`](crate::components::CsrfField).
+/// The token is created if the client doesn't have one yet.
+pub struct CsrfToken(pub String);
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfToken {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = req.local_cache(|| PageToken(match req.cookies().get_private(CSRF_COOKIE) {
+            Some(cookie) => cookie.value().to_string(),
+            None => {
+                let token = rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(TOKEN_LEN)
+                    .map(char::from)
+                    .collect::<String>();
+                req.cookies().add_private(Cookie::build(CSRF_COOKIE, token.clone())
+                    .http_only(true)
+                    .same_site(SameSite::Strict)
+                    .finish());
+                token
+            }
+        }));
+        Outcome::Success(Self(token.0.clone()))
+    }
+}
+
+/// Request Guard that fails with [`Status::Forbidden`] if the request didn't send a valid CSRF token.
+/// Needed by every `POST` route, and requires the [`CsrfFairing`] to be attached.
+///
+/// ```rust
+/// #[post("/edit", data = "<form>")]
+/// fn edit(_csrf: Csrf, form: Form<Edit<'_>>) { }
+/// ```
+pub struct Csrf;
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Csrf {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if req.local_cache(|| Checked(false)).0 {
+            Outcome::Success(Self)
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+    }
+}
+
+/// Finds the value of the [`FIELD`] at the start of a form's **body**,
+/// which is in the *urlencoded* or *multipart* format according to the **content_type**.
+pub(super) fn sent_token<'a>(content_type: &ContentType, body: &'a [u8]) -> Option<&'a [u8]> {
+    if content_type.is_form() {
+        body.split(|&byte| byte == b'&')
+            .find_map(|field| field.strip_prefix(format!("{FIELD}=").as_bytes()))
+    } else if content_type.is_form_data() {
+        let header = format!("name=\"{FIELD}\"\r\n\r\n");
+        let start = find(body, header.as_bytes())? + header.len();
+        let len = find(&body[start..], b"\r\n")?;
+        Some(&body[start..start + len])
+    } else {
+        None
+    }
+}
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len())
+        .position(|window| window == needle)
+}
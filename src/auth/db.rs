@@ -1,43 +1,89 @@
 use std::{
     io,
-    collections::HashMap,
-    path::PathBuf,
+    collections::BTreeSet,
+    path::{Path, PathBuf},
     ops::RangeInclusive,
-    num::ParseIntError
+    num::ParseIntError,
+    sync::Arc
 };
 use argon2::{
-    Argon2, PasswordHash, PasswordVerifier,
+    Argon2, Params, PasswordVerifier,
     password_hash::{PasswordHashString, errors::Error as HashError}
 };
-use rand::{distributions::Alphanumeric, Rng};
-use rocket::tokio::{/*self,*/ fs, io::AsyncWriteExt};
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
 use async_std::sync::Mutex as AsyncMutex;
+use serde::{Serialize, Deserialize};
 use thiserror::Error;
-use crate::do_while;
-use super::helpers;
+use super::{
+    helpers, Config,
+    sessions::{Sessions, Session, ClientInfo},
+    limiter::LoginLimiter,
+    totp::{Totp, TotpInfo, TotpConfig},
+    invites::Invites,
+    audit::AuditLog,
+    tokens::{ApiToken, TokenScope},
+    password::{self, PasswordPolicy, PolicyError},
+    store::{UserStore, StoreConfig, FileStore, SqliteStore}
+};
 
 type Cookie = rocket::http::Cookie<'static>;
 static USERS_FILE: Lazy<PathBuf> = Lazy::new(|| PathBuf::from(".secrets/db/users"));
-static NON_ASCII_PASS_MSG: &str = "Password must only contain ASCII characters";
 pub static ADMIN_USR_ID: &str = "admin";
+/// Users in this group have admin rights. The [`ADMIN_USR_ID`] user is always in this group.
+pub static ADMIN_GROUP: &str = "admin";
+
+
+/// A registered user.
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub hash: PasswordHashString,
+    pub info: AccountInfo,
+}
+/// Extra information about a user, stored with their password hash in the users file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountInfo {
+    /// Named groups (or roles) the user belongs to, which give the user access to routes guarded by [`RequireRole`](super::RequireRole).
+    pub groups: BTreeSet<String>,
+    /// When the account was registered. Is [`None`] for accounts created before this was recorded.
+    pub created: Option<DateTime<Utc>>,
+    /// A disabled user can't log in, but can be enabled again by an admin.
+    pub disabled: bool,
+    /// The last time the user logged in with their password.
+    pub last_login: Option<DateTime<Utc>>,
+    /// Set when the user has enabled two-factor authentication.
+    pub totp: Option<TotpInfo>,
+    /// Personal access tokens, used by scripts instead of a session.
+    pub tokens: Vec<ApiToken>,
+    /// Id of the user's profile picture (see [`pfp::path()`](super::pfp::path())).
+    pub pfp: Option<String>,
+}
 
 
 #[derive(Debug)]
 pub struct Users {
-    path: PathBuf,
-    // TODO: maybe use RwLock
-    db: AsyncMutex<HashMap<String, PasswordHashString>>,
-    /// `HashMap<SessionUuid, UserId>`
-    /// 
-    /// When server shuts down, all sessions are deleted, and all users are logged out.
-    sessions: AsyncMutex<HashMap<String, String>>
+    store: Arc<dyn UserStore>,
+    /// Held while an account is being read and written back (see [`Self::modify()`]),
+    /// so that concurrent changes (e.g. two admins editing the same user) don't overwrite each other.
+    write_lock: AsyncMutex<()>,
+    /// Saved in the [`UserStore`], so users stay logged in when the server restarts.
+    sessions: Arc<Sessions>,
+    /// Stored in a file next to the users database (see [`Self::invites_path()`]).
+    invites: Invites,
+    /// Stored in a file next to the users database (see [`Self::audit_path()`]).
+    audit: AuditLog,
+    /// Throttles failed logins, so passwords can't be brute-forced.
+    limiter: LoginLimiter,
+    totp_config: TotpConfig,
+    /// Parameters for hashing new passwords (see [`HashConfig`]).
+    hash_params: Params,
+    /// Checked for new passwords.
+    policy: PasswordPolicy,
 }
 impl Users {
     /// The character that separates [`User`] components (e.g. name, salt, ...).
     pub const SEP: char = '$';
-    /// Separates each entry in the file. E.g. `$user0$...\n$user1`
-    const ENTRY_SEP: char = '\n';
     /// Ranges of the allowed ASCII chars in the `UserName`.
     pub const ALLOWED_NAME_CHARS: [RangeInclusive<u8>; 4] = [
         48..=57, // Integers 0-9
@@ -46,225 +92,568 @@ impl Users {
         97..=122, // lowercase a-z
     ];
 
-    /// Load existing [`Users`] from a file.
-    // #[tokio::main]
-    pub fn load_path(path: PathBuf) -> Result<Self, LoadUsersError> {
-        use std::fs;
-        use std::process::Command;
-
-        let file = match fs::read_to_string(&path) {
-            Ok(file) => file,
-            // If file does not exist, create it
-            Err(_) => {
-                Command::new("mkdir")
-                    .arg("-p")
-                    .arg(match path.parent() {
-                        Some(path) => path, // path is a file within a directory
-                        None => &path // path is a file in the server's root
-                    }).status()?;
-                
-                Command::new("touch")
-                    .arg(&path)
-                    .status()?;
-                
-                fs::read_to_string(&path)?
-            }
-        };
-        
+    /// Use **store** for the users and sessions.
+    /// **data_path** is the path of the database, next to which other data (e.g. invites) is stored.
+    pub fn new(store: Arc<dyn UserStore>, data_path: &Path, config: Config) -> Result<Self, LoadUsersError> {
         Ok(Self {
-            db: AsyncMutex::new(Self::db_from_str(&file)?),
-            sessions: AsyncMutex::new(HashMap::new()),
-            path
+            sessions: Arc::new(Sessions::new(store.clone(), config.sessions)),
+            invites: Invites::load_path(Self::invites_path(data_path))?,
+            audit: AuditLog::new(Self::audit_path(data_path)),
+            limiter: LoginLimiter::new(config.rate_limit),
+            totp_config: config.totp,
+            hash_params: config.hash.params()?,
+            policy: PasswordPolicy::load(config.password)?,
+            write_lock: AsyncMutex::new(()),
+            store,
         })
     }
+
+    /// Load existing [`Users`] from a [`FileStore`] at **path**.
+    pub fn load_path(path: PathBuf, config: Config) -> Result<Self, LoadUsersError> {
+        let store = FileStore::load_path(path.clone())?;
+        Self::new(Arc::new(store), &path, config)
+    }
+
+    /// Load the [`UserStore`] selected in the **config** (see [`StoreConfig`]).
+    pub fn load_default(config: Config) -> Result<Self, LoadUsersError> {
+        match &config.store {
+            StoreConfig::File => Self::load_path(USERS_FILE.clone(), config),
+            StoreConfig::Sqlite { path } => {
+                let path = path.clone();
+                let store = SqliteStore::open(&path)?;
+                Self::new(Arc::new(store), &path, config)
+            }
+        }
+    }
+
+    /// The unused invites are stored in a file with the same name as the users database, followed by `.invites`.
+    /// E.g. `.secrets/db/users` -> `.secrets/db/users.invites`.
+    fn invites_path(path: &Path) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".invites");
+        PathBuf::from(path)
+    }
+    /// The [`AuditLog`] is stored in a file with the same name as the users database, followed by `.audit`.
+    /// E.g. `.secrets/db/users` -> `.secrets/db/users.audit`.
+    fn audit_path(path: &Path) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".audit");
+        PathBuf::from(path)
+    }
+
     #[inline]
-    pub fn load_default() -> Result<Self, LoadUsersError> {
-        Self::load_path(USERS_FILE.clone())
+    fn hasher(&self) -> Argon2<'static> {
+        password::hasher(self.hash_params.clone())
+    }
+
+    /// Reads the account of **username**, lets **f** change it, and writes it back.
+    /// Nothing is written if **f** returns an error.
+    async fn modify<T, E: From<io::Error>>(
+        &self,
+        username: &str,
+        unknown_user: E,
+        f: impl FnOnce(&mut Account) -> Result<T, E>
+    ) -> Result<T, E> {
+        let _lock = self.write_lock.lock().await;
+
+        let mut account = match self.store.get_user(username).await? {
+            Some(account) => account,
+            None => return Err(unknown_user)
+        };
+        let value = f(&mut account)?;
+        // The user could only be deleted by another change, which is prevented by the lock
+        self.store.update_user(username, &account).await?;
+        Ok(value)
+    }
+
+    async fn get_account(&self, username: &str) -> Option<Account> {
+        self.store.get_user(username).await
+            .unwrap_or_else(|error| {
+                eprintln!("Error reading user {username:?}: {error}");
+                None
+            })
     }
 
     /// Used for registering new users.
     /// Checks that **username** and **password** are both valid.
-    pub async fn add_user(&self, username: &str, password: &str) -> Result<Cookie, RegisterError> {
-        let db = &mut *self.db.lock().await;
+    pub async fn add_user(&self, username: &str, password: &str, client: &ClientInfo) -> Result<Cookie, RegisterError> {
+        self.create_user(username, password).await?;
+        Ok(self.new_session(username, client).await)
+    }
+    /// Like [`Self::add_user()`], but does not log in the new user (e.g. when an admin creates an account).
+    pub async fn create_user(&self, username: &str, password: &str) -> Result<(), RegisterError> {
+        let password = password::normalize(password);
+
+        helpers::validate_username(username)?;
+        self.policy.check(&password)?;
+        let hash = helpers::create_pass_hash(&self.hasher(), password.as_bytes())?;
+
+        let mut info = AccountInfo {
+            created: Some(Utc::now()),
+            ..Default::default()
+        };
+        if username == ADMIN_USR_ID {
+            info.groups.insert(ADMIN_GROUP.to_string());
+        }
 
-        if db.contains_key(username) {
+        let _lock = self.write_lock.lock().await;
+        if !self.store.insert_user(username, &Account { hash, info }).await? {
             return Err(RegisterError::ExistingUser)
         }
+        Ok(())
+    }
 
-        let password = if password.is_ascii() {
-            password.as_bytes()
-        } else {
-            return Err(RegisterError::NonAsciiPassword)
-        };
+    /// Registers a new user with an invite created by an admin.
+    /// The invite can only be used once, and if it has a group the new user is added to it.
+    pub async fn register_invited(&self, token: &str, username: &str, password: &str, client: &ClientInfo) -> Result<Cookie, RegisterError> {
+        let invite = self.invites.take(token).await.ok_or(RegisterError::InvalidInvite)?;
 
-        helpers::validate_username(username)?;
-        let hash = helpers::create_pass_hash(password)?;
-        
-        // Write new User to file before putting it in the server state, as this can fail.
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(&self.path).await?;
-
-        file.write_all(unsafe {
-            format!("{sep}{username}{sep}{}{}",
-                hash.as_str().chars().count(), hash, sep=Self::SEP
-            ).as_bytes_mut()
-        }).await?;
-        file.write_u8(b'\n').await?;
-        
-        db.insert(username.to_string(), hash.clone());
+        if let Err(error) = self.create_user(username, password).await {
+            // Let the invitee try again with another username
+            self.invites.restore(invite).await;
+            return Err(error)
+        }
+        if let Some(group) = invite.group {
+            // The account is already created, so this should not fail the registration
+            if let Err(error) = self.set_groups(username, [group]).await {
+                eprintln!("Could not add invited user {username:?} to their group: {error}");
+            }
+        }
+
+        Ok(self.new_session(username, client).await)
+    }
+
+    #[inline]
+    pub fn invites(&self) -> &Invites {
+        &self.invites
+    }
 
-        Ok(self.new_session(username).await)
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
     }
 
     /// Used for loging in existing users.
-    pub async fn verify_user(&self, username: &str, password: &str) -> Result<Cookie, LoginError> {
-        let password = if password.is_ascii() {
-            password.as_bytes()
-        } else {
-            return Err(LoginError::NonAsciiPassword)
-        };
+    /// Failed attempts are counted for both the **client**'s IP and the **username**,
+    /// and after too many the client has to wait before trying again (see [`LoginLimiter`]).
+    ///
+    /// If the user has enabled 2FA, they only get a session after also passing [`Self::verify_totp()`].
+    pub async fn verify_user(&self, username: &str, password: &str, client: &ClientInfo) -> Result<LoginStep, LoginError> {
+        let ip = client.ip;
+        let account = self.get_account(username).await;
+        // Check before hashing, so that the server's CPU can't be burned either.
+        // Made up usernames are only counted for the IP
+        if let Some(wait) = self.limiter.check(ip, account.as_ref().map(|_| username)).await {
+            return Err(LoginError::RateLimited(wait))
+        }
+
+        let password = password::normalize(password);
+        let password = password.as_bytes();
 
-        let hash = match self.db.lock().await.get(username) {
-            Some(hash) => hash.clone(),
-            None => return Err(LoginError::UnknownUser)
+        // Failures were already counted by the check
+        let account = account.ok_or(LoginError::UnknownUser)?;
+
+        if self.hasher().verify_password(password, &account.hash.password_hash()).is_err() {
+            return Err(LoginError::WrongPassword)
+        }
+        // Only after the password, so that it doesn't tell others which accounts are disabled
+        if account.info.disabled {
+            return Err(LoginError::Disabled)
+        }
+        if password::is_outdated(&account.hash.password_hash(), &self.hash_params) {
+            self.rehash(username, &account.hash, password).await;
+        }
+
+        if account.info.totp.is_some() {
+            self.limiter.release(ip, username).await;
+            return Ok(LoginStep::Totp(self.totp_pending_cookie(username)))
+        }
+
+        self.limiter.succeed(ip, username).await;
+        self.record_login(username).await;
+        Ok(LoginStep::Session(self.new_session(username, client).await))
+    }
+
+    /// The second step of [`Self::verify_user()`] for users with 2FA.
+    /// **pending** is the value of the [`TOTP_PENDING_COOKIE`](super::TOTP_PENDING_COOKIE) given after the first step,
+    /// and **code** is either a [`Totp`] code or one of the user's recovery codes.
+    pub async fn verify_totp(&self, pending: &str, code: &str, client: &ClientInfo) -> Result<Cookie, LoginError> {
+        let ip = client.ip;
+        let username = Self::parse_totp_pending(pending).ok_or(LoginError::TotpExpired)?;
+
+        if let Some(wait) = self.limiter.check(ip, Some(username)).await {
+            return Err(LoginError::RateLimited(wait))
+        }
+
+        // Failures were already counted by the check.
+        // A code that can't be consumed must not be accepted either, or it could be used again
+        self.modify(username, LoginError::UnknownUser, |account| {
+            if account.info.disabled {
+                return Err(LoginError::Disabled)
+            }
+            let now = Utc::now();
+            let verified = match &mut account.info.totp {
+                Some(totp) => totp.verify(code, now),
+                // 2FA was disabled after the first step, so the password is enough
+                None => true
+            };
+            if !verified {
+                return Err(LoginError::WrongCode)
+            }
+            // Also consumes the code
+            account.info.last_login = Some(now);
+            Ok(())
+        }).await?;
+
+        self.limiter.succeed(ip, username).await;
+        Ok(self.new_session(username, client).await)
+    }
+
+    /// Proves that the client passed the first step of the login (the password) within [`Self::TOTP_PENDING_TIMEOUT`].
+    /// Its value is `"{user id}$[expiry timestamp]"`, which the client can't forge because it is a *private cookie*.
+    fn totp_pending_cookie(&self, username: &str) -> Cookie {
+        let expires = Utc::now().timestamp() + Self::TOTP_PENDING_TIMEOUT;
+        Cookie::build(super::TOTP_PENDING_COOKIE, format!("{username}{}{expires}", Self::SEP))
+            .http_only(true)
+            .same_site(rocket::http::SameSite::Strict)
+            .max_age(rocket::time::Duration::seconds(Self::TOTP_PENDING_TIMEOUT))
+            .finish()
+    }
+    /// Seconds that the user has to enter their 2FA code after entering their password.
+    const TOTP_PENDING_TIMEOUT: i64 = 5 * 60;
+    /// The `user id` in the value of a [`TOTP_PENDING_COOKIE`](super::TOTP_PENDING_COOKIE), if it has not expired.
+    pub(super) fn parse_totp_pending(pending: &str) -> Option<&str> {
+        let (username, expires) = pending.rsplit_once(Self::SEP)?;
+        let expires = expires.parse::<i64>().ok()?;
+        (Utc::now().timestamp() < expires).then_some(username)
+    }
+    /// Replaces the **old** hash of **username** with a hash of the same **password** using the current [`HashConfig`].
+    /// The old hash still works, so failing to save the new one is only logged.
+    async fn rehash(&self, username: &str, old: &PasswordHashString, password: &[u8]) {
+        let hash = match helpers::create_pass_hash(&self.hasher(), password) {
+            Ok(hash) => hash,
+            Err(error) => return eprintln!("Could not rehash the password of {username:?}: {error}")
         };
-        let hash = hash.password_hash();
+        let result = self.modify(username, io::Error::from(io::ErrorKind::NotFound), |account| {
+            // The password could have been changed since it was verified
+            if account.hash.as_str() == old.as_str() {
+                account.hash = hash;
+            }
+            Ok(())
+        }).await;
+        if let Err(error) = result {
+            eprintln!("Could not save the rehashed password of {username:?}: {error}");
+        }
+    }
+
+    /// Sets [`AccountInfo::last_login`] of **username** to now.
+    /// Failing to save this should not prevent the user from logging in, so the error is only logged.
+    async fn record_login(&self, username: &str) {
+        let result = self.modify(username, io::Error::from(io::ErrorKind::NotFound), |account| {
+            account.info.last_login = Some(Utc::now());
+            Ok(())
+        }).await;
+        if let Err(error) = result {
+            eprintln!("Could not save last login of {username:?}: {error}");
+        }
+    }
+
+    #[inline]
+    pub fn totp_config(&self) -> &TotpConfig {
+        &self.totp_config
+    }
+
+    /// Whether the user must enable 2FA before using their admin rights (see [`TotpConfig::require_for_admin`]).
+    pub fn needs_totp(&self, info: &AccountInfo) -> bool {
+        self.totp_config.require_for_admin
+        && info.totp.is_none()
+        && info.groups.contains(ADMIN_GROUP)
+    }
+
+    /// Enables 2FA for **username** with the **totp** secret they have added to their authenticator app.
+    /// The user must enter a valid **code** to prove they did.
+    ///
+    /// Returns the recovery codes, which can be used once each instead of a [`Totp`] code.
+    pub async fn enable_totp(&self, username: &str, totp: &Totp, code: &str) -> Result<Vec<String>, TotpError> {
+        self.modify(username, TotpError::UnknownUser, |account| {
+            if account.info.totp.is_some() {
+                return Err(TotpError::AlreadyEnabled)
+            }
+            let step = totp.verify(code, Utc::now(), None).ok_or(TotpError::WrongCode)?;
+
+            let (mut info, codes) = TotpInfo::new(totp);
+            info.last_step = Some(step);
+            account.info.totp = Some(info);
+            Ok(codes)
+        }).await
+    }
+
+    /// Disables 2FA for **username**, after checking their **password**.
+    /// Wrong passwords are throttled like failed logins (see [`LoginLimiter`]).
+    pub async fn disable_totp(&self, username: &str, password: &str, client: &ClientInfo) -> Result<(), TotpError> {
+        let ip = client.ip;
+        let require_for_admin = self.totp_config.require_for_admin;
+        let is_required = |info: &AccountInfo| require_for_admin && info.groups.contains(ADMIN_GROUP);
+
+        let account = self.get_account(username).await.ok_or(TotpError::UnknownUser)?;
+        if is_required(&account.info) {
+            return Err(TotpError::Required)
+        }
+        if let Some(wait) = self.limiter.check(ip, Some(username)).await {
+            return Err(TotpError::RateLimited(wait))
+        }
+        // Failures were already counted by the check.
+        // Hashed before taking the lock of `modify`, so that it doesn't block other changes
+        if self.hasher().verify_password(password::normalize(password).as_bytes(), &account.hash.password_hash()).is_err() {
+            return Err(TotpError::WrongPassword)
+        }
+        self.limiter.succeed(ip, username).await;
+
+        self.modify(username, TotpError::UnknownUser, |current| {
+            // The password could have been changed since it was verified
+            if current.hash.as_str() != account.hash.as_str() {
+                return Err(TotpError::WrongPassword)
+            }
+            if is_required(&current.info) {
+                return Err(TotpError::Required)
+            }
+            current.info.totp = None;
+            Ok(())
+        }).await
+    }
+
+    /// Used by a logged in user to change their own password.
+    /// Wrong **current** passwords are throttled like failed logins (see [`LoginLimiter`]).
+    /// All of the user's sessions are removed, and the user gets a new session.
+    pub async fn change_password(&self, username: &str, current: &str, new: &str, client: &ClientInfo) -> Result<Cookie, ChangePasswordError> {
+        let ip = client.ip;
+        let current = password::normalize(current);
 
-        match Argon2::default().verify_password(password, &hash) {
-            Ok(()) => Ok(self.new_session(username).await),
-            Err(_) => Err(LoginError::WrongPassword)
+        let hash = match self.get_account(username).await {
+            Some(account) => account.hash,
+            None => return Err(ChangePasswordError::UnknownUser)
+        };
+        if let Some(wait) = self.limiter.check(ip, Some(username)).await {
+            return Err(ChangePasswordError::RateLimited(wait))
+        }
+        // Failures were already counted by the check
+        if self.hasher().verify_password(current.as_bytes(), &hash.password_hash()).is_err() {
+            return Err(ChangePasswordError::WrongPassword)
         }
+        self.limiter.succeed(ip, username).await;
+
+        self.replace_password(username, new, Some(&hash)).await?;
+        Ok(self.new_session(username, client).await)
+    }
+
+    /// Replaces the password of **username** without checking the old one (e.g. when an admin resets it).
+    /// All of the user's sessions are removed, so they have to log in again with the new password.
+    pub async fn set_password(&self, username: &str, password: &str) -> Result<(), ChangePasswordError> {
+        self.replace_password(username, password, None).await
     }
 
-    /// If is a valid session, returns the `user id` of that session
-    pub async fn validate_session(&self, session_uuid: &str) -> Option<String> {
-        self.sessions.lock().await
-            .get(session_uuid)
-            .and_then(|user_id| Some(user_id.clone()))
+    /// Replaces the password of **username**, if their hash is still the **verified** one.
+    /// The hashing is done before taking the lock of [`Self::modify()`], so that it doesn't block other changes.
+    async fn replace_password(&self, username: &str, password: &str, verified: Option<&PasswordHashString>) -> Result<(), ChangePasswordError> {
+        let password = password::normalize(password);
+        self.policy.check(&password)?;
+        let hash = helpers::create_pass_hash(&self.hasher(), password.as_bytes())?;
+
+        self.modify(username, ChangePasswordError::UnknownUser, |account| {
+            // The password could have been changed since it was verified
+            if verified.is_some_and(|verified| verified.as_str() != account.hash.as_str()) {
+                return Err(ChangePasswordError::WrongPassword)
+            }
+            account.hash = hash;
+            Ok(())
+        }).await?;
+
+        self.sessions.remove_user(username).await;
+        Ok(())
+    }
+
+    /// If is a valid session, returns the [`Session`], which has the `user id` of that session.
+    /// Returns [`None`] if the session does not exist or expired.
+    pub async fn validate_session(&self, session_uuid: &str) -> Option<Session> {
+        self.sessions.validate(session_uuid).await
     }
 
     pub async fn remove_session(&self, session_uuid: &str) {
-        self.sessions.lock().await.remove(session_uuid);
+        self.sessions.remove(session_uuid).await
     }
 
-    async fn new_session(&self, username: &str) -> Cookie {
-        let sessions = &mut *self.sessions.lock().await;
+    /// The active sessions of **username**, most recently seen first.
+    pub async fn user_sessions(&self, username: &str) -> Vec<Session> {
+        self.sessions.list_user(username).await
+    }
 
-        let mut uuid: String;
-        // Ensure the uuid is unique
-        do_while!{ do {
-            uuid = rand::thread_rng()
-                .sample_iter(&Alphanumeric)
-                .take(37)
-                .map(char::from)
-                .collect();
-        } while sessions.contains_key(&uuid) };
+    /// Removes the session of **username** with the [`id`](Session::id()) **session_id**.
+    /// Returns the removed session, or [`None`] if the user has no such session.
+    pub async fn revoke_session(&self, username: &str, session_id: &str) -> Option<Session> {
+        let session = self.sessions.list_user(username).await
+            .into_iter()
+            .find(|session| session.id() == session_id)?;
+        self.sessions.remove(&session.uuid).await;
+        Some(session)
+    }
 
-        // Save session
-        sessions.insert(uuid.clone(), username.to_string());
+    /// Logs out **username** everywhere, except in the session with **keep_uuid**.
+    pub async fn logout_others(&self, username: &str, keep_uuid: &str) {
+        self.sessions.remove_user_except(username, keep_uuid).await
+    }
+
+    /// A handle to the sessions, used to prune them in the background.
+    pub fn sessions(&self) -> Arc<Sessions> {
+        self.sessions.clone()
+    }
 
-        Cookie::build(super::SESSION_COOKIE, uuid)
-            .secure(true)
+    async fn new_session(&self, username: &str, client: &ClientInfo) -> Cookie {
+        let session = self.sessions.insert(username, client.clone()).await;
+        self.session_cookie(&session)
+    }
+
+    /// The cookie the client must hold to use the **session**.
+    /// Expires when the session would become idle, so sending it again renews the client's cookie.
+    /// Is marked `Secure` by the [`ProxyFairing`](crate::proxy::ProxyFairing) when the client uses *https*.
+    pub fn session_cookie(&self, session: &Session) -> Cookie {
+        Cookie::build(super::SESSION_COOKIE, session.uuid.clone())
             .http_only(true)
             .same_site(rocket::http::SameSite::Strict)
+            .max_age(rocket::time::Duration::seconds(session.cookie_max_age(self.sessions.config())))
             .finish()
             .into_owned()
     }
 
+    /// Creates a personal access token for **username**, that expires after **lifetime** (or never).
+    /// Returns the token's info, and the token to give to the user.
+    pub async fn create_token(
+        &self,
+        username: &str,
+        name: &str,
+        scope: TokenScope,
+        lifetime: Option<chrono::Duration>
+    ) -> Result<(ApiToken, String), UpdateUserError> {
+        self.modify(username, UpdateUserError::UnknownUser, |account| {
+            let now = Utc::now();
+            account.info.tokens.retain(|token| !token.is_expired(now));
+
+            let (token, secret) = ApiToken::new(username, name.to_string(), scope, lifetime);
+            account.info.tokens.push(token.clone());
+            Ok((token, secret))
+        }).await
+    }
+
+    /// Removes the token of **username** with **token_id**, and returns it.
+    pub async fn revoke_token(&self, username: &str, token_id: &str) -> Result<ApiToken, UpdateUserError> {
+        self.modify(username, UpdateUserError::UnknownUser, |account| {
+            let index = account.info.tokens.iter()
+                .position(|token| token.id == token_id)
+                .ok_or(UpdateUserError::UnknownToken)?;
+            Ok(account.info.tokens.remove(index))
+        }).await
+    }
+
+    /// If **token** is a valid personal access token, returns its `user id` and [`TokenScope`].
+    /// Tokens of disabled users are not valid.
+    pub async fn validate_token(&self, token: &str) -> Option<(String, TokenScope)> {
+        let (username, secret) = ApiToken::parse(token)?;
+        let account = self.get_account(username).await?;
+        if account.info.disabled {
+            return None
+        }
+
+        let now = Utc::now();
+        account.info.tokens.iter()
+            .find(|token| token.verify(secret, now))
+            .map(|token| (username.to_string(), token.scope))
+    }
+
     /// Returns an [`Iterator`] over all the `users ids` registered in the server.
     pub(super) async fn usernames(&self) -> impl Iterator<Item = String> {
-        self.db.lock().await
-            .keys()
-            .map(|s| s.to_owned())
-            .collect::<Vec<_>>()
+        self.accounts().await
             .into_iter()
+            .map(|(name, _)| name)
     }
 
-    /// Format: `$username$PasswordHashLength$PasswordHash`.
-    /// Each user separated by a *line-break* `\n`.
-    fn db_from_str(s: &str) -> Result<HashMap<String, PasswordHashString>, LoadUsersError> {
-        #[derive(PartialEq)]
-        enum State {
-            Name, HashLen
+    /// Returns the [`AccountInfo`] of all users, sorted by `user id`.
+    pub async fn accounts(&self) -> Vec<(String, AccountInfo)> {
+        match self.store.list_users().await {
+            Ok(accounts) => accounts.into_iter()
+                .map(|(name, account)| (name, account.info))
+                .collect(),
+            Err(error) => {
+                eprintln!("Error reading users: {error}");
+                Vec::new()
+            }
         }
-        #[derive(Default)]
-        struct Builder {
-            name: String,
+    }
+
+    pub async fn account_info(&self, username: &str) -> Option<AccountInfo> {
+        self.get_account(username).await.map(|account| account.info)
+    }
+
+    /// Sets the id of the profile picture of **username** (or removes it with [`None`]).
+    /// Returns the id of the previous picture, so its files can be deleted.
+    pub async fn set_pfp(&self, username: &str, pfp: Option<String>) -> Result<Option<String>, UpdateUserError> {
+        self.modify(username, UpdateUserError::UnknownUser, |account| {
+            Ok(std::mem::replace(&mut account.info.pfp, pfp))
+        }).await
+    }
+
+    /// Replaces the groups that **username** belongs to.
+    /// The [`ADMIN_USR_ID`] user can't be removed from the [`ADMIN_GROUP`].
+    pub async fn set_groups(&self, username: &str, groups: impl IntoIterator<Item = String>) -> Result<(), UpdateUserError> {
+        let mut groups = groups.into_iter()
+            .map(|group| helpers::validate_group(&group).map(|()| group))
+            .collect::<Result<BTreeSet<_>, _>>()?;
+        if username == ADMIN_USR_ID {
+            groups.insert(ADMIN_GROUP.to_string());
         }
 
-        let mut db = HashMap::new();
-        let mut builder = Builder::default();
-        let mut buf = String::new();
-        let mut chars = s.chars();
-        let mut state = None;
-
-        while let Some(ch) = chars.next() {
-            if ch == Self::SEP {
-                match state {
-                    // Every entry starts with `Self::SEP`
-                    None => state = Some(State::Name),
-
-                    Some(State::Name) => {
-                        if buf.is_empty() {
-                            return Err(LoadUsersError::InvalidUserName(UserNameError::Empty))
-                        }
-                        builder.name = buf;
-                        buf = String::new();
-                        state = Some(State::HashLen)
-                    }
-                    Some(State::HashLen) => {
-                        // The length of the PasswordHashString
-                        let len = buf.parse::<usize>()
-                            .map_err(|error| LoadUsersError::BadHashLength(error))?;
-                        buf = String::with_capacity(len);
-
-                        buf.push(Self::SEP);
-                        let mut count = 1; // already read the '$'
-                        // Read `len` characters of PasswordHash 
-                        while let Some(ch) = chars.next() {
-                            buf.push(ch);
-                            count += 1;
-                            if count == len {
-                                break
-                            }
-                        }
-                        if count != len {
-                            // File ended before enough hash characters could be read
-                            return Err(LoadUsersError::IncompleteHash)
-                        }
-
-                        // After each entry there should be a line to separate Users
-                        match chars.next() {
-                            Some(Self::ENTRY_SEP) | None => {},
-                            Some(_) => return Err(LoadUsersError::InvalidEntrySep(ch))
-                        }
-
-                        db.insert(builder.name, PasswordHash::new(&buf)?.serialize());
-                        builder = Builder::default();
-                        buf = String::new();
-                        state = None
-                    }
-                }
-
-                continue
-            }
-            match state {
-                // When entry does not start with `Self::SEP`
-                None => return Err(LoadUsersError::InvalidEntry),
-                // If reading UserName, make sure it has valid chars
-                Some(State::Name) =>
-                    if !helpers::validate_username_char(ch) {
-                        return Err(LoadUsersError::InvalidUserName(ch.into()))
-                    }
-                _ => {}
-            }
+        self.modify(username, UpdateUserError::UnknownUser, |account| {
+            account.info.groups = groups;
+            Ok(())
+        }).await
+    }
 
-            buf.push(ch);
+    /// Disables or enables **username**.
+    /// A disabled user is logged out everywhere, and can't log in until enabled.
+    pub async fn set_disabled(&self, username: &str, disabled: bool) -> Result<(), UpdateUserError> {
+        if username == ADMIN_USR_ID {
+            return Err(UpdateUserError::AdminUser)
         }
+        self.modify(username, UpdateUserError::UnknownUser, |account| {
+            account.info.disabled = disabled;
+            Ok(())
+        }).await?;
 
-        Ok(db)
+        if disabled {
+            self.sessions.remove_user(username).await;
+        }
+        Ok(())
+    }
+
+    /// Removes all the sessions of **username**, without changing their account.
+    pub async fn logout_user(&self, username: &str) -> Result<(), UpdateUserError> {
+        if self.get_account(username).await.is_none() {
+            return Err(UpdateUserError::UnknownUser)
+        }
+        self.sessions.remove_user(username).await;
+        Ok(())
+    }
+
+    /// Removes **username** from the database, and logs them out everywhere.
+    pub async fn delete_user(&self, username: &str) -> Result<(), UpdateUserError> {
+        if username == ADMIN_USR_ID {
+            return Err(UpdateUserError::AdminUser)
+        }
+
+        let _lock = self.write_lock.lock().await;
+        if !self.store.delete_user(username).await? {
+            return Err(UpdateUserError::UnknownUser)
+        }
+
+        self.sessions.remove_user(username).await;
+        Ok(())
     }
 }
 
@@ -274,7 +663,7 @@ pub enum LoadUsersError {
     BadHashLength(#[from] ParseIntError),
     #[error("Length of PasswordHash does not match its actual length")]
     IncompleteHash,
-    #[error("Each user entry in the file must be separated by a {:?}", Users::ENTRY_SEP)]
+    #[error("Each user entry in the file must be separated by a {:?}", FileStore::ENTRY_SEP)]
     InvalidEntrySep(char),
     #[error("Each entry must start with {:?}", Users::SEP)]
     InvalidEntry,
@@ -282,6 +671,10 @@ pub enum LoadUsersError {
     InvalidUserName(#[from] UserNameError),
     #[error("Error hashing password: {0:}")]
     InvalidHash(HashError),
+    #[error("Invalid entry in \"users\" database file: {0}")]
+    InvalidJson(serde_json::Error),
+    #[error("\"users\" database file has version {0}, but this server only supports up to version {}", FileStore::VERSION)]
+    UnsupportedVersion(u32),
     #[error("Error reading \"users\" database file")]
     IoError(#[from] io::Error)
 }
@@ -296,10 +689,12 @@ impl From<HashError> for LoadUsersError {
 pub enum RegisterError {
     #[error("Username already exists")]
     ExistingUser,
-    #[error("{NON_ASCII_PASS_MSG}")]
-    NonAsciiPassword,
+    #[error("This invite link is invalid or has expired")]
+    InvalidInvite,
     #[error("{0}")]
     InvalidName(#[from] UserNameError),
+    #[error("{0}")]
+    WeakPassword(#[from] PolicyError),
     #[error("Error hashing password: {0}")]
     HashError(HashError),
     /// Error when appending to users file.
@@ -312,14 +707,86 @@ impl From<HashError> for RegisterError {
     }
 }
 
+#[derive(Error, Debug)]
+pub enum ChangePasswordError {
+    #[error("Username not found")]
+    UnknownUser,
+    #[error("Current password is wrong")]
+    WrongPassword,
+    #[error("Too many failed attempts, try again in {0} seconds")]
+    RateLimited(u64),
+    #[error("{0}")]
+    WeakPassword(#[from] PolicyError),
+    #[error("Error hashing password: {0}")]
+    HashError(HashError),
+    #[error("Error saving password hash: {0:?}")]
+    IoError(#[from] io::Error)
+}
+impl From<HashError> for ChangePasswordError {
+    fn from(value: HashError) -> Self {
+        Self::HashError(value)
+    }
+}
+
+/// The outcome of a successful [`Users::verify_user()`].
+#[derive(Debug)]
+pub enum LoginStep {
+    /// The user is logged in with this session cookie.
+    Session(Cookie),
+    /// The password is right, but the user has to enter a 2FA code (see [`Users::verify_totp()`]).
+    /// Holds the [`TOTP_PENDING_COOKIE`](super::TOTP_PENDING_COOKIE).
+    Totp(Cookie),
+}
+
 #[derive(Error, Debug)]
 pub enum LoginError {
-    #[error("{NON_ASCII_PASS_MSG}")]
-    NonAsciiPassword,
     #[error("Username not found")]
     UnknownUser,
     #[error("Wrong password")]
-    WrongPassword
+    WrongPassword,
+    #[error("This account is disabled")]
+    Disabled,
+    #[error("Too many failed attempts, try again in {0} seconds")]
+    RateLimited(u64),
+    #[error("Wrong code")]
+    WrongCode,
+    #[error("Took too long to enter the code, please log in again")]
+    TotpExpired,
+    #[error("IO Error: {0}")]
+    IoError(#[from] io::Error)
+}
+
+#[derive(Error, Debug)]
+pub enum TotpError {
+    #[error("Username not found")]
+    UnknownUser,
+    #[error("Two-factor authentication is already enabled")]
+    AlreadyEnabled,
+    #[error("Wrong code, make sure the time on your device is correct")]
+    WrongCode,
+    #[error("Wrong password")]
+    WrongPassword,
+    #[error("Too many failed attempts, try again in {0} seconds")]
+    RateLimited(u64),
+    #[error("Two-factor authentication is required for admins")]
+    Required,
+    #[error("Error saving user: {0:?}")]
+    IoError(#[from] io::Error)
+}
+
+/// Error when modifying an existing user.
+#[derive(Error, Debug)]
+pub enum UpdateUserError {
+    #[error("Username not found")]
+    UnknownUser,
+    #[error("Group name {0:?} can only contain characters allowed in usernames")]
+    InvalidGroup(String),
+    #[error("The {ADMIN_USR_ID:?} user can't be disabled or deleted")]
+    AdminUser,
+    #[error("Token not found")]
+    UnknownToken,
+    #[error("Error saving user: {0:?}")]
+    IoError(#[from] io::Error)
 }
 
 #[derive(Error, Debug)]
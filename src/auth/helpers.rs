@@ -3,11 +3,13 @@ use argon2::{
     password_hash::{PasswordHashString, SaltString, errors::Error as HashError}
 };
 use rand_core::OsRng;
+use rocket::tokio::fs;
+use std::{io, path::Path};
 use crate::do_while;
-use super::db::{Users, UserNameError};
+use super::db::{Users, UserNameError, UpdateUserError};
 
 
-pub fn create_pass_hash(password: &[u8]) -> Result<PasswordHashString, HashError> {
+pub fn create_pass_hash(argon2: &Argon2, password: &[u8]) -> Result<PasswordHashString, HashError> {
     let salt = {
         let mut salt;
         // regenerate `salt` while it contains ':'
@@ -18,7 +20,7 @@ pub fn create_pass_hash(password: &[u8]) -> Result<PasswordHashString, HashError
         salt
     };
     // let pepper = SaltString::generate(&mut OsRng);
-    let password_hash = Argon2::default().hash_password(password, &salt)?;
+    let password_hash = argon2.hash_password(password, &salt)?;
 
     Ok(password_hash.serialize())
 }
@@ -36,6 +38,10 @@ pub fn validate_username(username: &str) -> Result<(), UserNameError> {
     
     Ok(())
 }
+/// Group names follow the same rules as usernames.
+pub fn validate_group(group: &str) -> Result<(), UpdateUserError> {
+    validate_username(group).map_err(|_| UpdateUserError::InvalidGroup(group.to_string()))
+}
 /// Returns `true` for any valid [`char`].
 /// Returns `false` if **ch** is not in any of the ranges in [`User::ALLOWED_NAME_CHARS`].
 pub fn validate_username_char(ch: char) -> bool {
@@ -49,6 +55,16 @@ pub fn validate_username_char(ch: char) -> bool {
     false
 }
 
+/// Replaces the contents of the file at **path** without leaving it half-written if the server stops midway.
+/// The **contents** are written to a temporary file next to it, which is then renamed to **path**.
+pub async fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+
+    fs::write(&temp, contents).await?;
+    fs::rename(&temp, path).await
+}
+
 pub async fn admin_user_exists(users: &Users) -> bool {
     users.usernames().await
         .find(|user_id|
@@ -61,9 +77,15 @@ pub async fn admin_user_exists(users: &Users) -> bool {
 mod tests {
     use std::process::Command;
     use std::fs;
-    use std::{path::PathBuf, error::Error};
+    use std::{path::{Path, PathBuf}, error::Error};
     use rocket::tokio;
-    use super::Users;
+    use chrono::{Duration, TimeZone, Utc};
+    use crate::auth::db::{Users, LoginError, LoginStep, RegisterError, ChangePasswordError, TotpError};
+    use crate::auth::totp::Totp;
+    use crate::auth::tokens::TokenScope;
+    use std::net::IpAddr;
+    use crate::auth::{Config, sessions::{SessionConfig, ClientInfo}, limiter::RateLimitConfig, store::{SqliteStore, UserStore}, password::{HashConfig, PolicyConfig, PolicyError}};
+    use std::sync::Arc;
 
     
     fn temp() -> Result<PathBuf, Box<dyn Error>> {
@@ -84,17 +106,531 @@ mod tests {
         ).unwrap();
         println!("file:\n{}", String::from_utf8(std::fs::read(&path).unwrap()).unwrap());
 
-        let db = Users::load_path(path.clone()).unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
         dbg!(db);
     }
 
+    #[tokio::test]
+    async fn migrate_v1() {
+        let path = temp().unwrap();
+        let v1 = "$admin$113$argon2id$v=19$m=4096,t=3,p=1$DkiuneDgPzT0wJDiNly1TQ$TBPaDhAzZNnvSEQVHHy5yd/Ih34jwHkRJDTP9Yy+KG5gpLvfC/siR9NFJ9GK\n$viewer$113$argon2id$v=19$m=4096,t=3,p=1$M92l6PXdp3JfUtLd5mfD2Q$DxDiy3w54NcvTvxrzT4JbM8zreWimW8HMCit1vZ+Yczes9u8Yu0pVBGoRCxt";
+        fs::write(&path, v1).unwrap();
+        fs::write(sibling(&path, ".info"), r#"{ "viewer": { "groups": ["editors"] } }"#).unwrap();
+
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        assert!(db.account_info("viewer").await.unwrap().groups.contains("editors"));
+        drop(db);
+
+        // The old files are backed up, and the info is merged into the users file
+        assert_eq!(fs::read_to_string(sibling(&path, ".v1.bak")).unwrap(), v1);
+        assert!(sibling(&path, ".info.v1.bak").exists());
+        assert!(!sibling(&path, ".info").exists());
+        let file = fs::read_to_string(&path).unwrap();
+        assert!(file.starts_with("#users-db v2\n"));
+
+        // Loading the new file does not migrate again
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        assert!(db.account_info("viewer").await.unwrap().groups.contains("editors"));
+        assert!(db.account_info("admin").await.unwrap().groups.contains("admin"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), file);
+
+        // Files from a newer server are not overwritten
+        fs::write(&path, "#users-db v3\n").unwrap();
+        assert!(Users::load_path(path, Config::default()).is_err());
+    }
+
+    fn sibling(path: &Path, suffix: &str) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
     #[tokio::test]
     async fn add_user() {
         let path = temp().unwrap();
-        let db = Users::load_path(path.clone()).unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
 
-        db.add_user("admin", "password").await.unwrap();
-        db.add_user("viewer", "password").await.unwrap();
+        db.add_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        db.add_user("viewer", "password", &ClientInfo::default()).await.unwrap();
         println!("file:\n{}", String::from_utf8(std::fs::read(path).unwrap()).unwrap());
     }
+
+    #[tokio::test]
+    async fn groups() {
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        db.add_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        db.add_user("viewer", "password", &ClientInfo::default()).await.unwrap();
+
+        db.set_groups("viewer", ["admin".to_string(), "editors".to_string()]).await.unwrap();
+        // "admin" user can't lose admin rights
+        db.set_groups("admin", []).await.unwrap();
+        assert!(db.set_groups("viewer", ["not a group".to_string()]).await.is_err());
+        drop(db);
+
+        let db = Users::load_path(path, Config::default()).unwrap();
+        let viewer = db.account_info("viewer").await.unwrap();
+        assert!(viewer.groups.contains("admin") && viewer.groups.contains("editors"));
+        assert!(db.account_info("admin").await.unwrap().groups.contains("admin"));
+    }
+
+    #[tokio::test]
+    async fn change_password() {
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        let old_session = db.add_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        db.add_user("viewer", "password", &ClientInfo::default()).await.unwrap();
+
+        assert!(db.change_password("admin", "wrong", "new-password", &ClientInfo::default()).await.is_err());
+        let new_session = db.change_password("admin", "password", "new-password", &ClientInfo::default()).await.unwrap();
+        // Only the new session is valid
+        assert!(db.validate_session(old_session.value()).await.is_none());
+        assert!(db.validate_session(new_session.value()).await.is_some());
+        db.set_password("viewer", "reset-password").await.unwrap();
+        drop(db);
+
+        let db = Users::load_path(path, Config::default()).unwrap();
+        assert!(db.verify_user("admin", "password", &ClientInfo::default()).await.is_err());
+        db.verify_user("admin", "new-password", &ClientInfo::default()).await.unwrap();
+        db.verify_user("viewer", "reset-password", &ClientInfo::default()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn disable_and_delete() {
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        db.add_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        let session = db.add_user("viewer", "password", &ClientInfo::default()).await.unwrap();
+        db.add_user("guest", "password", &ClientInfo::default()).await.unwrap();
+
+        // The admin user is protected
+        assert!(db.set_disabled("admin", true).await.is_err());
+        assert!(db.delete_user("admin").await.is_err());
+
+        db.set_disabled("viewer", true).await.unwrap();
+        assert!(db.validate_session(session.value()).await.is_none());
+        assert!(matches!(db.verify_user("viewer", "password", &ClientInfo::default()).await, Err(LoginError::Disabled)));
+        // Only told with the right password
+        assert!(matches!(db.verify_user("viewer", "wrong", &ClientInfo::default()).await, Err(LoginError::WrongPassword)));
+        db.delete_user("guest").await.unwrap();
+        drop(db);
+
+        let db = Users::load_path(path, Config::default()).unwrap();
+        assert!(matches!(db.verify_user("viewer", "password", &ClientInfo::default()).await, Err(LoginError::Disabled)));
+        assert!(matches!(db.verify_user("guest", "password", &ClientInfo::default()).await, Err(LoginError::UnknownUser)));
+        db.set_disabled("viewer", false).await.unwrap();
+        db.verify_user("viewer", "password", &ClientInfo::default()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn login_stats() {
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        db.create_user("admin", "password").await.unwrap();
+        assert_eq!(db.account_info("admin").await.unwrap().last_login, None);
+        assert!(db.sessions().count_by_user().await.is_empty());
+
+        db.verify_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        db.verify_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        assert_eq!(db.sessions().count_by_user().await.get("admin"), Some(&2));
+        let last_login = db.account_info("admin").await.unwrap().last_login;
+        assert!(last_login.is_some());
+
+        db.logout_user("admin").await.unwrap();
+        assert!(db.sessions().count_by_user().await.is_empty());
+        drop(db);
+
+        let db = Users::load_path(path, Config::default()).unwrap();
+        assert_eq!(db.account_info("admin").await.unwrap().last_login, last_login);
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let path = temp().unwrap();
+        let config = Config {
+            rate_limit: RateLimitConfig { free_attempts: 2, base_delay: 60, ..Default::default() },
+            ..Default::default()
+        };
+        let db = Users::load_path(path, config).unwrap();
+        db.create_user("admin", "password").await.unwrap();
+        db.create_user("viewer", "password").await.unwrap();
+        let ip = &ClientInfo { ip: Some(IpAddr::from([127, 0, 0, 1])), user_agent: None };
+        let other_ip = &ClientInfo { ip: Some(IpAddr::from([127, 0, 0, 2])), user_agent: None };
+
+        let third_ip = &ClientInfo { ip: Some(IpAddr::from([127, 0, 0, 3])), user_agent: None };
+
+        // A success resets the count of the user, but not of the IP
+        assert!(matches!(db.verify_user("admin", "wrong", ip).await, Err(LoginError::WrongPassword)));
+        db.verify_user("admin", "password", ip).await.unwrap();
+        assert!(matches!(db.verify_user("admin", "wrong", other_ip).await, Err(LoginError::WrongPassword)));
+        assert!(matches!(db.verify_user("admin", "wrong", other_ip).await, Err(LoginError::WrongPassword)));
+
+        // Even the right password is rejected while throttled, from any IP
+        assert!(matches!(db.verify_user("admin", "password", ip).await, Err(LoginError::RateLimited(_))));
+        assert!(matches!(db.verify_user("admin", "password", third_ip).await, Err(LoginError::RateLimited(_))));
+        // And the IP is throttled for any user
+        assert!(matches!(db.verify_user("viewer", "wrong", ip).await, Err(LoginError::WrongPassword)));
+        assert!(matches!(db.verify_user("viewer", "password", ip).await, Err(LoginError::RateLimited(_))));
+        db.verify_user("viewer", "password", third_ip).await.unwrap();
+
+        // Parallel attempts can't all pass before the first ones fail
+        let attempts = rocket::futures::future::join_all((0..5).map(|_| db.verify_user("viewer", "wrong", third_ip))).await;
+        assert_eq!(attempts.iter().filter(|attempt| matches!(attempt, Err(LoginError::WrongPassword))).count(), 2);
+        // Guessing the current password to change it is throttled the same way
+        let fourth_ip = &ClientInfo { ip: Some(IpAddr::from([127, 0, 0, 4])), user_agent: None };
+        assert!(matches!(db.change_password("viewer", "password", "new password", fourth_ip).await, Err(ChangePasswordError::RateLimited(_))));
+    }
+
+    #[test]
+    fn totp_codes() {
+        // Test vectors from RFC 6238, truncated to 6 digits
+        let totp = Totp::from_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        let at = |secs| Totp::step(Utc.timestamp_opt(secs, 0).unwrap());
+        assert_eq!(totp.code(at(59)), "287082");
+        assert_eq!(totp.code(at(1111111109)), "081804");
+        assert_eq!(totp.code(at(20000000000)), "353130");
+    }
+
+    #[tokio::test]
+    async fn totp_login() {
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        db.create_user("admin", "password").await.unwrap();
+        let totp = Totp::generate();
+        let step = Totp::step(Utc::now());
+
+        assert!(matches!(db.enable_totp("admin", &totp, "000000x").await, Err(TotpError::WrongCode)));
+        let codes = db.enable_totp("admin", &totp, &totp.code(step)).await.unwrap();
+        drop(db);
+
+        let db = Users::load_path(path, Config::default()).unwrap();
+        let pending = match db.verify_user("admin", "password", &ClientInfo::default()).await.unwrap() {
+            LoginStep::Totp(cookie) => cookie,
+            LoginStep::Session(_) => panic!("logged in without 2FA")
+        };
+        // The code used to enable 2FA can't be used again
+        assert!(matches!(db.verify_totp(pending.value(), &totp.code(step), &ClientInfo::default()).await, Err(LoginError::WrongCode)));
+        assert!(matches!(db.verify_totp("admin$0", &totp.code(step + 1), &ClientInfo::default()).await, Err(LoginError::TotpExpired)));
+        db.verify_totp(pending.value(), &totp.code(step + 1), &ClientInfo::default()).await.unwrap();
+
+        // Each recovery code works once
+        db.verify_totp(pending.value(), &codes[0], &ClientInfo::default()).await.unwrap();
+        assert!(matches!(db.verify_totp(pending.value(), &codes[0], &ClientInfo::default()).await, Err(LoginError::WrongCode)));
+
+        assert!(matches!(db.disable_totp("admin", "wrong", &ClientInfo::default()).await, Err(TotpError::WrongPassword)));
+        db.disable_totp("admin", "password", &ClientInfo::default()).await.unwrap();
+        assert!(matches!(db.verify_user("admin", "password", &ClientInfo::default()).await, Ok(LoginStep::Session(_))));
+    }
+
+    #[tokio::test]
+    async fn invites() {
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        db.create_user("admin", "password").await.unwrap();
+        let invite = db.invites().create("admin", Some("friends".to_string()), Duration::days(1)).await.unwrap();
+        let expired = db.invites().create("admin", None, Duration::zero()).await.unwrap();
+        drop(db);
+
+        // Invites survive a restart
+        let db = Users::load_path(path, Config::default()).unwrap();
+        assert!(matches!(db.register_invited(&expired.token, "guest", "password", &ClientInfo::default()).await, Err(RegisterError::InvalidInvite)));
+        // A failed registration does not use up the invite
+        assert!(matches!(db.register_invited(&invite.token, "admin", "password", &ClientInfo::default()).await, Err(RegisterError::ExistingUser)));
+        db.register_invited(&invite.token, "guest", "password", &ClientInfo::default()).await.unwrap();
+        assert!(db.account_info("guest").await.unwrap().groups.contains("friends"));
+        assert!(matches!(db.register_invited(&invite.token, "guest2", "password", &ClientInfo::default()).await, Err(RegisterError::InvalidInvite)));
+        assert!(db.invites().list().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sessions_persist() {
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        let cookie = db.add_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        drop(db);
+
+        // Sessions are still valid after the server "restarts"
+        let db = Users::load_path(path, Config::default()).unwrap();
+        assert_eq!(db.validate_session(cookie.value()).await.map(|s| s.user).as_deref(), Some("admin"));
+        db.remove_session(cookie.value()).await;
+        assert!(db.validate_session(cookie.value()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn sessions_expire() {
+        let path = temp().unwrap();
+        let config = Config {
+            sessions: SessionConfig { max_age: 60, idle_timeout: 0 },
+            ..Default::default()
+        };
+        let db = Users::load_path(path, config).unwrap();
+        let cookie = db.add_user("admin", "password", &ClientInfo::default()).await.unwrap();
+
+        // Session is idle as soon as it is created
+        assert!(db.validate_session(cookie.value()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn sqlite_store() {
+        let path = temp().unwrap();
+        fs::remove_file(&path).unwrap();
+        let db = Users::new(Arc::new(SqliteStore::open(&path).unwrap()), &path, Config::default()).unwrap();
+        let client = ClientInfo { ip: Some(IpAddr::from([10, 0, 0, 1])), user_agent: Some("Test".to_string()) };
+        let cookie = db.add_user("admin", "password", &client).await.unwrap();
+        db.create_user("viewer", "password").await.unwrap();
+        assert!(matches!(db.create_user("viewer", "password").await, Err(RegisterError::ExistingUser)));
+        db.set_groups("viewer", ["editors".to_string()]).await.unwrap();
+        drop(db);
+
+        // Users and sessions survive a restart
+        let db = Users::new(Arc::new(SqliteStore::open(&path).unwrap()), &path, Config::default()).unwrap();
+        assert_eq!(db.usernames().await.collect::<Vec<_>>(), ["admin", "viewer"]);
+        assert!(db.account_info("admin").await.unwrap().groups.contains("admin"));
+        assert!(db.account_info("viewer").await.unwrap().groups.contains("editors"));
+        assert_eq!(db.validate_session(cookie.value()).await.map(|s| s.client), Some(client));
+        assert!(matches!(db.verify_user("viewer", "password", &ClientInfo::default()).await, Ok(LoginStep::Session(_))));
+
+        // Using a session is only written when flushed, but is seen before
+        let store = SqliteStore::open(&path).unwrap();
+        let created = store.get_session(cookie.value()).await.unwrap().unwrap().last_seen;
+        let last_seen = Utc.timestamp_millis_opt(created.timestamp_millis() + 1000).unwrap();
+        store.touch_session(cookie.value(), last_seen).await.unwrap();
+        assert_eq!(store.get_session(cookie.value()).await.unwrap().unwrap().last_seen, last_seen);
+        assert_eq!(SqliteStore::open(&path).unwrap().get_session(cookie.value()).await.unwrap().unwrap().last_seen, created);
+        store.flush().await.unwrap();
+        assert_eq!(SqliteStore::open(&path).unwrap().get_session(cookie.value()).await.unwrap().unwrap().last_seen, last_seen);
+
+        // Deleting a user also deletes their sessions
+        let cookie = db.change_password("viewer", "password", "new password", &ClientInfo::default()).await.unwrap();
+        db.delete_user("viewer").await.unwrap();
+        assert!(db.validate_session(cookie.value()).await.is_none());
+        assert!(db.account_info("viewer").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rehash() {
+        let path = temp().unwrap();
+        let weak = Config {
+            hash: HashConfig { memory: 4096, iterations: 3, parallelism: 1 },
+            ..Default::default()
+        };
+        let db = Users::load_path(path.clone(), weak).unwrap();
+        db.create_user("admin", "password").await.unwrap();
+        drop(db);
+        assert!(fs::read_to_string(&path).unwrap().contains("m=4096,t=3,p=1"));
+
+        // Stronger params only replace the hash after a successful login
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        assert!(db.verify_user("admin", "wrong", &ClientInfo::default()).await.is_err());
+        assert!(fs::read_to_string(&path).unwrap().contains("m=4096,t=3,p=1"));
+        db.verify_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        let hash = |file: String| file.split('"').find(|part| part.starts_with("$argon2")).unwrap().to_string();
+        let new = hash(fs::read_to_string(&path).unwrap());
+        assert!(new.contains("m=19456,t=2,p=1"));
+        // and the new hash still works, without being replaced again
+        db.verify_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        assert_eq!(hash(fs::read_to_string(&path).unwrap()), new);
+    }
+
+    #[tokio::test]
+    async fn password_policy() {
+        let path = temp().unwrap();
+        let denylist = sibling(&path, ".denylist");
+        fs::write(&denylist, "password\nletmein123\n").unwrap();
+        let config = Config {
+            password: PolicyConfig { min_length: 8, max_length: 64, denylist: Some(denylist) },
+            ..Default::default()
+        };
+        let db = Users::load_path(path, config).unwrap();
+
+        assert!(matches!(db.create_user("admin", "short").await, Err(RegisterError::WeakPassword(PolicyError::TooShort(8)))));
+        assert!(matches!(db.create_user("admin", &"a".repeat(65)).await, Err(RegisterError::WeakPassword(PolicyError::TooLong(64)))));
+        assert!(matches!(db.create_user("admin", "LetMeIn123").await, Err(RegisterError::WeakPassword(PolicyError::Denylisted))));
+
+        // The composed and decomposed forms of "é" are the same password
+        db.create_user("admin", "mot de passe \u{e9}t\u{e9}").await.unwrap();
+        db.verify_user("admin", "mot de passe e\u{301}te\u{301}", &ClientInfo::default()).await.unwrap();
+        assert!(matches!(db.change_password("admin", "mot de passe \u{e9}t\u{e9}", "password", &ClientInfo::default()).await, Err(ChangePasswordError::WeakPassword(_))));
+    }
+
+    #[test]
+    fn next_is_same_origin() {
+        use crate::auth::{safe_next, with_next};
+
+        assert_eq!(safe_next(Some("/osts/albums?page=2")), Some("/osts/albums?page=2"));
+        for next in ["https://evil.com", "//evil.com", "/\\evil.com", "evil.com", "javascript:alert(1)", ""] {
+            assert_eq!(safe_next(Some(next)), None, "{next:?}");
+        }
+        assert_eq!(with_next("/login", Some("//evil.com")), "/login");
+        assert_eq!(with_next("/login", Some("/a b")), "/login");
+        assert_eq!(with_next("/login", Some("/games/x?y=1")), "/login?next=%2Fgames%2Fx%3Fy%3D1");
+    }
+
+    #[tokio::test]
+    async fn session_list() {
+        let path = temp().unwrap();
+        let laptop = ClientInfo { ip: Some(IpAddr::from([192, 168, 0, 2])), user_agent: Some("Laptop".to_string()) };
+        let phone = ClientInfo { ip: Some(IpAddr::from([192, 168, 0, 3])), user_agent: Some("Phone".to_string()) };
+
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        let laptop_cookie = db.add_user("admin", "password", &laptop).await.unwrap();
+        db.add_user("viewer", "password", &ClientInfo::default()).await.unwrap();
+        db.verify_user("admin", "password", &phone).await.unwrap();
+        db.verify_user("admin", "password", &phone).await.unwrap();
+        drop(db);
+
+        // The clients are saved with the sessions
+        let db = Users::load_path(path, Config::default()).unwrap();
+        let sessions = db.user_sessions("admin").await;
+        assert_eq!(sessions.len(), 3);
+        assert_eq!(sessions.iter().filter(|session| session.client == phone).count(), 2);
+
+        // Can't revoke another user's session
+        let viewer_session = &db.user_sessions("viewer").await[0];
+        assert!(db.revoke_session("admin", &viewer_session.id()).await.is_none());
+        assert!(db.validate_session(&viewer_session.uuid).await.is_some());
+
+        let phone_session = sessions.iter().find(|session| session.client == phone).unwrap();
+        assert_eq!(db.revoke_session("admin", &phone_session.id()).await.map(|session| session.uuid), Some(phone_session.uuid.clone()));
+        assert_eq!(db.user_sessions("admin").await.len(), 2);
+
+        db.logout_others("admin", laptop_cookie.value()).await;
+        let sessions = db.user_sessions("admin").await;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].client, laptop);
+        assert_eq!(db.user_sessions("viewer").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn api_tokens() {
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        db.add_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        db.add_user("viewer", "password", &ClientInfo::default()).await.unwrap();
+
+        let (info, token) = db.create_token("viewer", "script", TokenScope::User, Some(Duration::days(1))).await.unwrap();
+        assert_eq!(db.validate_token(&token).await, Some(("viewer".to_string(), TokenScope::User)));
+        assert!(db.create_token("nobody", "script", TokenScope::User, None).await.is_err());
+
+        // The secret must match, and belong to the user
+        let (_, secret) = token.split_once('.').unwrap();
+        assert_eq!(db.validate_token(&format!("viewer.{secret}x")).await, None);
+        assert_eq!(db.validate_token(&format!("admin.{secret}")).await, None);
+        assert_eq!(db.validate_token("viewer").await, None);
+
+        // Expired tokens are not valid
+        let (_, expired) = db.create_token("viewer", "old", TokenScope::User, Some(Duration::seconds(-1))).await.unwrap();
+        assert_eq!(db.validate_token(&expired).await, None);
+
+        // Tokens are saved, but not while the user is disabled
+        drop(db);
+        let db = Users::load_path(path, Config::default()).unwrap();
+        assert!(db.validate_token(&token).await.is_some());
+        db.set_disabled("viewer", true).await.unwrap();
+        assert_eq!(db.validate_token(&token).await, None);
+        db.set_disabled("viewer", false).await.unwrap();
+
+        assert!(db.revoke_token("admin", &info.id).await.is_err());
+        db.revoke_token("viewer", &info.id).await.unwrap();
+        assert_eq!(db.validate_token(&token).await, None);
+        assert!(db.revoke_token("viewer", &info.id).await.is_err());
+    }
+
+    #[test]
+    fn csrf_tokens() {
+        use rocket::http::ContentType;
+        use crate::auth::csrf::sent_token;
+
+        assert_eq!(sent_token(&ContentType::Form, b"csrf_token=token&username=admin"), Some(&b"token"[..]));
+        assert_eq!(sent_token(&ContentType::Form, b"username=admin&csrf_token_x=token"), None);
+        let multipart = ContentType::new("multipart", "form-data").with_params(("boundary", "X"));
+        assert_eq!(
+            sent_token(&multipart, b"--X\r\nContent-Disposition: form-data; name=\"csrf_token\"\r\n\r\ntoken\r\n--X--\r\n"),
+            Some(&b"token"[..])
+        );
+        assert_eq!(sent_token(&ContentType::JSON, b"csrf_token=token"), None);
+    }
+
+    #[tokio::test]
+    async fn profile_pictures() {
+        use crate::auth::pfp::{self, parse_identify, sniff_format};
+
+        assert_eq!(sniff_format(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"), Some("PNG"));
+        assert_eq!(sniff_format(b"GIF89a\x01\0"), Some("GIF"));
+        assert_eq!(sniff_format(b"RIFF\x24\0\0\0WEBPVP8 "), Some("WEBP"));
+        assert_eq!(sniff_format(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff_format(b"<svg xmlns="), None);
+        assert_eq!(sniff_format(b"%!PS"), None);
+
+        assert_eq!(parse_identify("PNG 640 480\n"), Some(("PNG".to_string(), 640, 480)));
+        assert_eq!(parse_identify("GIF 10 10\nGIF 10 10\n"), Some(("GIF".to_string(), 10, 10)));
+        assert_eq!(parse_identify(""), None);
+        assert_eq!(parse_identify("PNG wide 480"), None);
+
+        let path = temp().unwrap();
+        let db = Users::load_path(path.clone(), Config::default()).unwrap();
+        db.add_user("admin", "password", &ClientInfo::default()).await.unwrap();
+        assert_eq!(db.set_pfp("admin", Some("first".to_string())).await.unwrap(), None);
+        assert_eq!(db.set_pfp("admin", Some("second".to_string())).await.unwrap(), Some("first".to_string()));
+        assert!(db.set_pfp("nobody", None).await.is_err());
+        drop(db);
+
+        let db = Users::load_path(path, Config::default()).unwrap();
+        let id = db.account_info("admin").await.unwrap().pfp.unwrap();
+        assert_eq!(id, "second");
+        assert!(pfp::path("admin", &id, pfp::SMALL).ends_with("admin/second-64.png"));
+    }
+
+    #[tokio::test]
+    async fn audit_log() {
+        use crate::auth::audit::{AuditLog, AuditEntry, AuditEvent, AuditFilter};
+
+        let path = temp().unwrap();
+        let log = AuditLog::new(path.clone());
+        let entry = |actor: &str, event| AuditEntry {
+            time: Utc::now(),
+            actor: Some(actor.to_string()),
+            ip: Some(IpAddr::from([127, 0, 0, 1])),
+            event
+        };
+
+        log.record(entry("admin", AuditEvent::Login)).await;
+        for i in 0..AuditLog::PAGE_SIZE {
+            log.record(entry("admin", AuditEvent::TokenCreated { name: format!("token {i}") })).await;
+        }
+        log.record(entry("admin", AuditEvent::UserDisabled { username: "viewer".to_string() })).await;
+        log.record(entry("viewer", AuditEvent::LoginFailed { username: "viewer".to_string(), reason: "Disabled".to_string() })).await;
+
+        // Every line is a JSON object with the event's name
+        let file = fs::read_to_string(&path).unwrap();
+        assert_eq!(file.lines().count(), AuditLog::PAGE_SIZE + 3);
+        assert!(file.lines().next().unwrap().contains(r#""event":"login""#));
+        // Longer than the blocks that pages are read in
+        assert!(file.len() > 4 * 1024);
+
+        // Newest first, in pages
+        let all = AuditFilter::default();
+        let (entries, more) = log.page(&all, 0).await.unwrap();
+        assert_eq!(entries.len(), AuditLog::PAGE_SIZE);
+        assert!(more);
+        assert_eq!(entries[0].event.name(), "login_failed");
+        let (entries, more) = log.page(&all, 1).await.unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(!more);
+        assert_eq!(entries.last().unwrap().event, AuditEvent::Login);
+
+        // Filtering by user matches the actor or the target
+        let viewer = AuditFilter { event: None, user: Some("viewer".to_string()) };
+        let (entries, _) = log.page(&viewer, 0).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].event.details(), "");
+        assert_eq!(AuditEvent::SessionRevoked { user_agent: None }.details(), "Session of an unknown browser");
+        let login = AuditFilter { event: Some("login".to_string()), user: Some(String::new()) };
+        let (entries, more) = log.page(&login, 0).await.unwrap();
+        assert_eq!((entries.len(), more), (1, false));
+
+        // A line that was cut short is skipped
+        fs::write(&path, format!("{file}{{\"time\":")).unwrap();
+        assert_eq!(log.page(&login, 0).await.unwrap().0.len(), 1);
+    }
 }
@@ -0,0 +1,145 @@
+use std::{
+    io,
+    collections::HashMap,
+    path::PathBuf,
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use async_std::sync::Mutex as AsyncMutex;
+use serde::{Serialize, Deserialize};
+use crate::do_while;
+use super::helpers;
+
+
+/// A single-use link that lets someone register an account without an admin choosing their password.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invite {
+    pub token: String,
+    /// The admin that created the invite.
+    pub created_by: String,
+    pub expires: DateTime<Utc>,
+    /// The invited user is added to this group when they register.
+    pub group: Option<String>,
+}
+impl Invite {
+    #[inline]
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires
+    }
+
+    /// Where the invited user can register.
+    pub fn link(&self) -> String {
+        format!("/register?invite={}", self.token)
+    }
+}
+
+/// The unused invites, persisted to a file.
+///
+/// File format: each line is an [`Invite`] in *JSON* format.
+/// The file is rewritten whenever an invite is added or removed.
+#[derive(Debug)]
+pub struct Invites {
+    path: PathBuf,
+    /// `HashMap<Token, Invite>`
+    map: AsyncMutex<HashMap<String, Invite>>,
+}
+impl Invites {
+    /// Load existing [`Invites`] from a file, dropping the ones that have already expired.
+    pub fn load_path(path: PathBuf) -> io::Result<Self> {
+        let now = Utc::now();
+
+        let file = match std::fs::read_to_string(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error)
+        };
+        let map = file.lines()
+            .filter_map(|line| serde_json::from_str::<Invite>(line).ok())
+            .filter(|invite| !invite.is_expired(now))
+            .map(|invite| (invite.token.clone(), invite))
+            .collect::<HashMap<_, _>>();
+
+        Ok(Self { path, map: AsyncMutex::new(map) })
+    }
+
+    /// Creates an invite that expires after **lifetime**.
+    pub async fn create(&self, created_by: &str, group: Option<String>, lifetime: Duration) -> io::Result<Invite> {
+        let map = &mut *self.map.lock().await;
+
+        let mut token: String;
+        // Ensure the token is unique
+        do_while!{ do {
+            token = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect();
+        } while map.contains_key(&token) };
+
+        let invite = Invite {
+            token: token.clone(),
+            created_by: created_by.to_string(),
+            expires: Utc::now() + lifetime,
+            group,
+        };
+        map.insert(token.clone(), invite.clone());
+
+        if let Err(error) = self.save(map).await {
+            map.remove(&token);
+            return Err(error)
+        }
+        Ok(invite)
+    }
+
+    /// Returns the invite if it exists and has not expired.
+    pub async fn get(&self, token: &str) -> Option<Invite> {
+        self.map.lock().await
+            .get(token)
+            .filter(|invite| !invite.is_expired(Utc::now()))
+            .cloned()
+    }
+
+    /// Removes the invite so it can't be used again, and returns it if it was still valid.
+    pub async fn take(&self, token: &str) -> Option<Invite> {
+        let map = &mut *self.map.lock().await;
+        let invite = map.remove(token)?;
+        self.save_logged(map).await;
+        (!invite.is_expired(Utc::now())).then_some(invite)
+    }
+
+    /// Puts back an invite that was [taken](Self::take()), but could not be used (e.g. the username was taken).
+    pub async fn restore(&self, invite: Invite) {
+        let map = &mut *self.map.lock().await;
+        map.insert(invite.token.clone(), invite);
+        self.save_logged(map).await;
+    }
+
+    /// All the invites that have not expired, sorted by expiry.
+    pub async fn list(&self) -> Vec<Invite> {
+        let now = Utc::now();
+        let mut invites = self.map.lock().await
+            .values()
+            .filter(|invite| !invite.is_expired(now))
+            .cloned()
+            .collect::<Vec<_>>();
+        invites.sort_by_key(|invite| invite.expires);
+        invites
+    }
+
+    /// Rewrites the invites file, also dropping the expired invites.
+    async fn save(&self, map: &mut HashMap<String, Invite>) -> io::Result<()> {
+        let now = Utc::now();
+        map.retain(|_, invite| !invite.is_expired(now));
+
+        let file = map.values()
+            .map(|invite| serde_json::to_string(invite).expect("Invite can always be serialized") + "\n")
+            .collect::<String>();
+        helpers::write_atomic(&self.path, file).await
+    }
+    /// Like [`Self::save()`], for when the change should still be kept in memory if it can't be written.
+    async fn save_logged(&self, map: &mut HashMap<String, Invite>) {
+        if let Err(error) = self.save(map).await {
+            eprintln!("Could not save invites to {:?}: {error}", self.path);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+};
+use chrono::{DateTime, Duration, Utc};
+use async_std::sync::Mutex as AsyncMutex;
+use serde::Deserialize;
+
+
+/// Throttling of failed logins, in seconds.
+/// Set in the `auth.rate_limit` table of the server's config (e.g. `Rocket.toml`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Number of failed attempts allowed before the client has to wait between attempts.
+    pub free_attempts: u32,
+    /// Wait after the first throttled attempt. Doubles with each further failed attempt.
+    pub base_delay: u64,
+    /// After this many failed attempts the client is locked out for [`Self::lockout`] seconds.
+    pub lockout_after: u32,
+    /// How long a lockout lasts. Failed attempts are also forgotten if there are none for this long.
+    pub lockout: u64,
+}
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            free_attempts: 3,
+            base_delay: 1,
+            lockout_after: 10,
+            lockout: 60 * 60, // 1 hour
+        }
+    }
+}
+
+/// Something that failed logins are counted for.
+/// Both the client's IP and the username they tried are tracked,
+/// so an attacker can't bypass the limit by changing one of them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Ip(IpAddr),
+    User(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: DateTime<Utc>,
+}
+
+/// Keeps track of failed logins in memory, and decides when a client has to wait before trying again.
+#[derive(Debug)]
+pub struct LoginLimiter {
+    config: RateLimitConfig,
+    failures: AsyncMutex<HashMap<Key, Failures>>,
+}
+impl LoginLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self { config, failures: AsyncMutex::new(HashMap::new()) }
+    }
+
+    /// Returns how many seconds the client must wait before it can try logging in again as **username**,
+    /// or [`None`] if it can try now.
+    ///
+    /// An attempt that may go ahead is counted as failed right away, so that parallel attempts can't all pass before the first one fails.
+    /// It is taken back by [`Self::succeed()`] or [`Self::release()`].
+    /// **username** must only be given for existing users, so that made up usernames don't fill the memory.
+    pub async fn check(&self, ip: Option<IpAddr>, username: Option<&str>) -> Option<u64> {
+        let failures = &mut *self.failures.lock().await;
+        let now = Utc::now();
+
+        // Forget clients that stopped trying
+        let forget = Duration::seconds(self.config.lockout as i64);
+        failures.retain(|_, failures| now < failures.last + forget);
+
+        let wait = Self::keys(ip, username)
+            .filter_map(|key| failures.get(&key))
+            .filter_map(|failures| {
+                let wait = (failures.last + self.delay(failures.count) - now).num_seconds();
+                (wait > 0).then_some(wait as u64)
+            })
+            .max();
+
+        if wait.is_none() {
+            for key in Self::keys(ip, username) {
+                let entry = failures.entry(key).or_insert(Failures { count: 0, last: now });
+                entry.count += 1;
+                entry.last = now;
+            }
+        }
+        wait
+    }
+
+    /// Forgets the failed attempts of **username** after a successful login.
+    /// Only the attempt of this login is taken back for the **ip**,
+    /// so that logging in to one's own account doesn't reset the throttling of guesses at other accounts.
+    pub async fn succeed(&self, ip: Option<IpAddr>, username: &str) {
+        let failures = &mut *self.failures.lock().await;
+        failures.remove(&Key::User(username.to_string()));
+        if let Some(ip) = ip {
+            Self::take_back(failures, Key::Ip(ip));
+        }
+    }
+
+    /// Takes back the attempt counted by [`Self::check()`], when it neither failed nor succeeded,
+    /// e.g. the password was right but the 2FA code is still needed.
+    pub async fn release(&self, ip: Option<IpAddr>, username: &str) {
+        let failures = &mut *self.failures.lock().await;
+        for key in Self::keys(ip, Some(username)) {
+            Self::take_back(failures, key);
+        }
+    }
+
+    fn take_back(failures: &mut HashMap<Key, Failures>, key: Key) {
+        if let Some(entry) = failures.get_mut(&key) {
+            entry.count = entry.count.saturating_sub(1);
+            if entry.count == 0 {
+                failures.remove(&key);
+            }
+        }
+    }
+
+    /// How long to wait after the last of **count** failed attempts.
+    fn delay(&self, count: u32) -> Duration {
+        let config = &self.config;
+        if count >= config.lockout_after {
+            Duration::seconds(config.lockout as i64)
+        } else if count >= config.free_attempts {
+            // Exponential backoff, which can't exceed the lockout
+            let exp = (count - config.free_attempts).min(31);
+            let delay = config.base_delay.saturating_mul(1 << exp).min(config.lockout);
+            Duration::seconds(delay as i64)
+        } else {
+            Duration::zero()
+        }
+    }
+
+    fn keys(ip: Option<IpAddr>, username: Option<&str>) -> impl Iterator<Item = Key> {
+        ip.map(Key::Ip)
+            .into_iter()
+            .chain(username.map(|username| Key::User(username.to_string())))
+    }
+}
@@ -1,4 +1,14 @@
 pub mod db;
+pub mod sessions;
+pub mod limiter;
+pub mod totp;
+pub mod invites;
+pub mod tokens;
+pub mod csrf;
+pub mod pfp;
+pub mod audit;
+pub mod store;
+pub mod password;
 mod helpers;
 
 use rocket::{
@@ -9,35 +19,118 @@ use rocket::{
         content::RawHtml as Html
     },
     http::{Cookie, CookieJar},
-    outcome::IntoOutcome
+    outcome::{IntoOutcome, try_outcome},
+    fairing::{Fairing, Info, Kind}
 };
+use std::{collections::BTreeSet, marker::PhantomData};
+use tokens::TokenScope;
+use csrf::{Csrf, CsrfToken};
+use audit::{Audit, AuditEvent};
+use serde::Deserialize;
 use super::*;
 
 pub static SESSION_COOKIE: &str = "session_uuid";
+/// Held by a client between entering the password and the 2FA code (see [`db::LoginStep::Totp`]).
+pub static TOTP_PENDING_COOKIE: &str = "totp_pending";
 
 
-/// Request Guard requiring the the request to come from an admin session
-struct Admin;
+/// Prunes expired sessions every [`Sessions::PRUNE_INTERVAL`](sessions::Sessions::PRUNE_INTERVAL),
+/// and saves the sessions' pending changes when the server shuts down.
+pub struct SessionsFairing;
 #[rocket::async_trait]
-impl<'r> FromRequest<'r> for Admin {
+impl Fairing for SessionsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Sessions pruner",
+            kind: Kind::Liftoff | Kind::Shutdown
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &rocket::Rocket<rocket::Orbit>) {
+        let sessions = match rocket.state::<db::Users>() {
+            Some(users) => users.sessions(),
+            None => return
+        };
+
+        rocket::tokio::spawn(async move {
+            let mut interval = rocket::tokio::time::interval(sessions::Sessions::PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                sessions.prune().await;
+            }
+        });
+    }
+
+    async fn on_shutdown(&self, rocket: &rocket::Rocket<rocket::Orbit>) {
+        if let Some(users) = rocket.state::<db::Users>() {
+            users.sessions().flush().await;
+        }
+    }
+}
+
+
+/// Configuration of the authentication system.
+/// Read from the `auth` table of the server's config (e.g. `Rocket.toml`).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub sessions: sessions::SessionConfig,
+    pub rate_limit: limiter::RateLimitConfig,
+    pub totp: totp::TotpConfig,
+    pub store: store::StoreConfig,
+    pub hash: password::HashConfig,
+    pub password: password::PolicyConfig,
+}
+
+
+/// Request Guard for a valid session of any user.
+/// 
+/// When the session has expired, its cookie is removed from the client.
+/// Otherwise the cookie is sent again so that its expiry is pushed forward.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for sessions::Session {
     type Error = ();
 
     /// Returns error when can't access the server's sessions
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let session = match req.cookies().get_private(SESSION_COOKIE) {
-            Some(session) => session,
-            None => return Outcome::Forward(())
-        };
         let users = match req.rocket().state::<db::Users>() {
             Some(users) => users,
             None => return Outcome::Failure((Status::InternalServerError, ()))
         };
 
-        // See if the user id for this session is the admin
-        users.validate_session(session.value()).await
-            .and_then(|user|
-                (user == db::ADMIN_USR_ID).then_some(Self))
-            .or_forward(())
+        // Only validate the session once per request, even if multiple guards need it.
+        let session = req.local_cache_async(async {
+            let cookie = req.cookies().get_private(SESSION_COOKIE)?;
+
+            match users.validate_session(cookie.value()).await {
+                Some(session) => {
+                    req.cookies().add_private(users.session_cookie(&session));
+                    Some(session)
+                },
+                None => {
+                    // Remove user's session_uuid if it is invalid or expired
+                    req.cookies().remove_private(Cookie::named(SESSION_COOKIE));
+                    None
+                }
+            }
+        }).await;
+
+        session.clone().or_forward(())
+    }
+}
+
+/// Request Guard describing the client, recorded in the sessions it logs in to. Never fails.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for sessions::ClientInfo {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user_agent = req.headers().get_one("User-Agent")
+            .map(|agent| agent.chars().take(Self::MAX_USER_AGENT_LEN).collect());
+        Outcome::Success(Self {
+            ip: req.client_ip(),
+            user_agent
+        })
     }
 }
 
@@ -45,33 +138,130 @@ impl<'r> FromRequest<'r> for Admin {
 pub struct User {
     pub name: String,
     pub pfp_path: Option<PathBuf>,
-    // pub group: String,
+    pub groups: BTreeSet<String>,
+    /// The user is an admin, but can't use their admin rights until they enable 2FA
+    /// (see [`TotpConfig::require_for_admin`](totp::TotpConfig::require_for_admin)).
+    pub needs_totp: bool,
+}
+impl User {
+    #[inline]
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.contains(group)
+    }
+    #[inline]
+    pub fn is_admin(&self) -> bool {
+        self.in_group(db::ADMIN_GROUP)
+    }
 }
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for User {
     type Error = ();
 
+    /// Uses the session of the client, or a personal access token in the `Authorization: Bearer` header if it has none.
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let session = match req.cookies().get_private(SESSION_COOKIE) {
-            Some(session) => session,
-            None => return Outcome::Forward(())
-        };
-        let users = match req.rocket().state::<db::Users>() {
-            Some(users) => users,
-            None => return Outcome::Failure((Status::InternalServerError, ()))
+        let users = try_outcome!(req.guard::<&State<db::Users>>().await);
+
+        let (name, scope) = match req.guard::<sessions::Session>().await {
+            Outcome::Success(session) => (session.user, TokenScope::Admin),
+            Outcome::Failure(failure) => return Outcome::Failure(failure),
+            Outcome::Forward(()) => match bearer_token(req) {
+                Some(token) => try_outcome!(users.validate_token(token).await.or_forward(())),
+                None => return Outcome::Forward(())
+            }
         };
 
-        match users.validate_session(session.value()).await {
-            Some(username) =>
+        // The user could have been removed or disabled after the session was created
+        match users.account_info(&name).await {
+            Some(info) if info.disabled => Outcome::Forward(()),
+            Some(mut info) => {
+                let needs_totp = users.needs_totp(&info);
+                if needs_totp || scope == TokenScope::User {
+                    info.groups.remove(db::ADMIN_GROUP);
+                }
                 Outcome::Success(Self {
-                    name: username,
-                    pfp_path: None // TODO: get pfp path from users-info file
-                }),
-            None => return Outcome::Forward(())
+                    pfp_path: info.pfp.map(|id| pfp::path(&name, &id, pfp::SMALL)),
+                    name,
+                    groups: info.groups,
+                    needs_totp
+                })
+            },
+            None => Outcome::Forward(())
         }
     }
 }
 
+/// A named group of users that can be required with [`RequireRole`].
+/// 
+/// ```no_run
+/// struct Editor;
+/// impl Role for Editor {
+///     const GROUP: &'static str = "editor";
+/// }
+/// 
+/// #[post("/edit")]
+/// fn edit(editor: RequireRole<Editor>) { }
+/// ```
+pub trait Role: Send + Sync + 'static {
+    /// The name of the group, as stored in the users' [`AccountInfo`](db::AccountInfo).
+    const GROUP: &'static str;
+}
+/// Request Guard requiring the request to come from a user in the group of **R**.
+/// Forwards the request otherwise, so a lower ranked route can handle users without the role.
+pub struct RequireRole<R: Role> {
+    pub user: User,
+    _role: PhantomData<R>
+}
+#[rocket::async_trait]
+impl<'r, R: Role> FromRequest<'r> for RequireRole<R> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = try_outcome!(req.guard::<User>().await);
+
+        user.in_group(R::GROUP)
+            .then_some(Self { user, _role: PhantomData })
+            .or_forward(())
+    }
+}
+
+/// The [`Role`] of users with admin rights.
+pub struct AdminRole;
+impl Role for AdminRole {
+    const GROUP: &'static str = db::ADMIN_GROUP;
+}
+/// Request Guard requiring the the request to come from an admin session
+pub type Admin = RequireRole<AdminRole>;
+
+
+/// Validates the `next` query parameter of the login pages, which is where the user returns after logging in.
+/// Only paths on this server are allowed, so that a link can't send the user to another site (e.g. `//evil.com`).
+pub fn safe_next(next: Option<&str>) -> Option<&str> {
+    next.filter(|next| next.starts_with('/')
+        && !next.starts_with("//")
+        && !next.contains('\\')
+        && rocket::http::uri::Origin::parse(next).is_ok()
+    )
+}
+
+/// **path** with the `next` query parameter, if there is a valid one (see [`safe_next()`]).
+pub fn with_next(path: &str, next: Option<&str>) -> String {
+    match safe_next(next) {
+        Some(next) => format!("{path}?next={}", rocket::http::RawStr::new(next).percent_encode()),
+        None => path.to_string()
+    }
+}
+
+/// Sends the user to log in, and then return to **uri**.
+pub fn login_redirect(uri: &rocket::http::uri::Origin) -> Redirect {
+    Redirect::to(with_next("/login", Some(&uri.to_string())))
+}
+
+/// The personal access token in the `Authorization: Bearer` header of the request.
+fn bearer_token<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    req.headers().get_one("Authorization")
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+}
+
 
 #[derive(Debug, FromForm)]
 struct Creds<'a> {
@@ -82,6 +272,7 @@ struct Creds<'a> {
 
 pub mod login {
     use rocket::response::Flash;
+    use sessions::ClientInfo;
     use super::*;
 
 
@@ -105,36 +296,96 @@ pub mod login {
     //         .and_then(|file| Ok(Html(file)))
     //     )
     // }
-    #[get("/")]
-    async fn index(jar: &CookieJar<'_>, users: &State<db::Users>, error: Option<FlashMessage<'_>>) -> Result<Html<TextStream![String]>, Redirect> {
+    /// **next** is where the user returns after logging in (see [`safe_next()`]).
+    #[get("/?<next>")]
+    async fn index(jar: &CookieJar<'_>, users: &State<db::Users>, csrf: CsrfToken, next: Option<&str>, error: Option<FlashMessage<'_>>) -> Result<Html<TextStream![String]>, Redirect> {
         // If no admin user exists, redirect to create one
         if !helpers::admin_user_exists(users).await {
             return Err(Redirect::to("/admin-register"))
         }
 
-        // If user is trying to log in but is already logged in, redirect to home page
+        // If user is trying to log in but is already logged in, send them where they were going
         if let Some(cookie) = jar.get_private(SESSION_COOKIE) {
             if let Some(_) = users.validate_session(cookie.value()).await {
-                return Err(Redirect::to("/"))
+                return Err(Redirect::to(safe_next(next).unwrap_or("/").to_string()))
             }
             // Remove user's session_uuid if it is invalid
             jar.remove_private(Cookie::named(SESSION_COOKIE));
         }
 
-        Ok(Html(TextStream(crate::components::render::<crate::components::authenticate::Login>(error.into()))))
+        Ok(Html(TextStream(crate::components::render::<crate::components::authenticate::Login>(
+            crate::components::authenticate::LoginProps::new(error, next, csrf)
+        ))))
+    }
+
+    #[post("/?<next>", data="<creds>")]
+    async fn login(_csrf: Csrf, jar: &CookieJar<'_>, users: &State<db::Users>, audit: Audit<'_>, client: ClientInfo, next: Option<&str>, creds: Form<Creds<'_>>) -> Result<Redirect, Flash<Redirect>> {
+        let step = match users.verify_user(creds.username, creds.password, &client).await {
+            Ok(step) => step,
+            Err(error) => {
+                audit.record(None, AuditEvent::LoginFailed { username: creds.username.to_string(), reason: error.to_string() }).await;
+                return Err(Flash::error(Redirect::to(with_next("/login", next)), error.to_string()))
+            }
+        };
+        match step {
+            db::LoginStep::Session(cookie) => {
+                audit.record(Some(creds.username), AuditEvent::Login).await;
+                jar.add_private(cookie)
+            },
+            db::LoginStep::Totp(cookie) => {
+                jar.add_private(cookie);
+                return Ok(Redirect::to(with_next("/login/totp", next)))
+            }
+        }
+        Ok(Redirect::to(safe_next(next).unwrap_or("/").to_string()))
+    }
+
+    #[get("/totp?<next>")]
+    async fn totp_index(jar: &CookieJar<'_>, csrf: CsrfToken, next: Option<&str>, error: Option<FlashMessage<'_>>) -> Result<Html<TextStream![String]>, Redirect> {
+        // The password must be entered first
+        if jar.get_private(TOTP_PENDING_COOKIE).is_none() {
+            return Err(Redirect::to(with_next("/login", next)))
+        }
+        Ok(Html(TextStream(crate::components::render::<crate::components::authenticate::TotpLogin>(
+            crate::components::authenticate::LoginProps::new(error, next, csrf)
+        ))))
+    }
+
+    #[derive(Debug, FromForm)]
+    struct TotpCode<'a> {
+        code: &'a str
     }
 
-    #[post("/", data="<creds>")]
-    async fn login(jar: &CookieJar<'_>, users: &State<db::Users>, creds: Form<Creds<'_>>) -> Result<Redirect, Flash<Redirect>> {
-        let cookie = users.verify_user(creds.username, creds.password).await
-            .map_err(|error| Flash::error(Redirect::to("/login"), error.to_string()))?;
+    #[post("/totp?<next>", data = "<form>")]
+    async fn totp(_csrf: Csrf, jar: &CookieJar<'_>, users: &State<db::Users>, audit: Audit<'_>, client: ClientInfo, next: Option<&str>, form: Form<TotpCode<'_>>) -> Result<Redirect, Flash<Redirect>> {
+        let pending = jar.get_private(TOTP_PENDING_COOKIE)
+            .ok_or_else(|| Flash::error(Redirect::to(with_next("/login", next)), db::LoginError::TotpExpired.to_string()))?;
+        let username = db::Users::parse_totp_pending(pending.value());
+
+        let result = users.verify_totp(pending.value(), form.code, &client).await;
+        match (&result, username) {
+            (Ok(_), Some(username)) => audit.record(Some(username), AuditEvent::Login).await,
+            (Err(error), Some(username)) => audit.record(None, AuditEvent::LoginFailed { username: username.to_string(), reason: error.to_string() }).await,
+            (_, None) => {}
+        }
+
+        let cookie = match result {
+            Ok(cookie) => cookie,
+            Err(error @ (db::LoginError::WrongCode | db::LoginError::RateLimited(_))) =>
+                return Err(Flash::error(Redirect::to(with_next("/login/totp", next)), error.to_string())),
+            // Have to start over
+            Err(error) => {
+                jar.remove_private(Cookie::named(TOTP_PENDING_COOKIE));
+                return Err(Flash::error(Redirect::to(with_next("/login", next)), error.to_string()))
+            }
+        };
+        jar.remove_private(Cookie::named(TOTP_PENDING_COOKIE));
         jar.add_private(cookie);
-        // TODO: read the "prev_page" flash cookie and redirect there
-        Ok(Redirect::to("/"))
+        Ok(Redirect::to(safe_next(next).unwrap_or("/").to_string()))
     }
 
     pub fn routes() -> Vec<Route> {
-        routes![index, login]
+        routes![index, login, totp_index, totp]
     }
 }
 
@@ -143,8 +394,11 @@ pub mod logout {
     use super::*;
 
     #[get("/")]
-    async fn logout(jar: &CookieJar<'_>, users: &State<db::Users>) -> Redirect {
+    async fn logout(jar: &CookieJar<'_>, users: &State<db::Users>, audit: Audit<'_>) -> Redirect {
         if let Some(cookie) = jar.get_private(SESSION_COOKIE) {
+            if let Some(session) = users.validate_session(cookie.value()).await {
+                audit.record(Some(&session.user), AuditEvent::Logout).await;
+            }
             users.remove_session(cookie.value()).await;
             jar.remove_private(Cookie::named(SESSION_COOKIE));
         }
@@ -168,7 +422,7 @@ pub mod register {
         if !helpers::admin_user_exists(users).await {
             Err(Redirect::to("/admin-register"))
         } else {
-            Ok(Forbidden(Some("Please contact admin to create an account")))
+            Ok(Forbidden(Some("Please ask an admin for an invite link to create an account")))
         }
     }
 
@@ -176,27 +430,66 @@ pub mod register {
     // async fn index_admin(_admin: Admin) -> io::Result<Html<File>> {
     //     Ok(Html(File::open(RENDER_ROOT.join("register.html")).await?))
     // }
-    #[get("/", rank = 1)]
-    async fn index_admin(_admin: Admin, error: Option<FlashMessage<'_>>) -> Html<TextStream![String]> {
-        Html(TextStream(crate::components::render::<crate::components::authenticate::Register>(error.into())))
+    #[get("/?<next>", rank = 1)]
+    async fn index_admin(_admin: Admin, csrf: CsrfToken, next: Option<&str>, error: Option<FlashMessage<'_>>) -> Html<TextStream![String]> {
+        let mut props = crate::components::authenticate::RegisterProps::new(error, csrf);
+        props.next = safe_next(next).map(str::to_string);
+        Html(TextStream(crate::components::render::<crate::components::authenticate::Register>(props)))
+    }
+
+    /// Page for someone with an invite link to choose their username and password.
+    #[get("/?<invite>", rank = 0)]
+    async fn index_invite(users: &State<db::Users>, csrf: CsrfToken, invite: &str, error: Option<FlashMessage<'_>>) -> Result<Html<TextStream![String]>, Forbidden<&'static str>> {
+        if users.invites().get(invite).await.is_none() {
+            return Err(Forbidden(Some("This invite link is invalid or has expired")))
+        }
+        Ok(Html(TextStream(crate::components::render::<crate::components::authenticate::Register>(
+            crate::components::authenticate::RegisterProps {
+                error: error.map(|error| error.message().to_string()).unwrap_or_default(),
+                invite: Some(invite.to_string()),
+                next: None,
+                csrf: csrf.0
+            }
+        ))))
+    }
+
+    #[derive(Debug, FromForm)]
+    struct InviteCreds<'a> {
+        invite: &'a str,
+        username: &'a str,
+        password: &'a str
+    }
+
+    #[post("/invite", data="<creds>")]
+    async fn register_invited(_csrf: Csrf, jar: &CookieJar<'_>, users: &State<db::Users>, audit: Audit<'_>, client: sessions::ClientInfo, creds: Form<InviteCreds<'_>>) -> Result<Redirect, Flash<Redirect>> {
+        let cookie = users.register_invited(creds.invite, creds.username, creds.password, &client).await
+            .map_err(|error| Flash::error(Redirect::to(format!("/register?invite={}", rocket::http::RawStr::new(creds.invite).percent_encode())), error.to_string()))?;
+        audit.record(Some(creds.username), AuditEvent::Register { username: creds.username.to_string(), invited: true }).await;
+        jar.add_private(cookie);
+        Ok(Redirect::to("/"))
     }
 
-    #[post("/", data="<creds>")]
+    #[post("/?<next>", data="<creds>")]
+    #[allow(clippy::too_many_arguments)] // Request guards
     async fn register(
+        _csrf: Csrf,
         jar: &CookieJar<'_>,
         users: &State<db::Users>,
-        _admin: Admin,
+        audit: Audit<'_>,
+        admin: Admin,
+        client: sessions::ClientInfo,
+        next: Option<&str>,
         creds: Form<Creds<'_>>
     ) -> Result<Redirect, Flash<Redirect>> {
-        let cookie = users.add_user(creds.username, creds.password).await
-            .map_err(|error| Flash::error(Redirect::to("/register"), error.to_string()))?;
+        let cookie = users.add_user(creds.username, creds.password, &client).await
+            .map_err(|error| Flash::error(Redirect::to(with_next("/register", next)), error.to_string()))?;
+        audit.record(Some(&admin.user.name), AuditEvent::Register { username: creds.username.to_string(), invited: false }).await;
         jar.add_private(cookie);
-        // TODO: read the "prev_page" cookie and redirect there
-        Ok(Redirect::to("/"))
+        Ok(Redirect::to(safe_next(next).unwrap_or("/").to_string()))
     }
 
     pub fn routes() -> Vec<Route> {
-        routes![index, index_admin, register]
+        routes![index, index_admin, index_invite, register, register_invited]
     }
 }
 
@@ -233,24 +526,32 @@ pub mod admin_register {
     //     File::open(RENDER_ROOT.join("admin-register.html")).await.into()
     // }
     #[get("/")]
-    async fn index(users: &State<db::Users>, error: Option<FlashMessage<'_>>) -> Result<Html<TextStream![String]>, Forbidden<()>> {
+    async fn index(users: &State<db::Users>, csrf: CsrfToken, error: Option<FlashMessage<'_>>) -> Result<Html<TextStream![String]>, Forbidden<()>> {
         // Only allow if there is no admin user
         if helpers::admin_user_exists(users).await {
             return Err(Forbidden(None))
         }
-        Ok(Html(TextStream(crate::components::render::<crate::components::authenticate::AdminRegister>(error.into()))))
+        Ok(Html(TextStream(crate::components::render::<crate::components::authenticate::AdminRegister>(
+            crate::components::authenticate::AuthError::new(error, csrf)
+        ))))
+    }
+
+    #[derive(FromForm)]
+    struct AdminPassword<'a> {
+        password: &'a str
     }
 
-    #[post("/", data="<password>")]
-    async fn admin_register(users: &State<db::Users>, password: Form<&str>) -> Outcome<Redirect, Flash<Redirect>> {
+    #[post("/", data="<form>")]
+    async fn admin_register(_csrf: Csrf, users: &State<db::Users>, audit: Audit<'_>, form: Form<AdminPassword<'_>>) -> Outcome<Redirect, Flash<Redirect>> {
         // Only allow if there is no admin user
         if helpers::admin_user_exists(users).await {
             return Outcome::Forbidden(())
         }
         // Do not give the newly registered admin a session
-        if let Err(error) = users.add_user(db::ADMIN_USR_ID, *password).await {
+        if let Err(error) = users.create_user(db::ADMIN_USR_ID, form.password).await {
             return Outcome::Err(Flash::error(Redirect::to("/admin-register"), error.to_string()))
         }
+        audit.record(None, AuditEvent::AdminRegister).await;
         // Allow the user to log in separately
         Outcome::Ok(Redirect::to("/login"))
     }
@@ -259,3 +560,591 @@ pub mod admin_register {
         routes![index, admin_register]
     }
 }
+
+/// Serves the users' profile pictures (see [`pfp`]) to logged in users.
+pub mod pictures {
+    use rocket::{fs::NamedFile, http::Header};
+    use super::*;
+
+    /// A picture's path has its id, which changes when it is replaced, so it can be cached forever.
+    #[derive(Responder)]
+    struct Cached(NamedFile, Header<'static>);
+
+    #[get("/<path..>")]
+    async fn picture(_user: User, path: PathBuf) -> Option<Cached> {
+        let file = NamedFile::open(crate::components::DEFAULT_PFP_PATH.join(path)).await.ok()?;
+        Some(Cached(file, Header::new("Cache-Control", "private, max-age=31536000, immutable")))
+    }
+
+    pub fn routes() -> Vec<Route> {
+        routes![picture]
+    }
+}
+
+
+/// Pages where a logged in user manages their own account.
+pub mod account {
+    use rocket::response::Flash;
+    use rocket::{Either, http::uri::Origin};
+    use crate::components::authenticate::{TotpSetup, TotpSetupProps, RecoveryCodes, RecoveryCodesProps};
+    use crate::components::{
+        admin::Flash as FlashMsg,
+        account::{ProfilePage, ProfileProps, SessionsPage, SessionsProps, SessionItem, TokensPage, TokensProps, TokenItem}
+    };
+    use rocket::{fs::TempFile, form::Errors};
+    use super::*;
+
+    #[get("/profile")]
+    async fn profile_index(user: User, users: &State<db::Users>, csrf: CsrfToken, flash: Option<FlashMessage<'_>>) -> Html<TextStream![String]> {
+        let pfp = users.account_info(&user.name).await
+            .and_then(|info| info.pfp)
+            .map(|id| pfp::path(&user.name, &id, pfp::LARGE));
+
+        Html(TextStream(crate::components::render::<ProfilePage>(ProfileProps {
+            user: user.into(),
+            pfp,
+            flash: flash.into(),
+            csrf: csrf.0
+        })))
+    }
+    #[get("/profile", rank = 2)]
+    async fn profile_login(uri: &Origin<'_>) -> Redirect {
+        login_redirect(uri)
+    }
+
+    #[derive(FromForm)]
+    struct PfpUpload<'a> {
+        picture: TempFile<'a>
+    }
+
+    /// Replaces the user's profile picture, and deletes the previous one.
+    /// The size of the upload is limited by rocket's `limits.file` config.
+    #[post("/profile/picture", data = "<form>")]
+    async fn upload_pfp(_csrf: Csrf, user: User, users: &State<db::Users>, form: Result<Form<PfpUpload<'_>>, Errors<'_>>) -> Flash<Redirect> {
+        let mut form = match form {
+            Ok(form) => form,
+            Err(_) => return Flash::error(Redirect::to("/account/profile"), "Choose an image that is within the server's size limit")
+        };
+
+        let id = match pfp::save(&user.name, &mut form.picture).await {
+            Ok(id) => id,
+            Err(error) => return Flash::error(Redirect::to("/account/profile"), error.to_string())
+        };
+        match users.set_pfp(&user.name, Some(id.clone())).await {
+            Ok(old) => {
+                if let Some(old) = old {
+                    pfp::remove(&user.name, &old).await;
+                }
+                Flash::success(Redirect::to("/account/profile"), "Profile picture changed")
+            },
+            Err(error) => {
+                pfp::remove(&user.name, &id).await;
+                Flash::error(Redirect::to("/account/profile"), error.to_string())
+            }
+        }
+    }
+
+    #[post("/profile/picture/remove")]
+    async fn remove_pfp(_csrf: Csrf, user: User, users: &State<db::Users>) -> Flash<Redirect> {
+        match users.set_pfp(&user.name, None).await {
+            Ok(old) => {
+                if let Some(old) = old {
+                    pfp::remove(&user.name, &old).await;
+                }
+                Flash::success(Redirect::to("/account/profile"), "Profile picture removed")
+            },
+            Err(error) => Flash::error(Redirect::to("/account/profile"), error.to_string())
+        }
+    }
+
+    #[get("/password")]
+    async fn password_index(_user: User, _session: sessions::Session, csrf: CsrfToken, error: Option<FlashMessage<'_>>) -> Html<TextStream![String]> {
+        Html(TextStream(crate::components::render::<crate::components::authenticate::ChangePassword>(
+            crate::components::authenticate::AuthError::new(error, csrf)
+        )))
+    }
+    #[get("/password", rank = 2)]
+    async fn password_login(uri: &Origin<'_>) -> Redirect {
+        login_redirect(uri)
+    }
+
+    #[derive(Debug, FromForm)]
+    struct PasswordChange<'a> {
+        current: &'a str,
+        new: &'a str,
+        confirm: &'a str
+    }
+
+    /// Logs the user out everywhere else, since whoever knew the old password could have logged in.
+    /// Like the other account security settings, this requires a session, so that a stolen token can't take over the account.
+    #[post("/password", data = "<form>")]
+    #[allow(clippy::too_many_arguments)] // Request guards
+    async fn change_password(_csrf: Csrf, jar: &CookieJar<'_>, users: &State<db::Users>, audit: Audit<'_>, user: User, _session: sessions::Session, client: sessions::ClientInfo, form: Form<PasswordChange<'_>>) -> Flash<Redirect> {
+        if form.new != form.confirm {
+            return Flash::error(Redirect::to("/account/password"), "New passwords do not match")
+        }
+        // Replace the session cookie, since all the user's sessions were removed
+        match users.change_password(&user.name, form.current, form.new, &client).await {
+            Ok(cookie) => {
+                audit.record(Some(&user.name), AuditEvent::PasswordChanged).await;
+                jar.add_private(cookie);
+                Flash::success(Redirect::to("/account/sessions"), "Password changed, and logged out everywhere else")
+            },
+            Err(error) => Flash::error(Redirect::to("/account/password"), error.to_string())
+        }
+    }
+
+    /// Lists the places where the user is logged in.
+    #[get("/sessions")]
+    async fn sessions_index(user: User, current: sessions::Session, users: &State<db::Users>, csrf: CsrfToken, flash: Option<FlashMessage<'_>>) -> Html<TextStream![String]> {
+        let sessions = users.user_sessions(&user.name).await
+            .into_iter()
+            .map(|session| SessionItem {
+                id: session.id(),
+                current: session.uuid == current.uuid,
+                ip: session.client.ip.map(|ip| ip.to_string()),
+                user_agent: session.client.user_agent,
+                created: session.created,
+                last_seen: session.last_seen,
+            })
+            .collect();
+
+        Html(TextStream(crate::components::render::<SessionsPage>(SessionsProps {
+            user: user.into(),
+            sessions,
+            flash: flash.into(),
+            csrf: csrf.0
+        })))
+    }
+    #[get("/sessions", rank = 2)]
+    async fn sessions_login(uri: &Origin<'_>) -> Redirect {
+        login_redirect(uri)
+    }
+
+    /// Logs out one of the user's sessions, which can be the current one.
+    #[post("/sessions/<id>/revoke")]
+    #[allow(clippy::too_many_arguments)] // Request guards
+    async fn revoke_session(_csrf: Csrf, jar: &CookieJar<'_>, user: User, current: sessions::Session, users: &State<db::Users>, audit: Audit<'_>, id: &str) -> Flash<Redirect> {
+        let session = match users.revoke_session(&user.name, id).await {
+            Some(session) => session,
+            None => return Flash::error(Redirect::to("/account/sessions"), "Session not found")
+        };
+        audit.record(Some(&user.name), AuditEvent::SessionRevoked { user_agent: session.client.user_agent }).await;
+
+        if session.uuid == current.uuid {
+            jar.remove_private(Cookie::named(SESSION_COOKIE));
+            Flash::success(Redirect::to("/login"), "Logged out")
+        } else {
+            Flash::success(Redirect::to("/account/sessions"), "Logged out the session")
+        }
+    }
+
+    #[post("/sessions/revoke-others")]
+    async fn revoke_other_sessions(_csrf: Csrf, user: User, current: sessions::Session, users: &State<db::Users>, audit: Audit<'_>) -> Flash<Redirect> {
+        users.logout_others(&user.name, &current.uuid).await;
+        audit.record(Some(&user.name), AuditEvent::OtherSessionsRevoked).await;
+        Flash::success(Redirect::to("/account/sessions"), "Logged out everywhere else")
+    }
+
+    /// The page with the tokens of the **user** that have not expired.
+    async fn tokens_props(users: &db::Users, user: User, csrf: CsrfToken, new_token: Option<String>, flash: FlashMsg) -> TokensProps {
+        let now = chrono::Utc::now();
+        let tokens = users.account_info(&user.name).await
+            .map(|info| info.tokens)
+            .unwrap_or_default();
+
+        TokensProps {
+            tokens: tokens.into_iter()
+                .filter(|token| !token.is_expired(now))
+                .map(|token| TokenItem {
+                    id: token.id,
+                    name: token.name,
+                    scope: token.scope.as_str(),
+                    created: token.created,
+                    expires: token.expires,
+                })
+                .collect(),
+            is_admin: user.is_admin(),
+            user: user.into(),
+            new_token,
+            flash,
+            csrf: csrf.0
+        }
+    }
+
+    /// Lists the user's personal access tokens.
+    /// Managing tokens requires a session, so that a token can't be used to create more tokens.
+    #[get("/tokens")]
+    async fn tokens_index(user: User, _session: sessions::Session, users: &State<db::Users>, csrf: CsrfToken, flash: Option<FlashMessage<'_>>) -> Html<TextStream![String]> {
+        let props = tokens_props(users, user, csrf, None, flash.into()).await;
+        Html(TextStream(crate::components::render::<TokensPage>(props)))
+    }
+    #[get("/tokens", rank = 2)]
+    async fn tokens_login(uri: &Origin<'_>) -> Redirect {
+        login_redirect(uri)
+    }
+
+    #[derive(Debug, FromForm)]
+    struct NewToken<'a> {
+        #[field(validate = len(1..=64))]
+        name: &'a str,
+        scope: tokens::TokenScope,
+        /// Days until the token expires. `0` for a token that never expires.
+        #[field(validate = range(0..=3650))]
+        days: u16
+    }
+
+    /// Shows the page with the new token, which is the only time the user can see it.
+    #[post("/tokens", data = "<form>")]
+    async fn create_token(_csrf: Csrf, csrf: CsrfToken, user: User, _session: sessions::Session, users: &State<db::Users>, audit: Audit<'_>, form: Form<NewToken<'_>>) -> Result<Html<TextStream![String]>, Flash<Redirect>> {
+        let lifetime = (form.days > 0).then(|| chrono::Duration::days(form.days.into()));
+        let (info, token) = users.create_token(&user.name, form.name.trim(), form.scope, lifetime).await
+            .map_err(|error| Flash::error(Redirect::to("/account/tokens"), error.to_string()))?;
+        audit.record(Some(&user.name), AuditEvent::TokenCreated { name: info.name }).await;
+
+        let flash = FlashMsg {
+            kind: "success".to_string(),
+            msg: "Copy the token now, it won't be shown again".to_string()
+        };
+        let props = tokens_props(users, user, csrf, Some(token), flash).await;
+        Ok(Html(TextStream(crate::components::render::<TokensPage>(props))))
+    }
+
+    #[post("/tokens/<id>/revoke")]
+    async fn revoke_token(_csrf: Csrf, user: User, _session: sessions::Session, users: &State<db::Users>, audit: Audit<'_>, id: &str) -> Flash<Redirect> {
+        match users.revoke_token(&user.name, id).await {
+            Ok(token) => {
+                audit.record(Some(&user.name), AuditEvent::TokenRevoked { name: token.name }).await;
+                Flash::success(Redirect::to("/account/tokens"), "Revoked token")
+            },
+            Err(error) => Flash::error(Redirect::to("/account/tokens"), error.to_string())
+        }
+    }
+
+    #[post("/sessions/revoke-all")]
+    async fn revoke_all_sessions(_csrf: Csrf, jar: &CookieJar<'_>, user: User, _session: sessions::Session, users: &State<db::Users>, audit: Audit<'_>) -> Result<Flash<Redirect>, Flash<Redirect>> {
+        users.logout_user(&user.name).await
+            .map_err(|error| Flash::error(Redirect::to("/account/sessions"), error.to_string()))?;
+        audit.record(Some(&user.name), AuditEvent::AllSessionsRevoked).await;
+        jar.remove_private(Cookie::named(SESSION_COOKIE));
+        Ok(Flash::success(Redirect::to("/login"), "Logged out everywhere"))
+    }
+
+    /// Shows a new secret to enroll, or lets the user disable 2FA if it is already enabled.
+    #[get("/2fa")]
+    async fn totp_index(user: User, _session: sessions::Session, users: &State<db::Users>, csrf: CsrfToken, flash: Option<FlashMessage<'_>>) -> Html<TextStream![String]> {
+        let enabled = users.account_info(&user.name).await
+            .is_some_and(|info| info.totp.is_some());
+        let error = flash.map(|flash| flash.message().to_string()).unwrap_or_default();
+
+        let props = if enabled {
+            TotpSetupProps { secret: None, uri: String::new(), error, csrf: csrf.0 }
+        } else {
+            enroll_props(users, &user.name, &totp::Totp::generate(), error, csrf)
+        };
+        Html(TextStream(crate::components::render::<TotpSetup>(props)))
+    }
+    #[get("/2fa", rank = 2)]
+    async fn totp_login(uri: &Origin<'_>) -> Redirect {
+        login_redirect(uri)
+    }
+
+    fn enroll_props(users: &db::Users, username: &str, totp: &totp::Totp, error: String, csrf: CsrfToken) -> TotpSetupProps {
+        TotpSetupProps {
+            secret: Some(totp.base32()),
+            uri: totp.uri(&users.totp_config().issuer, username),
+            error,
+            csrf: csrf.0
+        }
+    }
+
+    #[derive(Debug, FromForm)]
+    struct TotpEnable<'a> {
+        secret: &'a str,
+        code: &'a str
+    }
+
+    #[post("/2fa/enable", data = "<form>")]
+    #[allow(clippy::too_many_arguments)] // Request guards
+    async fn enable_totp(
+        _csrf: Csrf,
+        csrf: CsrfToken,
+        user: User,
+        _session: sessions::Session,
+        users: &State<db::Users>,
+        audit: Audit<'_>,
+        form: Form<TotpEnable<'_>>
+    ) -> Result<Html<TextStream![String]>, Either<Html<TextStream![String]>, Flash<Redirect>>> {
+        let totp = totp::Totp::from_base32(form.secret)
+            .ok_or_else(|| Either::Right(Flash::error(Redirect::to("/account/2fa"), "Invalid secret")))?;
+
+        match users.enable_totp(&user.name, &totp, form.code).await {
+            Ok(codes) => {
+                audit.record(Some(&user.name), AuditEvent::TotpEnabled).await;
+                Ok(Html(TextStream(crate::components::render::<RecoveryCodes>(RecoveryCodesProps { codes }))))
+            },
+            // Show the same secret again, since the user may have already added it to their app
+            Err(error @ db::TotpError::WrongCode) => Err(Either::Left(Html(TextStream(crate::components::render::<TotpSetup>(
+                enroll_props(users, &user.name, &totp, error.to_string(), csrf)
+            ))))),
+            Err(error) => Err(Either::Right(Flash::error(Redirect::to("/account/2fa"), error.to_string())))
+        }
+    }
+
+    #[derive(Debug, FromForm)]
+    struct TotpDisable<'a> {
+        password: &'a str
+    }
+
+    #[post("/2fa/disable", data = "<form>")]
+    async fn disable_totp(_csrf: Csrf, user: User, _session: sessions::Session, users: &State<db::Users>, audit: Audit<'_>, client: sessions::ClientInfo, form: Form<TotpDisable<'_>>) -> Flash<Redirect> {
+        match users.disable_totp(&user.name, form.password, &client).await {
+            Ok(()) => {
+                audit.record(Some(&user.name), AuditEvent::TotpDisabled).await;
+                Flash::success(Redirect::to("/account/2fa"), "Two-factor authentication disabled")
+            },
+            Err(error) => Flash::error(Redirect::to("/account/2fa"), error.to_string())
+        }
+    }
+
+    pub fn routes() -> Vec<Route> {
+        routes![
+            profile_index, profile_login, upload_pfp, remove_pfp,
+            password_index, password_login, change_password,
+            sessions_index, sessions_login, revoke_session, revoke_other_sessions, revoke_all_sessions,
+            tokens_index, tokens_login, create_token, revoke_token,
+            totp_index, totp_login, enable_totp, disable_totp
+        ]
+    }
+}
+
+/// Management of the users, only accessible by users in the [`db::ADMIN_GROUP`].
+pub mod admin {
+    use rocket::response::{Flash, status::Forbidden};
+    use crate::components::admin as components;
+    use super::*;
+
+    #[get("/", rank = 1)]
+    async fn index(admin: Admin, users: &State<db::Users>, csrf: CsrfToken, flash: Option<FlashMessage<'_>>) -> Html<TextStream![String]> {
+        let sessions = users.sessions().count_by_user().await;
+        let accounts = users.accounts().await
+            .into_iter()
+            .map(|(name, info)| components::AccountItem {
+                sessions: sessions.get(&name).copied().unwrap_or(0),
+                groups: info.groups.into_iter().collect(),
+                disabled: info.disabled,
+                last_login: info.last_login,
+                name,
+            })
+            .collect();
+
+        let invites = users.invites().list().await
+            .into_iter()
+            .map(|invite| components::InviteItem {
+                link: invite.link(),
+                token: invite.token,
+                created_by: invite.created_by,
+                expires: invite.expires,
+                group: invite.group
+            })
+            .collect();
+
+        Html(TextStream(crate::components::render::<components::Dashboard>(components::DashboardProps {
+            user: admin.user.into(),
+            accounts,
+            invites,
+            flash: flash.into(),
+            csrf: csrf.0
+        })))
+    }
+    #[get("/", rank = 2)]
+    async fn index_forbidden(user: Option<User>) -> Result<Forbidden<()>, Flash<Redirect>> {
+        match user {
+            Some(user) if user.needs_totp => Err(Flash::error(Redirect::to("/account/2fa"), "Enable two-factor authentication to use admin rights")),
+            Some(_) => Ok(Forbidden(None)),
+            None => Err(Flash::error(Redirect::to(with_next("/login", Some("/admin"))), "Log in to access the admin page"))
+        }
+    }
+
+    /// The [`audit::AuditLog`], newest first, filtered by event and by user.
+    #[get("/audit?<page>&<filter..>", rank = 1)]
+    async fn audit_log(admin: Admin, users: &State<db::Users>, filter: audit::AuditFilter, page: Option<usize>) -> Html<TextStream![String]> {
+        let page = page.unwrap_or(0);
+        let (entries, more, flash) = match users.audit().page(&filter, page).await {
+            Ok((entries, more)) => (entries, more, components::Flash::default()),
+            Err(error) => (Vec::new(), false, components::Flash {
+                kind: "error".to_string(),
+                msg: format!("Can't read the audit log: {error}")
+            })
+        };
+        let entries = entries.into_iter()
+            .map(|entry| components::AuditItem {
+                name: entry.event.name(),
+                target: entry.event.target().map(str::to_string),
+                details: entry.event.details(),
+                time: entry.time,
+                actor: entry.actor,
+                ip: entry.ip.map(|ip| ip.to_string()),
+            })
+            .collect();
+
+        let event = filter.event.unwrap_or_default();
+        let user = filter.user.unwrap_or_default();
+        let link = |page: usize| format!("/admin/audit?event={}&user={}&page={page}",
+            rocket::http::RawStr::new(&event).percent_encode(),
+            rocket::http::RawStr::new(&user).percent_encode()
+        );
+
+        Html(TextStream(crate::components::render::<components::AuditPage>(components::AuditProps {
+            user: admin.user.into(),
+            entries,
+            prev: page.checked_sub(1).map(link),
+            next: more.then(|| link(page + 1)),
+            event,
+            filter_user: user,
+            flash
+        })))
+    }
+    #[get("/audit", rank = 2)]
+    async fn audit_log_forbidden(user: Option<User>) -> Result<Forbidden<()>, Flash<Redirect>> {
+        index_forbidden(user).await
+    }
+
+    /// Creates a new account. Unlike `/register`, the admin stays logged in as themselves.
+    #[post("/users", data = "<creds>")]
+    async fn create_user(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, creds: Form<Creds<'_>>) -> Flash<Redirect> {
+        match users.create_user(creds.username, creds.password).await {
+            Ok(()) => {
+                audit.record(Some(&admin.user.name), AuditEvent::Register { username: creds.username.to_string(), invited: false }).await;
+                Flash::success(Redirect::to("/admin"), format!("Created {:?}", creds.username))
+            },
+            Err(error) => Flash::error(Redirect::to("/admin"), error.to_string())
+        }
+    }
+
+    #[derive(Debug, FromForm)]
+    struct Groups<'a> {
+        /// Comma or whitespace separated group names.
+        groups: &'a str
+    }
+
+    #[post("/users/<username>/groups", data = "<form>")]
+    async fn set_groups(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, username: &str, form: Form<Groups<'_>>) -> Flash<Redirect> {
+        let groups = form.groups
+            .split(|ch: char| ch == ',' || ch.is_whitespace())
+            .filter(|group| !group.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        match users.set_groups(username, groups.clone()).await {
+            Ok(()) => {
+                audit.record(Some(&admin.user.name), AuditEvent::GroupsChanged { username: username.to_string(), groups }).await;
+                Flash::success(Redirect::to("/admin"), format!("Updated groups of {username:?}"))
+            },
+            Err(error) => Flash::error(Redirect::to("/admin"), error.to_string())
+        }
+    }
+
+    #[derive(Debug, FromForm)]
+    struct PasswordReset<'a> {
+        password: &'a str
+    }
+
+    /// Sets a new password for the user, and logs them out everywhere.
+    #[post("/users/<username>/password", data = "<form>")]
+    async fn reset_password(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, username: &str, form: Form<PasswordReset<'_>>) -> Flash<Redirect> {
+        match users.set_password(username, form.password).await {
+            Ok(()) => {
+                audit.record(Some(&admin.user.name), AuditEvent::PasswordReset { username: username.to_string() }).await;
+                Flash::success(Redirect::to("/admin"), format!("Reset password of {username:?}"))
+            },
+            Err(error) => Flash::error(Redirect::to("/admin"), error.to_string())
+        }
+    }
+
+    #[post("/users/<username>/disable")]
+    async fn disable(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, username: &str) -> Flash<Redirect> {
+        match users.set_disabled(username, true).await {
+            Ok(()) => {
+                audit.record(Some(&admin.user.name), AuditEvent::UserDisabled { username: username.to_string() }).await;
+                Flash::success(Redirect::to("/admin"), format!("Disabled {username:?}"))
+            },
+            Err(error) => Flash::error(Redirect::to("/admin"), error.to_string())
+        }
+    }
+    #[post("/users/<username>/enable")]
+    async fn enable(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, username: &str) -> Flash<Redirect> {
+        match users.set_disabled(username, false).await {
+            Ok(()) => {
+                audit.record(Some(&admin.user.name), AuditEvent::UserEnabled { username: username.to_string() }).await;
+                Flash::success(Redirect::to("/admin"), format!("Enabled {username:?}"))
+            },
+            Err(error) => Flash::error(Redirect::to("/admin"), error.to_string())
+        }
+    }
+    #[derive(Debug, FromForm)]
+    struct NewInvite<'a> {
+        /// Optional group to add the invited user to.
+        group: &'a str,
+        /// Days until the invite expires.
+        #[field(validate = range(1..=365))]
+        days: u16
+    }
+
+    #[post("/invites", data = "<form>")]
+    async fn create_invite(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, form: Form<NewInvite<'_>>) -> Flash<Redirect> {
+        let group = match form.group.trim() {
+            "" => None,
+            group => match helpers::validate_group(group) {
+                Ok(()) => Some(group.to_string()),
+                Err(error) => return Flash::error(Redirect::to("/admin"), error.to_string())
+            }
+        };
+
+        match users.invites().create(&admin.user.name, group.clone(), chrono::Duration::days(form.days.into())).await {
+            Ok(invite) => {
+                audit.record(Some(&admin.user.name), AuditEvent::InviteCreated { group }).await;
+                Flash::success(Redirect::to("/admin"), format!("Created invite {}", invite.link()))
+            },
+            Err(error) => Flash::error(Redirect::to("/admin"), format!("Error saving invite: {error}"))
+        }
+    }
+
+    #[post("/invites/<token>/revoke")]
+    async fn revoke_invite(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, token: &str) -> Flash<Redirect> {
+        match users.invites().take(token).await {
+            Some(_) => {
+                audit.record(Some(&admin.user.name), AuditEvent::InviteRevoked).await;
+                Flash::success(Redirect::to("/admin"), "Revoked invite")
+            },
+            None => Flash::error(Redirect::to("/admin"), "Invite not found")
+        }
+    }
+
+    /// Logs out the user everywhere.
+    #[post("/users/<username>/logout")]
+    async fn logout(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, username: &str) -> Flash<Redirect> {
+        match users.logout_user(username).await {
+            Ok(()) => {
+                audit.record(Some(&admin.user.name), AuditEvent::UserLoggedOut { username: username.to_string() }).await;
+                Flash::success(Redirect::to("/admin"), format!("Logged out {username:?}"))
+            },
+            Err(error) => Flash::error(Redirect::to("/admin"), error.to_string())
+        }
+    }
+    #[post("/users/<username>/delete")]
+    async fn delete(_csrf: Csrf, admin: Admin, users: &State<db::Users>, audit: Audit<'_>, username: &str) -> Flash<Redirect> {
+        match users.delete_user(username).await {
+            Ok(()) => {
+                audit.record(Some(&admin.user.name), AuditEvent::UserDeleted { username: username.to_string() }).await;
+                pfp::remove_all(username).await;
+                Flash::success(Redirect::to("/admin"), format!("Deleted {username:?}"))
+            },
+            Err(error) => Flash::error(Redirect::to("/admin"), error.to_string())
+        }
+    }
+
+    pub fn routes() -> Vec<Route> {
+        routes![index, index_forbidden, audit_log, audit_log_forbidden, create_user, set_groups, reset_password, disable, enable, logout, delete, create_invite, revoke_invite]
+    }
+}
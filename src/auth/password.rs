@@ -0,0 +1,146 @@
+use std::{
+    io,
+    collections::HashSet,
+    path::PathBuf,
+};
+use argon2::{
+    Algorithm, Argon2, Params, PasswordHash, Version,
+    password_hash::errors::Error as HashError
+};
+use serde::Deserialize;
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+
+
+/// Parameters of the *Argon2id* hash used for new passwords.
+/// Set in the `auth.hash` table of the server's config (e.g. `Rocket.toml`).
+///
+/// Passwords that were hashed with weaker parameters are rehashed the next time their user logs in
+/// (see [`is_outdated()`]), so the parameters can be raised without resetting any password.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HashConfig {
+    /// Memory used by each hash, in KiB.
+    pub memory: u32,
+    /// Number of passes over the memory.
+    pub iterations: u32,
+    /// Number of lanes that are hashed in parallel.
+    pub parallelism: u32,
+}
+impl Default for HashConfig {
+    /// The minimum recommended by [OWASP](https://cheatsheetseries.owasp.org/cheatsheets/Password_Storage_Cheat_Sheet.html#argon2id).
+    fn default() -> Self {
+        Self {
+            memory: 19 * 1024, // 19 MiB
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+impl HashConfig {
+    /// Fails if the parameters are out of the ranges that *Argon2* allows.
+    pub fn params(&self) -> Result<Params, HashError> {
+        Ok(Params::new(self.memory, self.iterations, self.parallelism, None)?)
+    }
+}
+
+/// The hasher for new passwords, with **params** from [`HashConfig::params()`].
+pub fn hasher(params: Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+/// Whether **hash** should be replaced by a hash with **params**,
+/// because it uses another algorithm or version, or any of its parameters is weaker.
+pub fn is_outdated(hash: &PasswordHash, params: &Params) -> bool {
+    if hash.algorithm != Algorithm::Argon2id.ident()
+    || hash.version != Some(Version::V0x13.into()) {
+        return true
+    }
+    match Params::try_from(hash) {
+        Ok(old) => old.m_cost() < params.m_cost()
+            || old.t_cost() < params.t_cost()
+            || old.p_cost() < params.p_cost(),
+        Err(_) => true
+    }
+}
+
+/// Passwords are hashed in *NFKC* form, so that the same passphrase matches
+/// however the client's keyboard or OS composes its characters (e.g. `é` as one or two code points).
+pub fn normalize(password: &str) -> String {
+    password.nfkc().collect()
+}
+
+/// Requirements for new passwords. Existing passwords are not checked, so users can still log in.
+/// Set in the `auth.password` table of the server's config (e.g. `Rocket.toml`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Minimum number of characters (after [normalizing](normalize())).
+    pub min_length: usize,
+    /// Maximum number of characters, so that hashing a huge password can't hog the server.
+    pub max_length: usize,
+    /// A file with a password on each line (e.g. a list of breached or common passwords), which are not allowed.
+    /// Compared without case.
+    pub denylist: Option<PathBuf>,
+}
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 1024,
+            denylist: None,
+        }
+    }
+}
+
+/// The [`PolicyConfig`] with its denylist loaded.
+#[derive(Debug)]
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    /// [Normalized](normalize()) and lowercase.
+    denylist: HashSet<String>,
+}
+impl PasswordPolicy {
+    /// Reads the denylist file if there is one in the **config**.
+    pub fn load(config: PolicyConfig) -> io::Result<Self> {
+        let denylist = match &config.denylist {
+            Some(path) => std::fs::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| normalize(line).to_lowercase())
+                .collect(),
+            None => HashSet::new()
+        };
+        Ok(Self {
+            min_length: config.min_length,
+            max_length: config.max_length,
+            denylist
+        })
+    }
+
+    /// Checks a [normalized](normalize()) **password**.
+    pub fn check(&self, password: &str) -> Result<(), PolicyError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            Err(PolicyError::TooShort(self.min_length))
+        } else if length > self.max_length {
+            Err(PolicyError::TooLong(self.max_length))
+        } else if self.denylist.contains(&password.to_lowercase()) {
+            Err(PolicyError::Denylisted)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("Password must have at least {0} characters")]
+    TooShort(usize),
+    #[error("Password must have at most {0} characters")]
+    TooLong(usize),
+    #[error("This password is too common, please choose another one")]
+    Denylisted,
+}
@@ -0,0 +1,165 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use async_process::Command;
+use rocket::fs::TempFile;
+use rand::{distributions::Alphanumeric, Rng};
+use thiserror::Error;
+use crate::components::DEFAULT_PFP_PATH;
+
+
+/// Sizes (in pixels, the pictures are square) that each uploaded picture is resized to.
+pub const SIZES: [u32; 2] = [SMALL, LARGE];
+/// Shown in the page header.
+pub const SMALL: u32 = 64;
+/// Shown in the profile page.
+pub const LARGE: u32 = 256;
+/// Formats that can be uploaded, as named by *ImageMagick*, with the bytes their files start with.
+/// *WEBP* files start with `RIFF`, their size, then `WEBP`.
+const FORMATS: [(&str, &[u8]); 5] = [
+    ("PNG", b"\x89PNG\r\n\x1a\n"),
+    ("JPEG", b"\xFF\xD8\xFF"),
+    ("GIF", b"GIF87a"),
+    ("GIF", b"GIF89a"),
+    ("WEBP", b"RIFF"),
+];
+/// Larger images are rejected before they are decoded, so that they can't exhaust the server's memory.
+const MAX_DIMENSION: u32 = 8192;
+const ID_LEN: usize = 8;
+
+/// Where the picture of **username** with **id** (see [`AccountInfo::pfp`](super::db::AccountInfo::pfp)) is stored in **size**.
+/// Each upload gets a new id, so the pictures can be cached forever.
+pub fn path(username: &str, id: &str, size: u32) -> PathBuf {
+    DEFAULT_PFP_PATH.join(username).join(format!("{id}-{size}.png"))
+}
+
+/// Checks that the **upload** is an image, and saves it resized to each of the [`SIZES`] for **username**.
+/// Returns the id of the new picture.
+///
+/// This function requires the `ImageMagick` package.
+pub async fn save(username: &str, upload: &mut TempFile<'_>) -> Result<String, PfpError> {
+    let id = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(ID_LEN)
+        .map(char::from)
+        .collect::<String>();
+
+    let dir = DEFAULT_PFP_PATH.join(username);
+    rocket::tokio::fs::create_dir_all(&dir).await?;
+    let path = dir.join(format!("{id}.upload"));
+    upload.copy_to(&path).await?;
+
+    let result = resize(username, &id, &path).await;
+    let _ = rocket::tokio::fs::remove_file(path).await;
+    result.map(|()| id)
+}
+
+async fn resize(username: &str, id: &str, upload: &Path) -> Result<(), PfpError> {
+    // Checked before ImageMagick sees the file, since it would otherwise choose a decoder by the content,
+    // including ones that run scripts or read other files (e.g. SVG, MVG, MSL or PostScript)
+    let mut header = [0; 12];
+    let len = read_header(upload, &mut header).await?;
+    let format = sniff_format(&header[..len]).ok_or(PfpError::NotAnImage)?;
+
+    // -ping reads only the header, so the image is not decoded yet
+    let output = Command::new("identify")
+        .arg("-ping")
+        .arg("-format").arg("%m %w %h\n")
+        .arg(format!("{format}:{}", frame(upload)))
+        .output().await?;
+    if !output.status.success() {
+        return Err(PfpError::NotAnImage)
+    }
+    let (identified, width, height) = parse_identify(&String::from_utf8_lossy(&output.stdout))
+        .ok_or(PfpError::NotAnImage)?;
+    if identified != format {
+        return Err(PfpError::UnsupportedFormat(identified))
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(PfpError::TooLarge(MAX_DIMENSION))
+    }
+
+    for size in SIZES {
+        // Crop to a square from the center, and remove metadata (e.g. GPS location)
+        let output = Command::new("convert")
+            // The format is given explicitly so that ImageMagick doesn't guess it from the content again
+            .arg(format!("{format}:{}", frame(upload)))
+            .arg("-auto-orient")
+            .arg("-strip")
+            .arg("-thumbnail").arg(format!("{size}x{size}^"))
+            .arg("-gravity").arg("center")
+            .arg("-extent").arg(format!("{size}x{size}"))
+            .arg(format!("PNG:{}", path(username, id, size).display()))
+            .output().await?;
+        if !output.status.success() {
+            remove(username, id).await;
+            return Err(PfpError::Convert(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes all sizes of the picture of **username** with **id**.
+pub async fn remove(username: &str, id: &str) {
+    for size in SIZES {
+        let _ = rocket::tokio::fs::remove_file(path(username, id, size)).await;
+    }
+}
+
+/// Deletes all the pictures of **username**, e.g. when the user is deleted.
+pub async fn remove_all(username: &str) {
+    let _ = rocket::tokio::fs::remove_dir_all(DEFAULT_PFP_PATH.join(username)).await;
+}
+
+/// Reads the start of the file at **path** into **header**, and returns how much was read.
+async fn read_header(path: &Path, header: &mut [u8]) -> io::Result<usize> {
+    use rocket::tokio::io::AsyncReadExt;
+
+    let mut file = rocket::tokio::fs::File::open(path).await?;
+    let mut len = 0;
+    while len < header.len() {
+        match file.read(&mut header[len..]).await? {
+            0 => break,
+            read => len += read
+        }
+    }
+    Ok(len)
+}
+
+/// The format (one of the [`FORMATS`]) of an image that starts with **header**.
+pub(super) fn sniff_format(header: &[u8]) -> Option<&'static str> {
+    FORMATS.iter()
+        .find(|(format, magic)| header.starts_with(magic)
+            && (*format != "WEBP" || header.get(8..12) == Some(b"WEBP")))
+        .map(|(format, _)| *format)
+}
+
+/// Only the first frame of animations (e.g. *GIF*s) is used.
+fn frame(path: &Path) -> String {
+    format!("{}[0]", path.display())
+}
+
+/// Parses the output of `identify -format "%m %w %h\n"`: the format, width and height.
+pub(super) fn parse_identify(output: &str) -> Option<(String, u32, u32)> {
+    let mut parts = output.lines().next()?.split_whitespace();
+    let format = parts.next()?.to_string();
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    Some((format, width, height))
+}
+
+#[derive(Error, Debug)]
+pub enum PfpError {
+    #[error("The file is not an image")]
+    NotAnImage,
+    #[error("Images in {0} format are not supported, use PNG, JPEG, GIF or WEBP")]
+    UnsupportedFormat(String),
+    #[error("The image can be at most {0}x{0} pixels")]
+    TooLarge(u32),
+    #[error("Can't resize the image: {0}")]
+    Convert(String),
+    #[error("Error saving picture: {0:?}")]
+    IoError(#[from] io::Error)
+}
@@ -0,0 +1,206 @@
+use std::{
+    io,
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Serialize, Deserialize};
+use data_encoding::HEXLOWER;
+use sha2::{Sha256, Digest};
+use crate::do_while;
+use super::store::UserStore;
+
+
+/// A logged in session, identified by the `uuid` stored in the client's [`SESSION_COOKIE`](super::SESSION_COOKIE).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub uuid: String,
+    /// The `user id` that owns this session.
+    pub user: String,
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// The session is no longer valid after this time.
+    pub expires: DateTime<Utc>,
+    /// The client that logged in. Sessions saved before this was recorded have none.
+    #[serde(default)]
+    pub client: ClientInfo,
+}
+impl Session {
+    /// A session expires when it reaches its absolute [`Self::expires`] time,
+    /// or when it has not been used in [`SessionConfig::idle_timeout`] seconds.
+    pub fn is_expired(&self, now: DateTime<Utc>, config: &SessionConfig) -> bool {
+        now >= self.expires
+        || now >= self.last_seen + Duration::seconds(config.idle_timeout as i64)
+    }
+
+    /// Identifies the session in pages and forms, where the [`Self::uuid`] must not be shown
+    /// since anyone who knows it can use the session.
+    pub fn id(&self) -> String {
+        let mut id = HEXLOWER.encode(&Sha256::digest(self.uuid.as_bytes()));
+        id.truncate(16);
+        id
+    }
+
+    /// How long the client should keep the session cookie (in seconds).
+    /// Is the time until the session would become idle, but never past the absolute expiry.
+    pub fn cookie_max_age(&self, config: &SessionConfig) -> i64 {
+        let idle = self.last_seen + Duration::seconds(config.idle_timeout as i64);
+        (std::cmp::min(idle, self.expires) - self.last_seen).num_seconds()
+    }
+}
+
+/// Describes the client that a [`Session`] was created for, so the user can recognize their sessions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+impl ClientInfo {
+    /// User agents are only shown to the user, so longer ones are cut off instead of stored whole.
+    pub const MAX_USER_AGENT_LEN: usize = 256;
+}
+
+/// Lifetimes of the sessions, in seconds.
+/// Set in the `auth.sessions` table of the server's config (e.g. `Rocket.toml`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// How long a session lasts since it was created, regardless of activity.
+    pub max_age: u64,
+    /// A session expires if it is not used for this long.
+    /// Each use of a session extends its cookie by this amount (up to [`Self::max_age`]).
+    pub idle_timeout: u64,
+}
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            max_age: 30 * 24 * 60 * 60, // 30 days
+            idle_timeout: 7 * 24 * 60 * 60, // 7 days
+        }
+    }
+}
+
+/// The sessions of all users, saved in the [`UserStore`] so they survive server restarts.
+#[derive(Debug)]
+pub struct Sessions {
+    config: SessionConfig,
+    store: Arc<dyn UserStore>,
+}
+impl Sessions {
+    /// How often expired sessions are pruned and pending changes are written to the store.
+    pub const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+    pub fn new(store: Arc<dyn UserStore>, config: SessionConfig) -> Self {
+        Self { config, store }
+    }
+
+    #[inline]
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// If is a valid session, marks it as seen and returns it.
+    /// Expired sessions are removed.
+    pub async fn validate(&self, uuid: &str) -> Option<Session> {
+        let now = Utc::now();
+
+        let mut session = Self::log(self.store.get_session(uuid).await)??;
+        if session.is_expired(now, &self.config) {
+            self.remove(uuid).await;
+            return None
+        }
+
+        session.last_seen = now;
+        Self::log(self.store.touch_session(uuid, now).await);
+        Some(session)
+    }
+
+    /// Creates a new session for **user**, logged in from **client**.
+    /// The session is still returned if it can't be saved, but won't be valid after the server restarts.
+    pub async fn insert(&self, user: &str, client: ClientInfo) -> Session {
+        let mut uuid: String;
+        // Ensure the uuid is unique
+        do_while!{ do {
+            uuid = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(37)
+                .map(char::from)
+                .collect();
+        } while matches!(self.store.get_session(&uuid).await, Ok(Some(_))) };
+
+        let now = Utc::now();
+        let session = Session {
+            uuid,
+            user: user.to_string(),
+            created: now,
+            last_seen: now,
+            expires: now + Duration::seconds(self.config.max_age as i64),
+            client,
+        };
+        Self::log(self.store.insert_session(&session).await);
+
+        session
+    }
+
+    pub async fn remove(&self, uuid: &str) {
+        Self::log(self.store.delete_session(uuid).await);
+    }
+
+    /// Removes all the sessions of **user** (e.g. to log them out everywhere).
+    pub async fn remove_user(&self, user: &str) {
+        Self::log(self.store.delete_user_sessions(user).await);
+    }
+
+    /// Removes all the sessions of **user**, except the one with **keep** (e.g. the session the user is using).
+    pub async fn remove_user_except(&self, user: &str, keep: &str) {
+        for session in self.list_user(user).await {
+            if session.uuid != keep {
+                self.remove(&session.uuid).await;
+            }
+        }
+    }
+
+    /// The active sessions of **user**, most recently seen first.
+    pub async fn list_user(&self, user: &str) -> Vec<Session> {
+        let now = Utc::now();
+
+        let mut sessions = Self::log(self.store.list_sessions().await).unwrap_or_default()
+            .into_iter()
+            .filter(|session| session.user == user && !session.is_expired(now, &self.config))
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen));
+        sessions
+    }
+
+    /// How many active sessions each user has. Users with no sessions are not included.
+    pub async fn count_by_user(&self) -> HashMap<String, usize> {
+        let now = Utc::now();
+
+        let mut counts = HashMap::new();
+        for session in Self::log(self.store.list_sessions().await).unwrap_or_default() {
+            if !session.is_expired(now, &self.config) {
+                *counts.entry(session.user).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// Removes all expired sessions, and writes any pending changes to the store.
+    pub async fn prune(&self) {
+        let now = Utc::now();
+        let idle = now - Duration::seconds(self.config.idle_timeout as i64);
+        Self::log(self.store.prune_sessions(now, idle).await);
+    }
+
+    /// Writes pending changes (e.g. [`Session::last_seen`]) to the store.
+    pub async fn flush(&self) {
+        Self::log(self.store.flush().await);
+    }
+
+    /// Errors of the store only make sessions invalid (or not renewed), so they are only logged.
+    fn log<T>(result: io::Result<T>) -> Option<T> {
+        result.map_err(|error| eprintln!("Error accessing sessions: {error}")).ok()
+    }
+}
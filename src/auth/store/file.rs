@@ -0,0 +1,449 @@
+use std::{
+    io,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use argon2::{PasswordHash, password_hash::PasswordHashString};
+use chrono::{DateTime, Utc};
+use async_std::sync::Mutex as AsyncMutex;
+use serde::{Serialize, Deserialize};
+use crate::auth::{
+    helpers,
+    db::{Users, Account, AccountInfo, LoadUsersError, UserNameError, ADMIN_USR_ID, ADMIN_GROUP},
+    sessions::Session,
+};
+use super::UserStore;
+
+
+/// A line in the users file (see [`FileStore::VERSION`]).
+#[derive(Deserialize)]
+struct Entry {
+    name: String,
+    hash: String,
+    #[serde(default)]
+    info: AccountInfo,
+}
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    name: &'a str,
+    hash: &'a str,
+    info: &'a AccountInfo,
+}
+
+/// Keeps all users and sessions in memory, and saves them to files in the `.secrets/db` directory.
+/// Good enough for a handful of users, since each change rewrites a whole file.
+///
+/// The sessions file has the same name as the users file, followed by `.sessions` (see [`Self::sessions_path()`]).
+/// Each of its lines is a [`Session`] in *JSON* format.
+/// Changes to [`Session::last_seen`] are only kept in memory until the next [`UserStore::flush()`].
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    // TODO: maybe use RwLock
+    db: AsyncMutex<HashMap<String, Account>>,
+    sessions: AsyncMutex<SessionsState>,
+}
+#[derive(Debug, Default)]
+struct SessionsState {
+    /// `HashMap<SessionUuid, Session>`
+    map: HashMap<String, Session>,
+    /// There are changes in memory that have not been written to the file.
+    dirty: bool,
+}
+impl FileStore {
+    /// Separates each entry in a version 1 file. E.g. `$user0$...\n$user1`
+    pub const ENTRY_SEP: char = '\n';
+
+    /// Load existing users and sessions from the users file at **path**.
+    /// The file is created if it does not exist, and migrated if it has an older [version](Self::VERSION).
+    pub fn load_path(path: PathBuf) -> Result<Self, LoadUsersError> {
+        use std::fs;
+        use std::process::Command;
+
+        let file = match fs::read_to_string(&path) {
+            Ok(file) => file,
+            // If file does not exist, create it
+            Err(_) => {
+                Command::new("mkdir")
+                    .arg("-p")
+                    .arg(match path.parent() {
+                        Some(path) => path, // path is a file within a directory
+                        None => &path // path is a file in the server's root
+                    }).status()?;
+                
+                Command::new("touch")
+                    .arg(&path)
+                    .status()?;
+                
+                fs::read_to_string(&path)?
+            }
+        };
+        
+        let mut db = match Self::file_version(&file) {
+            Some(Self::VERSION) => Self::db_from_json(&file)?,
+            Some(version) => return Err(LoadUsersError::UnsupportedVersion(version)),
+            None => Self::migrate_v1(&path, &file)?
+        };
+        if let Some(admin) = db.get_mut(ADMIN_USR_ID) {
+            admin.info.groups.insert(ADMIN_GROUP.to_string());
+        }
+
+        Ok(Self {
+            db: AsyncMutex::new(db),
+            sessions: AsyncMutex::new(SessionsState {
+                map: Self::load_sessions(&Self::sessions_path(&path))?,
+                dirty: false
+            }),
+            path,
+        })
+    }
+    /// The sessions are stored in a file with the same name as the users file, followed by `.sessions`.
+    /// E.g. `.secrets/db/users` -> `.secrets/db/users.sessions`.
+    fn sessions_path(path: &Path) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".sessions");
+        PathBuf::from(path)
+    }
+    /// Before version 2, each user's [`AccountInfo`] was stored in a file with the same name as the users file, followed by `.info`.
+    /// E.g. `.secrets/db/users` -> `.secrets/db/users.info`.
+    /// 
+    /// Format: a *JSON* object of `{ "username": AccountInfo }`.
+    fn v1_info_path(path: &Path) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".info");
+        PathBuf::from(path)
+    }
+    /// Where a file is moved to before it is migrated to the current version. E.g. `users` -> `users.v1.bak`.
+    fn backup_path(path: &Path, version: u32) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(format!(".v{version}.bak"));
+        PathBuf::from(path)
+    }
+
+    /// Lines that can't be parsed are dropped.
+    fn load_sessions(path: &Path) -> io::Result<HashMap<String, Session>> {
+        let file = match std::fs::read_to_string(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error)
+        };
+        Ok(file.lines()
+            .filter_map(|line| serde_json::from_str::<Session>(line).ok())
+            .map(|session| (session.uuid.clone(), session))
+            .collect())
+    }
+
+    /// Rewrites the users file with every user in **db** (see [`Self::VERSION`] for the format).
+    async fn save(&self, db: &HashMap<String, Account>) -> io::Result<()> {
+        helpers::write_atomic(&self.path, Self::db_to_json(db)).await
+    }
+
+    /// Rewrites the sessions file with the sessions in **state**.
+    async fn save_sessions(&self, state: &mut SessionsState) -> io::Result<()> {
+        let file = state.map.values()
+            .map(|session| serde_json::to_string(session).expect("Session can always be serialized") + "\n")
+            .collect::<String>();
+        match helpers::write_atomic(&Self::sessions_path(&self.path), file).await {
+            Ok(()) => {
+                state.dirty = false;
+                Ok(())
+            },
+            Err(error) => {
+                // The sessions are still valid in memory, so try again on the next change
+                state.dirty = true;
+                Err(error)
+            }
+        }
+    }
+
+    /// First line of the users file, followed by the version number of the format (e.g. `#users-db v2`).
+    /// Files without this header are [version 1](Self::db_from_v1()).
+    const HEADER: &'static str = "#users-db v";
+    /// Version of the users file format that is written by the server.
+    ///
+    /// Version 2: after the [header](Self::HEADER), each line is a user in *JSON* format:
+    /// `{ "name": "username", "hash": "$argon2id$...", "info": AccountInfo }`.
+    /// Files with an older version are migrated when they are loaded.
+    pub const VERSION: u32 = 2;
+
+    /// Reads the version in the file's [header](Self::HEADER).
+    /// Returns [`None`] for version 1, which had no header.
+    fn file_version(file: &str) -> Option<u32> {
+        let version = file.lines().next()?.strip_prefix(Self::HEADER)?;
+        // An unreadable version is newer than any version this server knows
+        Some(version.trim().parse().unwrap_or(u32::MAX))
+    }
+
+    fn db_from_json(file: &str) -> Result<HashMap<String, Account>, LoadUsersError> {
+        file.lines()
+            .skip(1) // header
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let entry = serde_json::from_str::<Entry>(line).map_err(LoadUsersError::InvalidJson)?;
+                helpers::validate_username(&entry.name)?;
+                let hash = PasswordHash::new(&entry.hash)?.serialize();
+                Ok((entry.name, Account { hash, info: entry.info }))
+            })
+            .collect()
+    }
+
+    fn db_to_json(db: &HashMap<String, Account>) -> String {
+        let mut accounts = db.iter().collect::<Vec<_>>();
+        accounts.sort_by_key(|(username, _)| *username);
+
+        let mut file = format!("{}{}\n", Self::HEADER, Self::VERSION);
+        for (name, account) in accounts {
+            let entry = EntryRef { name, hash: account.hash.as_str(), info: &account.info };
+            file.push_str(&serde_json::to_string(&entry).expect("User can always be serialized"));
+            file.push('\n');
+        }
+        file
+    }
+
+    /// Converts a [version 1](Self::db_from_v1()) users file (and its [`v1_info_path()`](Self::v1_info_path())) to the current version.
+    /// The old files are kept as backups (see [`Self::backup_path()`]).
+    fn migrate_v1(path: &Path, file: &str) -> Result<HashMap<String, Account>, LoadUsersError> {
+        use std::fs;
+
+        let info_path = Self::v1_info_path(path);
+        let mut info = match fs::read_to_string(&info_path) {
+            Ok(file) => Some(serde_json::from_str::<HashMap<String, AccountInfo>>(&file)
+                .map_err(LoadUsersError::InvalidJson)?),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into())
+        };
+        let db = Self::db_from_v1(file)?
+            .into_iter()
+            .map(|(name, hash)| {
+                let info = info.as_mut()
+                    .and_then(|info| info.remove(&name))
+                    .unwrap_or_default();
+                (name, Account { hash, info })
+            })
+            .collect::<HashMap<_, _>>();
+
+        // Keep the old files in case something goes wrong. An empty file is just a new database.
+        if !file.is_empty() {
+            fs::copy(path, Self::backup_path(path, 1))?;
+        }
+        // Write the new file without leaving it half-written (like helpers::write_atomic())
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, Self::db_to_json(&db))?;
+        fs::rename(&temp, path)?;
+        // The info is now in the users file
+        if info.is_some() {
+            fs::rename(&info_path, Self::backup_path(&info_path, 1))?;
+        }
+
+        Ok(db)
+    }
+
+    /// Version 1 format: `$username$PasswordHashLength$PasswordHash`.
+    /// Each user separated by a *line-break* `\n`.
+    fn db_from_v1(s: &str) -> Result<HashMap<String, PasswordHashString>, LoadUsersError> {
+        #[derive(PartialEq)]
+        enum State {
+            Name, HashLen
+        }
+        #[derive(Default)]
+        struct Builder {
+            name: String,
+        }
+
+        let mut db = HashMap::new();
+        let mut builder = Builder::default();
+        let mut buf = String::new();
+        let mut chars = s.chars();
+        let mut state = None;
+
+        while let Some(ch) = chars.next() {
+            if ch == Users::SEP {
+                match state {
+                    // Every entry starts with `Users::SEP`
+                    None => state = Some(State::Name),
+
+                    Some(State::Name) => {
+                        if buf.is_empty() {
+                            return Err(LoadUsersError::InvalidUserName(UserNameError::Empty))
+                        }
+                        builder.name = buf;
+                        buf = String::new();
+                        state = Some(State::HashLen)
+                    }
+                    Some(State::HashLen) => {
+                        // The length of the PasswordHashString
+                        let len = buf.parse::<usize>()
+                            .map_err(|error| LoadUsersError::BadHashLength(error))?;
+                        buf = String::with_capacity(len);
+
+                        buf.push(Users::SEP);
+                        let mut count = 1; // already read the '$'
+                        // Read `len` characters of PasswordHash 
+                        while let Some(ch) = chars.next() {
+                            buf.push(ch);
+                            count += 1;
+                            if count == len {
+                                break
+                            }
+                        }
+                        if count != len {
+                            // File ended before enough hash characters could be read
+                            return Err(LoadUsersError::IncompleteHash)
+                        }
+
+                        // After each entry there should be a line to separate Users
+                        match chars.next() {
+                            Some(Self::ENTRY_SEP) | None => {},
+                            Some(_) => return Err(LoadUsersError::InvalidEntrySep(ch))
+                        }
+
+                        db.insert(builder.name, PasswordHash::new(&buf)?.serialize());
+                        builder = Builder::default();
+                        buf = String::new();
+                        state = None
+                    }
+                }
+
+                continue
+            }
+            match state {
+                // When entry does not start with `Users::SEP`
+                None => return Err(LoadUsersError::InvalidEntry),
+                // If reading UserName, make sure it has valid chars
+                Some(State::Name) =>
+                    if !helpers::validate_username_char(ch) {
+                        return Err(LoadUsersError::InvalidUserName(ch.into()))
+                    }
+                _ => {}
+            }
+
+            buf.push(ch);
+        }
+
+        Ok(db)
+    }
+}
+#[rocket::async_trait]
+impl UserStore for FileStore {
+    async fn get_user(&self, username: &str) -> io::Result<Option<Account>> {
+        Ok(self.db.lock().await.get(username).cloned())
+    }
+
+    async fn list_users(&self) -> io::Result<Vec<(String, Account)>> {
+        let mut accounts = self.db.lock().await
+            .iter()
+            .map(|(name, account)| (name.clone(), account.clone()))
+            .collect::<Vec<_>>();
+        accounts.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(accounts)
+    }
+
+    async fn insert_user(&self, username: &str, account: &Account) -> io::Result<bool> {
+        let db = &mut *self.db.lock().await;
+        if db.contains_key(username) {
+            return Ok(false)
+        }
+        db.insert(username.to_string(), account.clone());
+
+        // Undo the change if it can't be saved
+        if let Err(error) = self.save(db).await {
+            db.remove(username);
+            return Err(error)
+        }
+        Ok(true)
+    }
+
+    async fn update_user(&self, username: &str, account: &Account) -> io::Result<bool> {
+        let db = &mut *self.db.lock().await;
+        let old = match db.get_mut(username) {
+            Some(old) => std::mem::replace(old, account.clone()),
+            None => return Ok(false)
+        };
+
+        // Undo the change if it can't be saved
+        if let Err(error) = self.save(db).await {
+            db.insert(username.to_string(), old);
+            return Err(error)
+        }
+        Ok(true)
+    }
+
+    async fn delete_user(&self, username: &str) -> io::Result<bool> {
+        let db = &mut *self.db.lock().await;
+        let old = match db.remove(username) {
+            Some(old) => old,
+            None => return Ok(false)
+        };
+
+        // Undo the change if it can't be saved
+        if let Err(error) = self.save(db).await {
+            db.insert(username.to_string(), old);
+            return Err(error)
+        }
+        Ok(true)
+    }
+
+    async fn get_session(&self, uuid: &str) -> io::Result<Option<Session>> {
+        Ok(self.sessions.lock().await.map.get(uuid).cloned())
+    }
+
+    async fn list_sessions(&self) -> io::Result<Vec<Session>> {
+        Ok(self.sessions.lock().await.map.values().cloned().collect())
+    }
+
+    async fn insert_session(&self, session: &Session) -> io::Result<()> {
+        let state = &mut *self.sessions.lock().await;
+        state.map.insert(session.uuid.clone(), session.clone());
+        self.save_sessions(state).await
+    }
+
+    async fn touch_session(&self, uuid: &str, last_seen: DateTime<Utc>) -> io::Result<()> {
+        let state = &mut *self.sessions.lock().await;
+        if let Some(session) = state.map.get_mut(uuid) {
+            session.last_seen = last_seen;
+            state.dirty = true;
+        }
+        Ok(())
+    }
+
+    async fn delete_session(&self, uuid: &str) -> io::Result<()> {
+        let state = &mut *self.sessions.lock().await;
+        if state.map.remove(uuid).is_some() {
+            self.save_sessions(state).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_user_sessions(&self, username: &str) -> io::Result<()> {
+        let state = &mut *self.sessions.lock().await;
+
+        let len = state.map.len();
+        state.map.retain(|_, session| session.user != username);
+
+        if state.map.len() != len {
+            self.save_sessions(state).await?;
+        }
+        Ok(())
+    }
+
+    async fn prune_sessions(&self, expired: DateTime<Utc>, idle: DateTime<Utc>) -> io::Result<()> {
+        let state = &mut *self.sessions.lock().await;
+
+        let len = state.map.len();
+        state.map.retain(|_, session| session.expires > expired && session.last_seen > idle);
+
+        if state.dirty || state.map.len() != len {
+            self.save_sessions(state).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> io::Result<()> {
+        let state = &mut *self.sessions.lock().await;
+        if state.dirty {
+            self.save_sessions(state).await?;
+        }
+        Ok(())
+    }
+}
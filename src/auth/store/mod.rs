@@ -0,0 +1,73 @@
+pub mod file;
+pub mod sqlite;
+
+use std::{io, path::PathBuf};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use super::{db::Account, sessions::Session};
+
+pub use file::FileStore;
+pub use sqlite::SqliteStore;
+
+
+/// Where [`Users`](super::db::Users) keeps the accounts and sessions.
+///
+/// A store only saves and loads data; the checks (e.g. passwords, expiry) are done by [`Users`](super::db::Users) and [`Sessions`](super::sessions::Sessions).
+/// Changes to an account are serialized by [`Users`](super::db::Users),
+/// so a store does not need to guard against two updates of the same user racing each other.
+#[rocket::async_trait]
+pub trait UserStore: Send + Sync + std::fmt::Debug {
+    async fn get_user(&self, username: &str) -> io::Result<Option<Account>>;
+    /// All users, sorted by `user id`.
+    async fn list_users(&self) -> io::Result<Vec<(String, Account)>>;
+    /// Returns `false` if a user with **username** already exists.
+    async fn insert_user(&self, username: &str, account: &Account) -> io::Result<bool>;
+    /// Replaces an existing user. Returns `false` if there is no user with **username**.
+    async fn update_user(&self, username: &str, account: &Account) -> io::Result<bool>;
+    /// Returns `false` if there is no user with **username**.
+    async fn delete_user(&self, username: &str) -> io::Result<bool>;
+
+    async fn get_session(&self, uuid: &str) -> io::Result<Option<Session>>;
+    async fn list_sessions(&self) -> io::Result<Vec<Session>>;
+    async fn insert_session(&self, session: &Session) -> io::Result<()>;
+    /// Records that the session was used. The store may keep this in memory until [`Self::flush()`].
+    async fn touch_session(&self, uuid: &str, last_seen: DateTime<Utc>) -> io::Result<()>;
+    async fn delete_session(&self, uuid: &str) -> io::Result<()>;
+    /// Deletes all the sessions of **username**.
+    async fn delete_user_sessions(&self, username: &str) -> io::Result<()>;
+    /// Deletes the sessions that expire at or before **expired**, or were last seen at or before **idle**.
+    async fn prune_sessions(&self, expired: DateTime<Utc>, idle: DateTime<Utc>) -> io::Result<()>;
+    /// Writes any changes that are only kept in memory.
+    async fn flush(&self) -> io::Result<()>;
+}
+
+/// Which [`UserStore`] the server uses.
+/// Set in the `auth.store` table of the server's config (e.g. `Rocket.toml`), e.g.
+///
+/// ```toml
+/// [default.auth.store]
+/// backend = "sqlite"
+/// path = ".secrets/db/users.sqlite3"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum StoreConfig {
+    /// [`FileStore`] at `.secrets/db/users`.
+    #[default]
+    File,
+    /// [`SqliteStore`] with the database at **path**.
+    Sqlite {
+        #[serde(default = "StoreConfig::default_sqlite_path")]
+        path: PathBuf
+    },
+}
+impl StoreConfig {
+    fn default_sqlite_path() -> PathBuf {
+        PathBuf::from(".secrets/db/users.sqlite3")
+    }
+}
+
+/// Errors of other libraries are returned as [`io::Error`]s, like the errors of the [`FileStore`].
+pub(super) fn other_error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::other(error)
+}
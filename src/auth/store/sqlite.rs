@@ -0,0 +1,280 @@
+use std::{
+    io,
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use argon2::PasswordHash;
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use crate::auth::{
+    db::{Account, AccountInfo, ADMIN_USR_ID, ADMIN_GROUP},
+    sessions::{Session, ClientInfo},
+};
+use super::{UserStore, other_error};
+
+
+/// Keeps the users and sessions in an embedded *SQLite* database,
+/// so that a change only writes the rows it touches.
+///
+/// The queries run on a blocking thread, since *SQLite* waits for the disk (and for other connections' locks).
+/// Like the [`FileStore`](super::FileStore), changes to [`Session::last_seen`] are only kept in memory until the next [`UserStore::flush()`].
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+    /// `HashMap<SessionUuid, LastSeen>` of the sessions that were used since the last flush.
+    touched: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+impl SqliteStore {
+    /// Version of the database schema, stored in its `user_version`.
+    ///
+    /// - `1`: users and sessions.
+    /// - `2`: sessions have the `ip` and `user_agent` of their client.
+    const VERSION: u32 = 2;
+
+    /// Opens (or creates) the database at **path**.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).map_err(other_error)?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> io::Result<Self> {
+        let version = conn.query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0))
+            .map_err(other_error)?;
+        if version > Self::VERSION {
+            return Err(other_error(format!("users database has version {version}, but this server only supports up to version {}", Self::VERSION)))
+        }
+
+        // Older databases are migrated, new ones get the full schema below
+        if version == 1 {
+            conn.execute_batch("
+                ALTER TABLE sessions ADD COLUMN ip TEXT;
+                ALTER TABLE sessions ADD COLUMN user_agent TEXT;
+            ").map_err(other_error)?;
+        }
+
+        conn.execute_batch(&format!("
+            PRAGMA journal_mode = WAL;
+            PRAGMA foreign_keys = ON;
+            CREATE TABLE IF NOT EXISTS users (
+                name TEXT PRIMARY KEY NOT NULL,
+                hash TEXT NOT NULL,
+                -- AccountInfo in JSON format
+                info TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS sessions (
+                uuid TEXT PRIMARY KEY NOT NULL,
+                user TEXT NOT NULL REFERENCES users(name) ON DELETE CASCADE,
+                -- Times are unix timestamps in milliseconds
+                created INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                expires INTEGER NOT NULL,
+                ip TEXT,
+                user_agent TEXT
+            );
+            CREATE INDEX IF NOT EXISTS sessions_user ON sessions(user);
+            PRAGMA user_version = {};
+        ", Self::VERSION)).map_err(other_error)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            touched: Mutex::default(),
+        })
+    }
+
+    async fn with_conn<T: Send + 'static>(&self, f: impl FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static) -> io::Result<T> {
+        let conn = Arc::clone(&self.conn);
+        rocket::tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock()
+                .map_err(|_| other_error("users database connection was poisoned"))?;
+            f(&mut conn).map_err(other_error)
+        }).await.map_err(other_error)?
+    }
+
+    fn touched(&self) -> MutexGuard<'_, HashMap<String, DateTime<Utc>>> {
+        // Only holds timestamps, which are still valid after a panic
+        self.touched.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Uses the [`last_seen`](Session::last_seen) that wasn't written yet, if the session was used since the last flush.
+    fn with_touched(&self, mut session: Session) -> Session {
+        if let Some(&last_seen) = self.touched().get(&session.uuid) {
+            session.last_seen = last_seen;
+        }
+        session
+    }
+
+    fn account_from_row(row: &Row) -> rusqlite::Result<(String, String, String)> {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    }
+    fn parse_account((name, hash, info): (String, String, String)) -> io::Result<(String, Account)> {
+        let hash = PasswordHash::new(&hash).map_err(|error| other_error(error.to_string()))?.serialize();
+        let mut info = serde_json::from_str::<AccountInfo>(&info)?;
+        if name == ADMIN_USR_ID {
+            info.groups.insert(ADMIN_GROUP.to_string());
+        }
+        Ok((name, Account { hash, info }))
+    }
+    fn info_json(account: &Account) -> String {
+        serde_json::to_string(&account.info).expect("AccountInfo can always be serialized")
+    }
+
+    fn session_from_row(row: &Row) -> rusqlite::Result<Session> {
+        Ok(Session {
+            uuid: row.get(0)?,
+            user: row.get(1)?,
+            created: from_millis(row.get(2)?),
+            last_seen: from_millis(row.get(3)?),
+            expires: from_millis(row.get(4)?),
+            client: ClientInfo {
+                // An invalid address is dropped instead of failing the whole session
+                ip: row.get::<_, Option<String>>(5)?.and_then(|ip| ip.parse().ok()),
+                user_agent: row.get(6)?,
+            },
+        })
+    }
+}
+#[rocket::async_trait]
+impl UserStore for SqliteStore {
+    async fn get_user(&self, username: &str) -> io::Result<Option<Account>> {
+        let username = username.to_string();
+        self.with_conn(move |conn| conn
+            .query_row("SELECT name, hash, info FROM users WHERE name = ?1", [username], Self::account_from_row)
+            .optional()
+        ).await?
+            .map(Self::parse_account)
+            .transpose()
+            .map(|account| account.map(|(_, account)| account))
+    }
+
+    async fn list_users(&self) -> io::Result<Vec<(String, Account)>> {
+        self.with_conn(|conn| conn
+            .prepare("SELECT name, hash, info FROM users ORDER BY name")?
+            .query_map([], Self::account_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        ).await?
+            .into_iter()
+            .map(Self::parse_account)
+            .collect()
+    }
+
+    async fn insert_user(&self, username: &str, account: &Account) -> io::Result<bool> {
+        let (username, hash, info) = (username.to_string(), account.hash.to_string(), Self::info_json(account));
+        let inserted = self.with_conn(move |conn| conn.execute(
+            "INSERT OR IGNORE INTO users (name, hash, info) VALUES (?1, ?2, ?3)",
+            params![username, hash, info]
+        )).await?;
+        Ok(inserted == 1)
+    }
+
+    async fn update_user(&self, username: &str, account: &Account) -> io::Result<bool> {
+        let (username, hash, info) = (username.to_string(), account.hash.to_string(), Self::info_json(account));
+        let updated = self.with_conn(move |conn| conn.execute(
+            "UPDATE users SET hash = ?2, info = ?3 WHERE name = ?1",
+            params![username, hash, info]
+        )).await?;
+        Ok(updated == 1)
+    }
+
+    async fn delete_user(&self, username: &str) -> io::Result<bool> {
+        let username = username.to_string();
+        // The user's sessions are deleted by the foreign key
+        let deleted = self.with_conn(move |conn| conn.execute("DELETE FROM users WHERE name = ?1", [username])).await?;
+        Ok(deleted == 1)
+    }
+
+    async fn get_session(&self, uuid: &str) -> io::Result<Option<Session>> {
+        let uuid = uuid.to_string();
+        let session = self.with_conn(move |conn| conn
+            .query_row("SELECT uuid, user, created, last_seen, expires, ip, user_agent FROM sessions WHERE uuid = ?1", [uuid], Self::session_from_row)
+            .optional()
+        ).await?;
+        Ok(session.map(|session| self.with_touched(session)))
+    }
+
+    async fn list_sessions(&self) -> io::Result<Vec<Session>> {
+        let sessions = self.with_conn(|conn| conn
+            .prepare("SELECT uuid, user, created, last_seen, expires, ip, user_agent FROM sessions")?
+            .query_map([], Self::session_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        ).await?;
+        Ok(sessions.into_iter().map(|session| self.with_touched(session)).collect())
+    }
+
+    async fn insert_session(&self, session: &Session) -> io::Result<()> {
+        let session = session.clone();
+        self.with_conn(move |conn| conn.execute(
+            "INSERT INTO sessions (uuid, user, created, last_seen, expires, ip, user_agent) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                session.uuid,
+                session.user,
+                session.created.timestamp_millis(),
+                session.last_seen.timestamp_millis(),
+                session.expires.timestamp_millis(),
+                session.client.ip.map(|ip| ip.to_string()),
+                session.client.user_agent
+            ]
+        )).await.map(|_| ())
+    }
+
+    async fn touch_session(&self, uuid: &str, last_seen: DateTime<Utc>) -> io::Result<()> {
+        // Written by the next flush, since this happens on every request
+        self.touched().insert(uuid.to_string(), last_seen);
+        Ok(())
+    }
+
+    async fn delete_session(&self, uuid: &str) -> io::Result<()> {
+        self.touched().remove(uuid);
+        let uuid = uuid.to_string();
+        self.with_conn(move |conn| conn.execute("DELETE FROM sessions WHERE uuid = ?1", [uuid])).await.map(|_| ())
+    }
+
+    async fn delete_user_sessions(&self, username: &str) -> io::Result<()> {
+        // The sessions left in `touched` are only updates of rows that no longer exist
+        let username = username.to_string();
+        self.with_conn(move |conn| conn.execute("DELETE FROM sessions WHERE user = ?1", [username])).await.map(|_| ())
+    }
+
+    async fn prune_sessions(&self, expired: DateTime<Utc>, idle: DateTime<Utc>) -> io::Result<()> {
+        // Sessions that were used since the last flush are not idle
+        self.flush().await?;
+        self.with_conn(move |conn| conn.execute(
+            "DELETE FROM sessions WHERE expires <= ?1 OR last_seen <= ?2",
+            params![expired.timestamp_millis(), idle.timestamp_millis()]
+        )).await.map(|_| ())
+    }
+
+    async fn flush(&self) -> io::Result<()> {
+        let touched = std::mem::take(&mut *self.touched());
+        if touched.is_empty() {
+            return Ok(())
+        }
+
+        let written = touched.clone();
+        let result = self.with_conn(move |conn| {
+            let transaction = conn.transaction()?;
+            {
+                let mut update = transaction.prepare("UPDATE sessions SET last_seen = ?2 WHERE uuid = ?1")?;
+                for (uuid, last_seen) in &written {
+                    update.execute(params![uuid, last_seen.timestamp_millis()])?;
+                }
+            }
+            transaction.commit()
+        }).await;
+
+        if result.is_err() {
+            // Still valid in memory, so try again on the next flush, unless the session was used again since
+            let mut pending = self.touched();
+            for (uuid, last_seen) in touched {
+                pending.entry(uuid).or_insert(last_seen);
+            }
+        }
+        result
+    }
+}
+
+fn from_millis(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis).single().unwrap_or_default()
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::HEXLOWER;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+
+/// What a request authenticated with an [`ApiToken`] is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromFormField)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    /// Acts as the user, but without admin rights (e.g. to download from the archives).
+    User,
+    /// Also has the user's admin rights, if they have any.
+    Admin,
+}
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+/// A personal access token, which scripts send in the `Authorization: Bearer <token>` header instead of logging in.
+/// Stored in the owner's [`AccountInfo`](super::db::AccountInfo).
+///
+/// The token given to the user is `"{user id}.{secret}"`, and only a hash of the secret is stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Identifies the token in pages and forms.
+    pub id: String,
+    /// Chosen by the user, to remember what the token is for.
+    pub name: String,
+    pub scope: TokenScope,
+    /// *SHA-256* hash (in hex) of the secret.
+    /// The secret is random, so unlike passwords it doesn't need a slow hash.
+    pub hash: String,
+    pub created: DateTime<Utc>,
+    /// Tokens without an expiry are valid until they are revoked.
+    pub expires: Option<DateTime<Utc>>,
+}
+impl ApiToken {
+    /// The character between the `user id` and the secret. Can't be in a username.
+    pub const SEP: char = '.';
+    const SECRET_LEN: usize = 40;
+    const ID_LEN: usize = 8;
+
+    /// Creates a token for **username**, returning it and the token to give to the user.
+    /// The token can't be recovered later, so it must be shown to the user now.
+    pub fn new(username: &str, name: String, scope: TokenScope, lifetime: Option<Duration>) -> (Self, String) {
+        let secret = random_string(Self::SECRET_LEN);
+        let now = Utc::now();
+        let token = Self {
+            id: random_string(Self::ID_LEN),
+            name,
+            scope,
+            hash: Self::hash_secret(&secret),
+            created: now,
+            expires: lifetime.map(|lifetime| now + lifetime),
+        };
+        (token, format!("{username}{}{secret}", Self::SEP))
+    }
+
+    /// Splits the token given by a client into the `user id` and the secret.
+    pub fn parse(token: &str) -> Option<(&str, &str)> {
+        token.trim().split_once(Self::SEP)
+    }
+
+    #[inline]
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+
+    /// Whether this token has the **secret** and has not expired.
+    pub fn verify(&self, secret: &str, now: DateTime<Utc>) -> bool {
+        !self.is_expired(now) && self.hash == Self::hash_secret(secret)
+    }
+
+    fn hash_secret(secret: &str) -> String {
+        HEXLOWER.encode(&Sha256::digest(secret.as_bytes()))
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
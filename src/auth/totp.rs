@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use serde::{Serialize, Deserialize};
+use sha1::Sha1;
+use sha2::{Sha256, Digest};
+use subtle::ConstantTimeEq;
+
+
+/// Time-based one-time passwords ([RFC 6238](https://www.rfc-editor.org/rfc/rfc6238)),
+/// with the parameters that authenticator apps use by default (*SHA-1*, 6 digits, 30 second steps).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Totp {
+    secret: Vec<u8>,
+}
+impl Totp {
+    /// Seconds that each code is valid for.
+    pub const STEP: i64 = 30;
+    pub const DIGITS: u32 = 6;
+    /// Number of random bytes in a generated secret (160 bits, as recommended by RFC 4226).
+    const SECRET_LEN: usize = 20;
+
+    pub fn generate() -> Self {
+        let mut secret = vec![0; Self::SECRET_LEN];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self { secret }
+    }
+
+    /// Parses a *base32* encoded secret, as shown to the user.
+    pub fn from_base32(secret: &str) -> Option<Self> {
+        let secret = BASE32_NOPAD.decode(secret.trim_end_matches('=').as_bytes()).ok()?;
+        (!secret.is_empty()).then_some(Self { secret })
+    }
+
+    /// The secret encoded in *base32*, which the user can enter into their authenticator app.
+    pub fn base32(&self) -> String {
+        BASE32_NOPAD.encode(&self.secret)
+    }
+
+    /// The `otpauth://` URI that authenticator apps use to enroll the secret (usually shown as a QR code).
+    pub fn uri(&self, issuer: &str, username: &str) -> String {
+        use rocket::http::RawStr;
+        let issuer = RawStr::new(issuer).percent_encode();
+        let username = RawStr::new(username).percent_encode();
+        format!("otpauth://totp/{issuer}:{username}?secret={}&issuer={issuer}", self.base32())
+    }
+
+    /// The time step that **time** falls in.
+    pub fn step(time: DateTime<Utc>) -> u64 {
+        (time.timestamp() / Self::STEP) as u64
+    }
+
+    /// The code for time **step** (see [`Self::step()`]).
+    pub fn code(&self, step: u64) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(&self.secret)
+            .expect("HMAC can take a key of any size");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        // Dynamic truncation (RFC 4226 section 5.3)
+        let offset = (hash[hash.len() - 1] & 0xf) as usize;
+        let bin = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+        format!("{:0width$}", bin % 10u32.pow(Self::DIGITS), width = Self::DIGITS as usize)
+    }
+
+    /// Checks **code** at **now**, also allowing the previous and next step to account for clock drift.
+    /// Returns the step the code belongs to, which must be later than **last_step** so that a code can't be used twice.
+    /// The codes are compared in constant time, so that the time taken doesn't tell how many digits are right.
+    pub fn verify(&self, code: &str, now: DateTime<Utc>, last_step: Option<u64>) -> Option<u64> {
+        let code = code.trim();
+        let now = Self::step(now);
+
+        [now.saturating_sub(1), now, now + 1].into_iter()
+            .filter(|step| last_step.is_none_or(|last| *step > last))
+            .find(|step| bool::from(self.code(*step).as_bytes().ct_eq(code.as_bytes())))
+    }
+}
+
+/// The second factor of a user, stored in their [`AccountInfo`](super::db::AccountInfo).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TotpInfo {
+    /// The [`Totp`] secret in *base32*.
+    pub secret: String,
+    /// *SHA-256* hashes (in hex) of the unused recovery codes.
+    /// Recovery codes are random, so unlike passwords they don't need a slow hash.
+    pub recovery: Vec<String>,
+    /// The step of the last code that was used (see [`Totp::verify()`]).
+    pub last_step: Option<u64>,
+}
+impl TotpInfo {
+    pub const RECOVERY_CODES: usize = 8;
+    const RECOVERY_CODE_LEN: usize = 10;
+
+    /// Enables **totp** for a user, returning the info and the recovery codes in plain text.
+    /// The recovery codes can't be recovered later, so they must be shown to the user now.
+    pub fn new(totp: &Totp) -> (Self, Vec<String>) {
+        let codes = (0..Self::RECOVERY_CODES)
+            .map(|_| rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(Self::RECOVERY_CODE_LEN)
+                .map(char::from)
+                .collect::<String>()
+                .to_lowercase()
+            )
+            .collect::<Vec<_>>();
+        let info = Self {
+            secret: totp.base32(),
+            recovery: codes.iter().map(|code| Self::hash_recovery_code(code)).collect(),
+            last_step: None,
+        };
+        (info, codes)
+    }
+
+    pub fn totp(&self) -> Option<Totp> {
+        Totp::from_base32(&self.secret)
+    }
+
+    /// Checks **code** as either a [`Totp`] code or a recovery code.
+    /// A used code is consumed, so the caller must save the changes to the info.
+    pub fn verify(&mut self, code: &str, now: DateTime<Utc>) -> bool {
+        if let Some(step) = self.totp().and_then(|totp| totp.verify(code, now, self.last_step)) {
+            self.last_step = Some(step);
+            return true
+        }
+
+        let hash = Self::hash_recovery_code(&code.trim().to_lowercase());
+        match self.recovery.iter().position(|recovery| bool::from(recovery.as_bytes().ct_eq(hash.as_bytes()))) {
+            Some(index) => {
+                self.recovery.remove(index);
+                true
+            },
+            None => false
+        }
+    }
+
+    fn hash_recovery_code(code: &str) -> String {
+        HEXLOWER.encode(&Sha256::digest(code.as_bytes()))
+    }
+}
+
+/// Two-factor authentication settings.
+/// Set in the `auth.totp` table of the server's config (e.g. `Rocket.toml`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TotpConfig {
+    /// Name of the server shown in the user's authenticator app.
+    pub issuer: String,
+    /// Users in the [`ADMIN_GROUP`](super::db::ADMIN_GROUP) lose their admin rights until they enable 2FA,
+    /// and can't disable it.
+    pub require_for_admin: bool,
+}
+impl Default for TotpConfig {
+    fn default() -> Self {
+        Self {
+            issuer: "rocket-server".to_string(),
+            require_for_admin: false,
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use chrono::{DateTime, Utc};
+use yew::prelude::*;
+use std::path::PathBuf;
+use super::{Document, UserInfo, CsrfField, admin::Flash, find_pfp};
+
+
+#[derive(Properties, PartialEq)]
+pub struct ProfileProps {
+    pub user: UserInfo,
+    /// The large size of the user's picture (see [`crate::auth::pfp::path()`]).
+    pub pfp: Option<PathBuf>,
+    pub flash: Flash,
+    /// See [`CsrfField`].
+    pub csrf: String,
+}
+/// Where a user changes their profile picture.
+#[function_component]
+pub fn ProfilePage(props: &ProfileProps) -> Html {
+    html! {
+        <Document title="Profile" header={ props.user.clone() }>
+            <link rel="stylesheet" href="/account/style.css"/>
+            <h1>{ "Profile" }</h1>
+            <p id="flash-msg" class={ props.flash.kind.clone() }>{ &props.flash.msg }</p>
+            <div id="profile" class="horizontal-wrapper">
+                <div class="pfp-wrapper">{ find_pfp(&props.pfp) }</div>
+                <div class="vertical-wrapper">
+                    <form action="/account/profile/picture" method="post" enctype="multipart/form-data">
+                        <CsrfField csrf={ props.csrf.clone() }/>
+                        <label for="picture">{ "Profile picture (PNG, JPEG, GIF or WEBP): " }</label>
+                        <input type="file" name="picture" id="picture" accept="image/png,image/jpeg,image/gif,image/webp"/>
+                        <input type="submit" value="Upload"/>
+                    </form>
+                    if props.pfp.is_some() {
+                        <form action="/account/profile/picture/remove" method="post">
+                            <CsrfField csrf={ props.csrf.clone() }/>
+                            <input type="submit" class="danger" value="Remove picture"/>
+                        </form>
+                    }
+                </div>
+            </div>
+        </Document>
+    }
+}
+
+#[derive(PartialEq)]
+pub struct SessionItem {
+    /// See [`Session::id()`](crate::auth::sessions::Session::id).
+    pub id: String,
+    /// The session of the client viewing the page.
+    pub current: bool,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SessionsProps {
+    pub user: UserInfo,
+    pub sessions: Vec<SessionItem>,
+    pub flash: Flash,
+    /// See [`CsrfField`].
+    pub csrf: String,
+}
+/// Where a user sees the places they are logged in, and can log out of them.
+#[function_component]
+pub fn SessionsPage(props: &SessionsProps) -> Html {
+    let others = props.sessions.iter().any(|session| !session.current);
+
+    html! {
+        <Document title="Sessions" header={ props.user.clone() }>
+            <link rel="stylesheet" href="/account/style.css"/>
+            <h1>{ "Sessions" }</h1>
+            <p id="flash-msg" class={ props.flash.kind.clone() }>{ &props.flash.msg }</p>
+            <ul id="sessions">{
+                props.sessions.iter()
+                    .map(|session| session_item(session, &props.csrf))
+                    .collect::<Html>()
+            }</ul>
+            <div id="session-actions" class="horizontal-wrapper">
+                <form action="/account/sessions/revoke-others" method="post">
+                    <CsrfField csrf={ props.csrf.clone() }/>
+                    <input type="submit" value="Log out everywhere else" disabled={ !others }/>
+                </form>
+                <form action="/account/sessions/revoke-all" method="post">
+                    <CsrfField csrf={ props.csrf.clone() }/>
+                    <input type="submit" class="danger" value="Log out everywhere"/>
+                </form>
+            </div>
+        </Document>
+    }
+}
+fn session_item(session: &SessionItem, csrf: &str) -> Html {
+    html! {
+        <li class={ classes!("item", "session-item", "vertical-wrapper", session.current.then_some("current")) }>
+            <div class="horizontal-wrapper">
+                <span class="user-agent">{ session.user_agent.as_deref().unwrap_or("Unknown browser") }</span>
+                if session.current {
+                    <span class="status">{ "This device" }</span>
+                }
+            </div>
+            <div class="stats horizontal-wrapper">
+                <span class="ip">{ format!("IP: {}", session.ip.as_deref().unwrap_or("unknown")) }</span>
+                <span class="created">{ format!("Logged in: {}", session.created.format("%Y-%m-%d %H:%M UTC")) }</span>
+                <span class="last-seen">{ format!("Last seen: {}", session.last_seen.format("%Y-%m-%d %H:%M UTC")) }</span>
+            </div>
+            <form action={ format!("/account/sessions/{}/revoke", session.id) } method="post">
+                <CsrfField csrf={ csrf.to_string() }/>
+                <input type="submit" class="danger" value="Log out"/>
+            </form>
+        </li>
+    }
+}
+
+#[derive(PartialEq)]
+pub struct TokenItem {
+    pub id: String,
+    pub name: String,
+    /// See [`TokenScope`](crate::auth::tokens::TokenScope).
+    pub scope: &'static str,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct TokensProps {
+    pub user: UserInfo,
+    pub tokens: Vec<TokenItem>,
+    /// Only admins can create tokens with the admin scope.
+    pub is_admin: bool,
+    /// A token that was just created, which can only be shown this once.
+    pub new_token: Option<String>,
+    pub flash: Flash,
+    /// See [`CsrfField`].
+    pub csrf: String,
+}
+/// Where a user manages the personal access tokens that their scripts use.
+#[function_component]
+pub fn TokensPage(props: &TokensProps) -> Html {
+    html! {
+        <Document title="Access tokens" header={ props.user.clone() }>
+            <link rel="stylesheet" href="/account/style.css"/>
+            <h1>{ "Access tokens" }</h1>
+            <p>{ "Scripts can use a token with the header " }<code>{ "Authorization: Bearer <token>" }</code>{ "." }</p>
+            <p id="flash-msg" class={ props.flash.kind.clone() }>{ &props.flash.msg }</p>
+            if let Some(token) = &props.new_token {
+                <pre id="new-token"><code>{ token }</code></pre>
+            }
+            <form id="create-token" class="horizontal-wrapper" action="/account/tokens" method="post">
+                <CsrfField csrf={ props.csrf.clone() }/>
+                <label for="token-name">{ "Name: " }</label>
+                <input type="text" name="name" id="token-name" maxlength="64"/>
+                <label for="token-scope">{ "Scope: " }</label>
+                <select name="scope" id="token-scope">
+                    <option value="user" selected=true>{ "User" }</option>
+                    if props.is_admin {
+                        <option value="admin">{ "Admin" }</option>
+                    }
+                </select>
+                <label for="token-days">{ "Expires in (days, 0 for never): " }</label>
+                <input type="number" name="days" id="token-days" min="0" max="3650" value="90"/>
+                <input type="submit" value="Create token"/>
+            </form>
+            <ul id="tokens">{
+                props.tokens.iter()
+                    .map(|token| token_item(token, &props.csrf))
+                    .collect::<Html>()
+            }</ul>
+        </Document>
+    }
+}
+fn token_item(token: &TokenItem, csrf: &str) -> Html {
+    html! {
+        <li class="item token-item horizontal-wrapper">
+            <span class="name">{ &token.name }</span>
+            <span class="stats">{ format!("Scope: {}", token.scope) }</span>
+            <span class="stats">{ format!("Created: {}", token.created.format("%Y-%m-%d %H:%M UTC")) }</span>
+            <span class="stats">{
+                match &token.expires {
+                    Some(time) => format!("Expires: {}", time.format("%Y-%m-%d %H:%M UTC")),
+                    None => "Never expires".to_string()
+                }
+            }</span>
+            <form action={ format!("/account/tokens/{}/revoke", token.id) } method="post">
+                <CsrfField csrf={ csrf.to_string() }/>
+                <input type="submit" class="danger" value="Revoke"/>
+            </form>
+        </li>
+    }
+}
@@ -0,0 +1,268 @@
+use rocket::request::FlashMessage;
+use chrono::{DateTime, Utc};
+use yew::prelude::*;
+use crate::auth::audit::AuditEvent;
+use super::{Document, UserInfo, CsrfField, CsrfProps};
+
+
+/// A message flashed after an admin action (e.g. an error when saving a user).
+#[derive(PartialEq, Default, Clone)]
+pub struct Flash {
+    /// Either `"success"` or `"error"`.
+    pub kind: String,
+    pub msg: String
+}
+impl From<Option<FlashMessage<'_>>> for Flash {
+    fn from(value: Option<FlashMessage>) -> Self {
+        match value {
+            Some(value) => Self {
+                kind: value.kind().to_string(),
+                msg: value.message().to_string()
+            },
+            None => Self::default()
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub struct AccountItem {
+    pub name: String,
+    pub groups: Vec<String>,
+    pub disabled: bool,
+    /// Number of sessions where the user is logged in.
+    pub sessions: usize,
+    pub last_login: Option<DateTime<Utc>>,
+}
+
+#[derive(PartialEq)]
+pub struct InviteItem {
+    pub token: String,
+    pub link: String,
+    pub created_by: String,
+    pub expires: DateTime<Utc>,
+    pub group: Option<String>,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct DashboardProps {
+    pub user: UserInfo,
+    pub accounts: Vec<AccountItem>,
+    pub invites: Vec<InviteItem>,
+    pub flash: Flash,
+    /// See [`CsrfField`].
+    pub csrf: String,
+}
+#[function_component]
+pub fn Dashboard(props: &DashboardProps) -> Html {
+    html! {
+        <Document title="Admin" header={ props.user.clone() }>
+            <link rel="stylesheet" href="/admin/style.css"/>
+            <h1>{ "Users" }</h1>
+            <p id="flash-msg" class={ props.flash.kind.clone() }>{ &props.flash.msg }</p>
+            <p id="admin-links"><a href="/admin/audit">{ "Audit log" }</a></p>
+            <CreateUser csrf={ props.csrf.clone() }/>
+            <ul id="users">{
+                props.accounts.iter()
+                    .map(|account| account_item(account, &props.csrf))
+                    .collect::<Html>()
+            }</ul>
+            <h1>{ "Invites" }</h1>
+            <CreateInvite csrf={ props.csrf.clone() }/>
+            <ul id="invites">{
+                props.invites.iter()
+                    .map(|invite| invite_item(invite, &props.csrf))
+                    .collect::<Html>()
+            }</ul>
+        </Document>
+    }
+}
+fn account_item(account: &AccountItem, csrf: &str) -> Html {
+    html! {
+        <li class={ classes!("item", "user-item", "vertical-wrapper", account.disabled.then_some("disabled")) }>
+            <div class="horizontal-wrapper">
+                <span class="name">{ &account.name }</span>
+                if account.disabled {
+                    <span class="status">{ "Disabled" }</span>
+                }
+            </div>
+            <div class="stats horizontal-wrapper">
+                <span class="sessions">{ format!("Sessions: {}", account.sessions) }</span>
+                <span class="last-login">{
+                    match &account.last_login {
+                        Some(time) => format!("Last login: {}", time.format("%Y-%m-%d %H:%M UTC")),
+                        None => "Never logged in".to_string()
+                    }
+                }</span>
+            </div>
+            <form class="groups" action={ format!("/admin/users/{}/groups", account.name) } method="post">
+                <CsrfField csrf={ csrf.to_string() }/>
+                <label for={ format!("groups-{}", account.name) }>{ "Groups: " }</label>
+                <input type="text" name="groups" id={ format!("groups-{}", account.name) } value={ account.groups.join(", ") }/>
+                <input type="submit" value="Save"/>
+            </form>
+            <form class="password" action={ format!("/admin/users/{}/password", account.name) } method="post">
+                <CsrfField csrf={ csrf.to_string() }/>
+                <label for={ format!("password-{}", account.name) }>{ "New password: " }</label>
+                <input type="password" name="password" id={ format!("password-{}", account.name) }/>
+                <input type="submit" value="Reset password"/>
+            </form>
+            <div class="actions horizontal-wrapper">
+                if account.disabled {
+                    <form action={ format!("/admin/users/{}/enable", account.name) } method="post">
+                        <CsrfField csrf={ csrf.to_string() }/>
+                        <input type="submit" value="Enable"/>
+                    </form>
+                } else {
+                    <form action={ format!("/admin/users/{}/disable", account.name) } method="post">
+                        <CsrfField csrf={ csrf.to_string() }/>
+                        <input type="submit" value="Disable"/>
+                    </form>
+                }
+                <form action={ format!("/admin/users/{}/logout", account.name) } method="post">
+                    <CsrfField csrf={ csrf.to_string() }/>
+                    <input type="submit" value="Log out" disabled={ account.sessions == 0 }/>
+                </form>
+                <form action={ format!("/admin/users/{}/delete", account.name) } method="post">
+                    <CsrfField csrf={ csrf.to_string() }/>
+                    <input type="submit" class="danger" value="Delete"/>
+                </form>
+            </div>
+        </li>
+    }
+}
+
+/// Form for an admin to create an account for someone else.
+#[function_component]
+pub fn CreateUser(props: &CsrfProps) -> Html {
+    html! {
+        <form id="create-user" class="horizontal-wrapper" action="/admin/users" method="post">
+            <CsrfField csrf={ props.csrf.clone() }/>
+            <label for="new-username">{ "Username: " }</label>
+            <input type="text" name="username" id="new-username"/>
+            <label for="new-password">{ "Password: " }</label>
+            <input type="password" name="password" id="new-password"/>
+            <input type="submit" value="Create user"/>
+        </form>
+    }
+}
+
+fn invite_item(invite: &InviteItem, csrf: &str) -> Html {
+    html! {
+        <li class="item invite-item horizontal-wrapper">
+            <a href={ invite.link.clone() }>{ &invite.link }</a>
+            <span class="stats">{
+                format!("By {}, expires {}", invite.created_by, invite.expires.format("%Y-%m-%d %H:%M UTC"))
+            }</span>
+            if let Some(group) = &invite.group {
+                <span class="stats">{ format!("Group: {group}") }</span>
+            }
+            <form action={ format!("/admin/invites/{}/revoke", invite.token) } method="post">
+                <CsrfField csrf={ csrf.to_string() }/>
+                <input type="submit" class="danger" value="Revoke"/>
+            </form>
+        </li>
+    }
+}
+
+/// Form for an admin to create a link that lets someone register themselves.
+#[function_component]
+pub fn CreateInvite(props: &CsrfProps) -> Html {
+    html! {
+        <form id="create-invite" class="horizontal-wrapper" action="/admin/invites" method="post">
+            <CsrfField csrf={ props.csrf.clone() }/>
+            <label for="invite-group">{ "Group (optional): " }</label>
+            <input type="text" name="group" id="invite-group"/>
+            <label for="invite-days">{ "Expires in (days): " }</label>
+            <input type="number" name="days" id="invite-days" min="1" max="365" value="7"/>
+            <input type="submit" value="Create invite"/>
+        </form>
+    }
+}
+
+#[derive(PartialEq)]
+pub struct AuditItem {
+    pub time: DateTime<Utc>,
+    pub actor: Option<String>,
+    pub ip: Option<String>,
+    /// See [`AuditEvent::NAMES`].
+    pub name: &'static str,
+    pub target: Option<String>,
+    pub details: String,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct AuditProps {
+    pub user: UserInfo,
+    pub entries: Vec<AuditItem>,
+    /// Links to the previous and next pages, with the same filter.
+    pub prev: Option<String>,
+    pub next: Option<String>,
+    /// The event being filtered by, or empty for all events.
+    pub event: String,
+    /// The user being filtered by, or empty for all users.
+    pub filter_user: String,
+    pub flash: Flash,
+}
+/// Where an admin looks through the security events recorded in the audit log.
+#[function_component]
+pub fn AuditPage(props: &AuditProps) -> Html {
+    html! {
+        <Document title="Audit log" header={ props.user.clone() }>
+            <link rel="stylesheet" href="/admin/style.css"/>
+            <h1>{ "Audit log" }</h1>
+            <p id="flash-msg" class={ props.flash.kind.clone() }>{ &props.flash.msg }</p>
+            <form id="audit-filter" class="horizontal-wrapper" action="/admin/audit" method="get">
+                <label for="audit-event">{ "Event: " }</label>
+                <select name="event" id="audit-event">
+                    <option value="" selected={ props.event.is_empty() }>{ "All" }</option>
+                    { for AuditEvent::NAMES.iter().map(|name| html! {
+                        <option value={ *name } selected={ props.event == *name }>{ name.replace('_', " ") }</option>
+                    }) }
+                </select>
+                <label for="audit-user">{ "User: " }</label>
+                <input type="text" name="user" id="audit-user" value={ props.filter_user.clone() }/>
+                <input type="submit" value="Filter"/>
+            </form>
+            <table id="audit">
+                <thead>
+                    <tr>
+                        <th>{ "Time" }</th>
+                        <th>{ "User" }</th>
+                        <th>{ "IP" }</th>
+                        <th>{ "Event" }</th>
+                        <th>{ "Target" }</th>
+                        <th>{ "Details" }</th>
+                    </tr>
+                </thead>
+                <tbody>{
+                    props.entries.iter()
+                        .map(audit_item)
+                        .collect::<Html>()
+                }</tbody>
+            </table>
+            if props.entries.is_empty() {
+                <p class="stats">{ "No events" }</p>
+            }
+            <div id="audit-pages" class="horizontal-wrapper">
+                if let Some(prev) = &props.prev {
+                    <a href={ prev.clone() }>{ "Newer" }</a>
+                }
+                if let Some(next) = &props.next {
+                    <a href={ next.clone() }>{ "Older" }</a>
+                }
+            </div>
+        </Document>
+    }
+}
+fn audit_item(item: &AuditItem) -> Html {
+    html! {
+        <tr class={ classes!("audit-item", item.name) }>
+            <td class="stats">{ item.time.format("%Y-%m-%d %H:%M:%S UTC").to_string() }</td>
+            <td>{ item.actor.as_deref().unwrap_or("-") }</td>
+            <td class="stats">{ item.ip.as_deref().unwrap_or("unknown") }</td>
+            <td>{ item.name.replace('_', " ") }</td>
+            <td>{ item.target.as_deref().unwrap_or("") }</td>
+            <td class="stats">{ &item.details }</td>
+        </tr>
+    }
+}
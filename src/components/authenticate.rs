@@ -3,23 +3,51 @@ use rocket::request::FlashMessage;
 // use std::process::Command;
 // use std::fs;
 use yew::prelude::*;
-use super::Head;
+use crate::auth::csrf::CsrfToken;
+use super::{Head, CsrfField};
 
 
 #[derive(Properties, PartialEq)]
-pub struct AuthError { msg: String }
-impl From<Option<FlashMessage<'_>>> for AuthError {
-    fn from(value: Option<FlashMessage>) -> Self {
-        Self { msg: match &value {
-            Some(value) => value.message(),
-            None => ""
-        }.to_string() }
+pub struct AuthError {
+    msg: String,
+    /// See [`CsrfField`].
+    csrf: String,
+}
+impl AuthError {
+    pub fn new(error: Option<FlashMessage<'_>>, csrf: CsrfToken) -> Self {
+        Self { msg: message(error), csrf: csrf.0 }
     }
 }
 
+fn message(flash: Option<FlashMessage<'_>>) -> String {
+    match &flash {
+        Some(flash) => flash.message(),
+        None => ""
+    }.to_string()
+}
+
+
+#[derive(Properties, PartialEq)]
+pub struct LoginProps {
+    pub error: String,
+    /// Where the user returns after logging in (see [`safe_next()`](crate::auth::safe_next)).
+    #[prop_or_default]
+    pub next: Option<String>,
+    /// See [`CsrfField`].
+    pub csrf: String,
+}
+impl LoginProps {
+    pub fn new(error: Option<FlashMessage<'_>>, next: Option<&str>, csrf: CsrfToken) -> Self {
+        Self {
+            error: message(error),
+            next: crate::auth::safe_next(next).map(str::to_string),
+            csrf: csrf.0
+        }
+    }
+}
 
 #[function_component]
-pub fn Login(error: &AuthError) -> yew::Html {
+pub fn Login(props: &LoginProps) -> yew::Html {
     html! {
         <html lang="en">
             <Head title="Login">
@@ -28,8 +56,9 @@ pub fn Login(error: &AuthError) -> yew::Html {
             <body>
                 <main>
                     <h1>{ "Log in" }</h1>
-                    <p id="auth-error-msg">{ &error.msg }</p>
-                    <form action="login" method="post">
+                    <p id="auth-error-msg">{ &props.error }</p>
+                    <form action={ crate::auth::with_next("/login", props.next.as_deref()) } method="post">
+                        <CsrfField csrf={ props.csrf.clone() }/>
                         <div>
                             <label for="username">{ "Username: " }</label>
                             <input type="text" name="username" id="username"/>
@@ -47,18 +76,51 @@ pub fn Login(error: &AuthError) -> yew::Html {
     }
 }
 
+#[derive(Properties, PartialEq)]
+pub struct RegisterProps {
+    pub error: String,
+    /// Token of the invite the user is registering with.
+    /// Without one, only an admin can register users.
+    #[prop_or_default]
+    pub invite: Option<String>,
+    /// Where the admin returns after registering a user (see [`safe_next()`](crate::auth::safe_next)).
+    #[prop_or_default]
+    pub next: Option<String>,
+    /// See [`CsrfField`].
+    pub csrf: String,
+}
+impl RegisterProps {
+    pub fn new(error: Option<FlashMessage<'_>>, csrf: CsrfToken) -> Self {
+        Self {
+            error: message(error),
+            invite: None,
+            next: None,
+            csrf: csrf.0
+        }
+    }
+}
+
 #[function_component]
-pub fn Register(error: &AuthError) -> yew::Html {
+pub fn Register(props: &RegisterProps) -> yew::Html {
+    let (title, action) = match &props.invite {
+        Some(_) => ("Register", "/register/invite".to_string()),
+        None => ("Register (Requires admin approval)", crate::auth::with_next("/register", props.next.as_deref())),
+    };
+
     html! {
         <html lang="en">
             <Head title="Register">
-                <link rel="stylesheet" href="auth.css"/>
+                <link rel="stylesheet" href="/auth.css"/>
             </Head>
             <body>
                 <main>
-                    <h1>{ "Register (Requires admin approval)" }</h1>
-                    <p id="auth-error-msg">{ &error.msg }</p>
-                    <form action="register" method="post">
+                    <h1>{ title }</h1>
+                    <p id="auth-error-msg">{ &props.error }</p>
+                    <form action={ action } method="post">
+                        <CsrfField csrf={ props.csrf.clone() }/>
+                        if let Some(invite) = &props.invite {
+                            <input type="hidden" name="invite" value={ invite.clone() }/>
+                        }
                         <div>
                             <label for="username">{ "Username: " }</label>
                             <input type="text" name="username" id="username"/>
@@ -92,6 +154,7 @@ pub fn AdminRegister(error: &AuthError) -> yew::Html {
                     <p>{ "Choose a secure password for Admin. Preferably generated by your password manager." }</p>
                     <p id="auth-error-msg">{ &error.msg }</p>
                     <form action="admin-register" method="post">
+                        <CsrfField csrf={ error.csrf.clone() }/>
                         // <input type="text" name="username" id="username" value={ ADMIN_USR_ID } style="display: none"/>
                         <div>
                             <label for="password">{ "Password: " }</label>
@@ -106,6 +169,149 @@ pub fn AdminRegister(error: &AuthError) -> yew::Html {
     }
 }
 
+#[function_component]
+pub fn ChangePassword(error: &AuthError) -> yew::Html {
+    html! {
+        <html lang="en">
+            <Head title="Change Password">
+                <link rel="stylesheet" href="/auth.css"/>
+            </Head>
+            <body>
+                <main>
+                    <h1>{ "Change password" }</h1>
+                    <p>{ "You will be logged out of all your other sessions." }</p>
+                    <p id="auth-error-msg">{ &error.msg }</p>
+                    <form action="/account/password" method="post">
+                        <CsrfField csrf={ error.csrf.clone() }/>
+                        <div>
+                            <label for="current">{ "Current password: " }</label>
+                            <input type="password" name="current" id="current"/>
+                        </div>
+                        <div>
+                            <label for="new">{ "New password: " }</label>
+                            <input type="password" name="new" id="new"/>
+                        </div>
+                        <div>
+                            <label for="confirm">{ "Confirm new password: " }</label>
+                            <input type="password" name="confirm" id="confirm"/>
+                        </div>
+                        <p id="flash-msg" style="color: red"></p>
+                        <input type="submit" value="Change Password"/>
+                    </form>
+                </main>
+            </body>
+        </html>
+    }
+}
+
+/// Second step of the login, for users with two-factor authentication.
+#[function_component]
+pub fn TotpLogin(props: &LoginProps) -> yew::Html {
+    html! {
+        <html lang="en">
+            <Head title="Login">
+                <link rel="stylesheet" href="/auth.css"/>
+            </Head>
+            <body>
+                <main>
+                    <h1>{ "Two-factor authentication" }</h1>
+                    <p>{ "Enter the code from your authenticator app, or one of your recovery codes." }</p>
+                    <p id="auth-error-msg">{ &props.error }</p>
+                    <form action={ crate::auth::with_next("/login/totp", props.next.as_deref()) } method="post">
+                        <CsrfField csrf={ props.csrf.clone() }/>
+                        <div>
+                            <label for="code">{ "Code: " }</label>
+                            <input type="text" name="code" id="code" autocomplete="one-time-code"/>
+                        </div>
+                        <input type="submit" value="Log In"/>
+                    </form>
+                </main>
+            </body>
+        </html>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct TotpSetupProps {
+    /// Secret of the [`Totp`](crate::auth::totp::Totp) being enrolled, in *base32*.
+    /// [`None`] if the user already has 2FA enabled.
+    pub secret: Option<String>,
+    /// The `otpauth://` URI of the **secret**.
+    pub uri: String,
+    pub error: String,
+    /// See [`CsrfField`].
+    pub csrf: String,
+}
+/// Page where a user enables or disables two-factor authentication.
+#[function_component]
+pub fn TotpSetup(props: &TotpSetupProps) -> yew::Html {
+    html! {
+        <html lang="en">
+            <Head title="Two-factor authentication">
+                <link rel="stylesheet" href="/auth.css"/>
+            </Head>
+            <body>
+                <main>
+                    <h1>{ "Two-factor authentication" }</h1>
+                    <p id="auth-error-msg">{ &props.error }</p>
+                    if let Some(secret) = &props.secret {
+                        <p>{ "Add this secret to your authenticator app, then enter the code it shows." }</p>
+                        <p>{ "Secret: " }<code>{ secret }</code></p>
+                        <p><a href={ props.uri.clone() }>{ "Open in authenticator app" }</a></p>
+                        <form action="/account/2fa/enable" method="post">
+                            <CsrfField csrf={ props.csrf.clone() }/>
+                            <input type="hidden" name="secret" value={ secret.clone() }/>
+                            <div>
+                                <label for="code">{ "Code: " }</label>
+                                <input type="text" name="code" id="code" autocomplete="one-time-code"/>
+                            </div>
+                            <input type="submit" value="Enable"/>
+                        </form>
+                    } else {
+                        <p>{ "Two-factor authentication is enabled." }</p>
+                        <form action="/account/2fa/disable" method="post">
+                            <CsrfField csrf={ props.csrf.clone() }/>
+                            <div>
+                                <label for="password">{ "Password: " }</label>
+                                <input type="password" name="password" id="password"/>
+                            </div>
+                            <input type="submit" value="Disable"/>
+                        </form>
+                    }
+                </main>
+            </body>
+        </html>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct RecoveryCodesProps {
+    pub codes: Vec<String>,
+}
+/// Shown once after enabling two-factor authentication.
+#[function_component]
+pub fn RecoveryCodes(props: &RecoveryCodesProps) -> yew::Html {
+    html! {
+        <html lang="en">
+            <Head title="Recovery codes">
+                <link rel="stylesheet" href="/auth.css"/>
+            </Head>
+            <body>
+                <main>
+                    <h1>{ "Two-factor authentication enabled" }</h1>
+                    <p>{ "Save these recovery codes somewhere safe. Each can be used once to log in if you lose your authenticator app. They will not be shown again." }</p>
+                    <ul id="recovery-codes">{
+                        props.codes.iter()
+                            .map(|code| html! { <li><code>{ code }</code></li> })
+                            .collect::<yew::Html>()
+                    }</ul>
+                    <a href="/">{ "Done" }</a>
+                </main>
+            </body>
+        </html>
+    }
+}
+
 // #[tokio::main]
 // pub async fn static_render() {
 //     Command::new("mkdir")
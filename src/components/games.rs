@@ -4,29 +4,35 @@ use nonempty::NonEmpty;
 use rocket::Either;
 use yew::prelude::*;
 use crate::helpers::{display_separated, command_output};
-use crate::archives::{ Url, games::{GameInfo, PlatFile, GAMES_PATH, GameFile}};
+use crate::archives::{ Url, games::{GameInfo, PlatFile, GameFile}};
 use super::{Document, Icon, UserInfo, item_error, text_file};
 
 
+#[derive(Properties, PartialEq, Eq)]
+pub struct GamesBrowserProps {
+    pub user: UserInfo,
+    /// Only the games that the user can see.
+    pub games: Vec<GameInfo>,
+    /// Games that couldn't be read: `(dir_name, error)`.
+    pub errors: Vec<(String, String)>
+}
 #[function_component]
-pub fn GamesBrowser(props: &UserInfo) -> Html {
+pub fn GamesBrowser(props: &GamesBrowserProps) -> Html {
     html! {
-        <Document title="Games" header={props.clone()}>
+        <Document title="Games" header={props.user.clone()}>
             <link rel="stylesheet" href="/games/style.css"/>
             <h1>{ "Games" }</h1>
-            <ul id="albums">{{
-                let (games, errors) = crate::archives::read_all_dirs::<GameInfo>(&*GAMES_PATH);
-
-                errors.into_iter()
-                    .map(|(dir_name, error)| item_error(dir_name, error.to_string()))
-                    .chain(games.into_iter()
+            <ul id="albums">{
+                props.errors.iter()
+                    .map(|(dir_name, error)| item_error(dir_name.clone(), error.clone()))
+                    .chain(props.games.iter()
                         .map(games_browser_item))
                     .collect::<Html>()
-            }}</ul>
+            }</ul>
         </Document>
     }
 }
-fn games_browser_item(game: GameInfo) -> Html {
+fn games_browser_item(game: &GameInfo) -> Html {
     let game_url = PathBuf::from("/games/").join(&game.dir_name);
     html! {
         <li class="item horizontal-wrapper">
@@ -34,14 +40,14 @@ fn games_browser_item(game: GameInfo) -> Html {
                 <div class="thumbnail"><img src={ game_url.join(&game.thumbnail_file_name).display().to_string() }/></div>
                 
                 <div class="title-wrapper">
-                    <span class="name">{ game.title }</span>
+                    <span class="name">{ &game.title }</span>
                     <span class="platforms">{"For "}<span>{ display_separated(&game.platforms, ", ") }</span></span>
                 </div>
             </a>
 
             <div class="more vertical-wrapper">
-                <span class="genre">{ "Genre: " }<span>{ game.genre }</span></span>
-                <span class="publisher">{ "Published by: " }<span>{ game.publisher }</span></span>
+                <span class="genre">{ "Genre: " }<span>{ &game.genre }</span></span>
+                <span class="publisher">{ "Published by: " }<span>{ &game.publisher }</span></span>
                 <span class="release-year">{ "Published on " }<span>{ game.release_year }</span></span>
                 if let Some(urls) = store_urls(game.store_urls.as_ref()) {
                     <span class="stores">{ "Get it on " }{ urls }</span>
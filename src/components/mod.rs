@@ -1,8 +1,10 @@
 pub mod authenticate;
+pub mod admin;
+pub mod account;
 pub mod osts;
 pub mod games;
 
-use std::{path::{PathBuf, Path}, process::Command, collections::HashMap, sync::RwLock};
+use std::{path::{PathBuf, Path}, collections::HashMap, sync::RwLock};
 use once_cell::sync::Lazy;
 use rocket::{
     // tokio,
@@ -15,7 +17,7 @@ use yew::{
 
 static ICONS_PATH: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("./res/icons/"));
 // The default path where pfps are stored
-static DEFAULT_PFP_PATH: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("./res/users-pfp/"));
+pub static DEFAULT_PFP_PATH: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("./res/users-pfp/"));
 // The path to the default pfp
 static DEFAULT_PFP: Lazy<String> = Lazy::new(|| {
     let data = std::fs::read("res/icons/default-user.svg").expect("No default pfp file");
@@ -46,20 +48,50 @@ fn Head(props: &HeadProps) -> Html {
     }
 }
 
+#[derive(Properties, PartialEq, Eq)]
+pub struct CsrfProps {
+    /// The client's token (see [`CsrfToken`](crate::auth::csrf::CsrfToken)).
+    pub csrf: String,
+}
+/// Hidden field with the CSRF token, which must be the first field of every `<form method="post">`.
+#[function_component]
+pub fn CsrfField(props: &CsrfProps) -> Html {
+    html! {
+        <input type="hidden" name={ crate::auth::csrf::FIELD } value={ props.csrf.clone() }/>
+    }
+}
+
 
 #[derive(Properties, Default, Clone, PartialEq, Eq)]
 pub struct UserInfo {
     #[prop_or_default]
     pub username: Option<String>,
     #[prop_or_default]
-    pub pfp_path: Option<PathBuf>
+    pub pfp_path: Option<PathBuf>,
+    /// Show links to the admin pages.
+    #[prop_or_default]
+    pub is_admin: bool,
+    /// Path of the current page, which the "Log In" link returns to (see [`Self::at()`]).
+    #[prop_or_default]
+    pub path: Option<String>
+}
+impl UserInfo {
+    /// Sets [`Self::path`] to the **uri** of the request.
+    pub fn at(self, uri: &rocket::http::uri::Origin) -> Self {
+        Self {
+            path: Some(uri.to_string()),
+            ..self
+        }
+    }
 }
 impl From<String> for UserInfo {
     /// [`Self::username`] is the string, [`Self::pfp_path`] is [`DEFAULT_PFP_PATH`]`/<username>`.
     fn from(username: String) -> Self {
         Self {
             pfp_path: Some(DEFAULT_PFP_PATH.join(&username)),
-            username: Some(username)
+            username: Some(username),
+            is_admin: false,
+            path: None
         }
     }
 }
@@ -71,13 +103,20 @@ impl From<Option<String>> for UserInfo {
         }
     }
 }
+impl From<crate::auth::User> for UserInfo {
+    fn from(user: crate::auth::User) -> Self {
+        Self {
+            is_admin: user.is_admin(),
+            username: Some(user.name),
+            pfp_path: user.pfp_path,
+            path: None
+        }
+    }
+}
 impl From<Option<crate::auth::User>> for UserInfo {
     fn from(user: Option<crate::auth::User>) -> Self {
         match user {
-            Some(user) => Self {
-                username: Some(user.name),
-                pfp_path: user.pfp_path
-            },
+            Some(user) => Self::from(user),
             None => Self::default()
         }
     }
@@ -85,6 +124,11 @@ impl From<Option<crate::auth::User>> for UserInfo {
 
 #[function_component]
 fn PageHeader(props: &UserInfo) -> Html {
+    let user_link = match &props.username {
+        Some(_) => "/login".to_string(),
+        None => crate::auth::with_next("/login", props.path.as_deref())
+    };
+
     html! {
         <header id="page-header">
             <div class="left">
@@ -101,7 +145,7 @@ fn PageHeader(props: &UserInfo) -> Html {
             </div>
             <div class="right">
                 <div id="user-controls">
-                    <a role="button" href="/login">
+                    <a role="button" href={ user_link }>
                         <div class="pfp-wrapper">{ find_pfp(&props.pfp_path) }</div>
                         <span>{
                             match &props.username {
@@ -121,6 +165,14 @@ fn PageHeader(props: &UserInfo) -> Html {
                                 /* aria-expanded="false" */ />
                             <div class="bottom-align">
                                 <ul id="user-controls-menu">
+                                    if props.is_admin {
+                                        <li><a href="/admin">{ "Admin" }</a></li>
+                                    }
+                                    <li><a href="/account/profile">{ "Profile" }</a></li>
+                                    <li><a href="/account/password">{ "Change password" }</a></li>
+                                    <li><a href="/account/sessions">{ "Sessions" }</a></li>
+                                    <li><a href="/account/tokens">{ "Access tokens" }</a></li>
+                                    <li><a href="/account/2fa">{ "Two-factor authentication" }</a></li>
                                     <li><a href="/logout">{ "Log out" }</a></li>
                                 </ul>
                             </div>
@@ -134,22 +186,15 @@ fn PageHeader(props: &UserInfo) -> Html {
 
 
 /// Helper that tries to find the image of a user's profile-picture.
-/// Returns an `<img>` element linking to the file.
+/// Returns an `<img>` element linking to the file, which is served by [`crate::auth::pictures`].
 /// 
-/// If an image is not found in the specified **path**,
+/// If an image is not found in the specified **path** (see [`crate::auth::pfp::path()`]),
 /// an `SVG` element of the default pfp is returned instead.
-/// 
-/// This function requires the `ImageMagick` package.
-fn find_pfp(path: &Option<PathBuf>) -> Html {
+pub fn find_pfp(path: &Option<PathBuf>) -> Html {
     if let Some(path) = path {
         if let Ok(meta) = std::fs::metadata(path) {
-            if meta.is_file()
-            && Command::new("identify")
-                .arg(path)
-                .output()
-                .is_ok() // file is an image
-            {
-                return html!(<img src={PathBuf::from("/files/").join(path).display().to_string()} alt="PFP"/>)
+            if let (true, Ok(file)) = (meta.is_file(), path.strip_prefix(&*DEFAULT_PFP_PATH)) {
+                return html!(<img src={PathBuf::from("/pfp/").join(file).display().to_string()} alt="PFP"/>)
             }
         }
     }
@@ -2,40 +2,45 @@ use std::path::PathBuf;
 use yew::prelude::*;
 use super::{Document, UserInfo, Icon, item_error};
 use crate::helpers::display_separated;
-use crate::archives::{ Url, osts::{AlbumInfo, SongInfo, SongCover, ALBUMS_PATH}};
-pub use crate::components::UserInfo as AlbumBrowserProps;
+use crate::archives::{ Url, osts::{AlbumInfo, SongInfo, SongCover}};
 
 
+#[derive(Properties, PartialEq, Eq)]
+pub struct AlbumBrowserProps {
+    pub user: UserInfo,
+    /// Only the albums that the user can see.
+    pub albums: Vec<AlbumInfo>,
+    /// Albums that couldn't be read: `(dir_name, error)`.
+    pub errors: Vec<(String, String)>
+}
 #[function_component]
-pub fn AlbumBrowser(props: &UserInfo) -> Html {
+pub fn AlbumBrowser(props: &AlbumBrowserProps) -> Html {
     html! {
-        <Document title="Albums" header={ props.clone() }>
+        <Document title="Albums" header={ props.user.clone() }>
             <link rel="stylesheet" href="/osts/style.css"/>
             <h1>{ "Soundtracks" }</h1>
-            <ul id="albums">{{
-                let (albums, errors) = crate::archives::read_all_dirs::<AlbumInfo>(&*ALBUMS_PATH);
-
-                errors.into_iter()
-                    .map(|(dir_name, error)| item_error(dir_name, error.to_string()))
-                    .chain(albums.into_iter()
+            <ul id="albums">{
+                props.errors.iter()
+                    .map(|(dir_name, error)| item_error(dir_name.clone(), error.clone()))
+                    .chain(props.albums.iter()
                         .map(album_browser_item))
                     .collect::<Html>()
-            }}</ul>
+            }</ul>
         </Document>
     }
 }
-fn album_browser_item(album: AlbumInfo) -> Html {
+fn album_browser_item(album: &AlbumInfo) -> Html {
     html! {
         <li class="item album-item horizontal-wrapper">
-            <a class="horizontal-wrapper" href={ Url::new("/osts/albums/").join(album.dir_name) }>
+            <a class="horizontal-wrapper" href={ Url::new("/osts/albums/").join(&album.dir_name) }>
                 <div class="thumbnail">{ album_cover(&album.cover_path) }</div>
 
                 <div class="title-wrapper">
-                    if let Some(artists) = album.artists {
-                        <span class="name">{ album.name }</span>
-                        <span class="artists-wrapper">{"By "}<span class="artists">{ display_separated(&artists, ", ") }</span></span>
+                    if let Some(artists) = &album.artists {
+                        <span class="name">{ &album.name }</span>
+                        <span class="artists-wrapper">{"By "}<span class="artists">{ display_separated(artists, ", ") }</span></span>
                     } else {
-                        <span class="name full">{ album.name }</span>
+                        <span class="name full">{ &album.name }</span>
                     }
                 </div>
             </a>
@@ -58,7 +63,10 @@ fn album_browser_item(album: AlbumInfo) -> Html {
 #[derive(Properties, PartialEq, Eq)]
 pub struct AlbumProps {
     pub user: UserInfo,
-    pub album: AlbumInfo
+    pub album: AlbumInfo,
+    pub songs: Vec<SongInfo>,
+    /// Songs that couldn't be read: `(file_name, error)`.
+    pub errors: Vec<(String, String)>
 }
 #[function_component]
 pub fn Album(props: &AlbumProps) -> Html {
@@ -94,15 +102,14 @@ pub fn Album(props: &AlbumProps) -> Html {
                 }
             </h4>
 
-            <ul id="songs">{{
-                let (songs, errors) = crate::archives::read_all_files::<SongInfo>(&*ALBUMS_PATH.join(&props.album.dir_name));
-
-                errors.into_iter()
-                    .map(|(file_name, error)| item_error(file_name, error.to_string()))
-                    .chain(songs.into_iter()
+            <ul id="songs">{
+                props.errors.iter()
+                    .map(|(file_name, error)| item_error(file_name.clone(), error.clone()))
+                    .chain(props.songs.iter()
+                        .cloned()
                         .map(song_item))
                     .collect::<Html>()
-            }}</ul>
+            }</ul>
         </Document>
     }
 }
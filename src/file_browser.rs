@@ -1,20 +1,28 @@
+use rocket::State;
 use rocket::http::Status;
 use rocket::tokio::fs::{self, File};
 use rocket_dyn_templates::{Template, context};
 use serde_json::json;
 use std::path::PathBuf;
 use std::io;
+use crate::archives::{AccessConfig, Denied};
 
+/// Directories that can't be browsed, including everything in them (e.g. the indexes in `target/index`).
+/// Profile pictures are only served to logged in users by [`crate::auth::pictures`].
 static EXCLUDED_DIRS: &[&str] = &[
-    "target", ".secrets"
+    "target", ".secrets", "res/users-pfp"
 ];
 
 /// Allows the user to browse the server's filesystem.
+/// Files inside an archive can only be browsed by users that can see that archive (see [`AccessConfig::check_path()`]).
 #[get("/<path..>", rank=4)]
-pub async fn dir_browser(path: PathBuf) -> ResResult {
-    if crate::helpers::eq_one_of(path.to_string_lossy().as_ref(), EXCLUDED_DIRS) {
+pub async fn dir_browser(user: Option<crate::auth::User>, access: &State<AccessConfig>, path: PathBuf) -> ResResult {
+    if EXCLUDED_DIRS.iter().any(|dir| path.starts_with(dir)) {
         return ResResult::Err(Status::Forbidden)
     }
+    if let Err(denied) = access.check_path(&path, user.as_ref()) {
+        return ResResult::Denied(denied)
+    }
 
     match dir_entries(&path).await {
         Ok(entries) => ResResult::Dir({
@@ -55,6 +63,7 @@ pub async fn file(path: &PathBuf) -> io::Result<File> {
 pub enum ResResult {
     File(File),
     Dir(Template),
+    Denied(Denied),
     Err(Status)
 }
 impl ResResult {
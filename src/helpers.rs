@@ -47,7 +47,6 @@ impl Dep {
 #[derive(Debug, Deserialize)]
 pub struct ExternalDeps {
     commands: Vec<Dep>,
-    python: Vec<Dep>,
 }
 impl ExternalDeps {
     /// Returns `false` if there are Missing Dependencies.
@@ -71,23 +70,6 @@ impl ExternalDeps {
             }
         }
 
-        for dep in self.python {
-            let exists = Command::new("python")
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .arg("-c")
-                .arg(format!("import {}", dep.name()))
-                .status()
-                .is_ok_and(|status| status.success());
-            if !exists {
-                eprintln!("Missing python module {:?}. Installation:\n\t{}", dep.name(), dep.installation())
-            }
-            // If was good, is not good now
-            if good {
-                good = exists
-            }
-        }
-
         good
     }
 }
@@ -101,16 +83,6 @@ pub fn strip_extension(path: impl AsRef<Path>) -> PathBuf {
     path.as_ref().with_extension("")
 }
 
-pub fn eq_one_of<'a>(this: &'a str, others: impl AsRef<[&'a str]>) -> bool {
-    for &other in others.as_ref() {
-        if this == other {
-            return true
-        }
-    }
-
-    false
-}
-
 /// Removes the trailing `'\n'` from a [`Command`]'s output (stdout or stderr).
 pub fn command_output(mut output: Vec<u8>) -> String {
     if output.last().is_some_and(|&l| l == b'\n') {
@@ -8,6 +8,8 @@ mod sass;
 mod archives;
 mod auth;
 mod components;
+mod proxy;
+#[cfg(feature = "tls")] mod tls;
 #[cfg(test)] mod tests;
 
 use std::{
@@ -23,7 +25,7 @@ use rocket::{
         content::RawHtml,
         stream::TextStream
     },
-    http::Status,
+    http::{Status, uri::Origin},
     tokio::fs::File,
     form::Form,
     figment::Figment,
@@ -48,11 +50,11 @@ static SECRET_KEY_PATH: &str = "./.secrets/secret-key";
 //     Template::render("index", context! { icons: &**icons })
 // }
 #[get("/")]
-fn index_md(user: Option<auth::User>) -> RawHtml<TextStream<impl async_std::stream::Stream<Item=String>>> {
+fn index_md(user: Option<auth::User>, uri: &Origin<'_>) -> RawHtml<TextStream<impl async_std::stream::Stream<Item=String>>> {
     RawHtml(TextStream(components::render::<components::InnerHtml>(
         components::InnerHtmlProps {
             title: "Home".to_string(),
-            header: user.into(),
+            header: components::UserInfo::from(user).at(uri),
             // TODO: cache markdown output
             content: markdown::to_html(&std::fs::read_to_string("./routes/index.md").expect("no index file"))
         }
@@ -80,10 +82,24 @@ async fn favicon() -> io::Result<File> {
 // }
 
 
+/// Settings of the server that are not part of [`rocket::Config`].
+/// Read from the same sources as rocket's config (e.g. `Rocket.toml`, `ROCKET_*` env variables).
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct ServerConfig {
+    auth: auth::Config,
+    access: archives::AccessConfig,
+    proxy: proxy::ProxyConfig,
+    #[cfg(feature = "tls")]
+    https: tls::HttpsConfig,
+}
+
 fn rocket_config() -> Figment {
-    Config::figment()
+    let figment = Config::figment()
         .merge(("template_dir", "./"))
         .merge(("port", 8000))
+        // The client's IP is only taken from the headers of trusted proxies (see `proxy::ProxyConfig`)
+        .merge(("ip_header", false))
         .merge(("secret_key", match std::fs::read(SECRET_KEY_PATH) {
             Ok(key) => key,
             Err(error) if error.kind() == io::ErrorKind::PermissionDenied =>
@@ -108,7 +124,12 @@ fn rocket_config() -> Figment {
             },
 
             Err(error) => panic!("{error}")
-        }))
+        }));
+
+    #[cfg(feature = "tls")]
+    let figment = tls::configure(figment);
+
+    figment
 }
 
 #[launch]
@@ -121,7 +142,14 @@ fn rocket_server() -> _ {
         std::process::exit(1);
     }
 
-    let rocket = rocket::custom(rocket_config())
+    let figment = rocket_config();
+    let config = figment.extract::<ServerConfig>().expect("Invalid server config");
+
+    let albums = archives::index::Index::<archives::osts::AlbumInfo>::load(archives::index::INDEX_PATH.join("albums.json"));
+    let songs = archives::index::Index::<archives::osts::SongInfo>::load(archives::index::INDEX_PATH.join("songs.json"));
+    archives::watcher::spawn(archives::watcher::Caches { albums: albums.clone(), songs: songs.clone() });
+
+    let rocket = rocket::custom(figment)
         // .mount(projects::ROOT.rocket_base(), projects::routes())
         // .mount(projects::ROOT.rocket_base(), FileServer::from("local-replit"))
         // Auth
@@ -129,6 +157,9 @@ fn rocket_server() -> _ {
         .mount("/logout", auth::logout::routes())
         .mount("/register", auth::register::routes())
         .mount("/admin-register", auth::admin_register::routes())
+        .mount("/account", auth::account::routes())
+        .mount("/admin", auth::admin::routes())
+        .mount("/pfp", auth::pictures::routes())
         // Base
         .mount("/", routes![sass::serve_css])
         .mount("/", routes![index_md, favicon])
@@ -138,7 +169,13 @@ fn rocket_server() -> _ {
         .mount("/games", archives::games::routes())
         
         .attach(Template::fairing())
-        .manage(auth::db::Users::load_default().unwrap()) // load db/users
+        .attach(proxy::ProxyFairing(config.proxy))
+        .attach(auth::SessionsFairing)
+        .attach(auth::csrf::CsrfFairing)
+        .manage(auth::db::Users::load_default(config.auth).unwrap()) // load db/users
+        .manage(config.access)
+        .manage(albums)
+        .manage(songs)
         .manage(std::fs::read_dir("./res/icons").unwrap() // icons
             .filter_map(|entry| {
                 let entry = entry.ok()?;
@@ -150,6 +187,12 @@ fn rocket_server() -> _ {
             ).collect::<Icons>()
         );
 
+    #[cfg(feature = "tls")]
+    let rocket = match config.https.redirect_port {
+        Some(port) => rocket.attach(tls::RedirectFairing(port)),
+        None => rocket
+    };
+
     // TODO:
     for dir in std::fs::read_dir(&*ROUTES_ROOT)
         .expect("must have routes dir")
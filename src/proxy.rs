@@ -0,0 +1,127 @@
+use std::net::{IpAddr, SocketAddr};
+use rocket::{
+    Request, Data, Response,
+    http::{Cookie, Header},
+    fairing::{Fairing, Info, Kind},
+};
+use serde::Deserialize;
+
+
+/// The reverse proxies (e.g. the `nginx` of `nginx.conf`) that the server runs behind.
+/// Set in the `proxy` table of the server's config (e.g. `Rocket.toml`):
+///
+/// ```toml
+/// [default.proxy]
+/// trusted = ["127.0.0.1", "::1"]
+/// ```
+///
+/// The proxies must set the `X-Forwarded-For` and `X-Forwarded-Proto` headers.
+/// Those headers are ignored in requests from anyone else, because any client could forge them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Addresses of the proxies whose `X-Forwarded-*` headers are trusted.
+    pub trusted: Vec<IpAddr>,
+}
+impl ProxyConfig {
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.contains(&ip)
+    }
+
+    /// Finds the client's IP for a request that came from **remote**.
+    /// Each trusted proxy appends the address it received the request from to **forwarded_for**,
+    /// so the client is the last address that was not added by a trusted proxy.
+    pub fn client_ip<'a>(&self, remote: IpAddr, forwarded_for: impl DoubleEndedIterator<Item = &'a str>) -> IpAddr {
+        let mut client = remote;
+        for hop in forwarded_for.rev() {
+            if !self.is_trusted(client) {
+                break
+            }
+            match parse_hop(hop) {
+                Some(ip) => client = ip,
+                None => break
+            }
+        }
+        client
+    }
+}
+
+/// An address in `X-Forwarded-For`, which may also have a port.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim();
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+}
+
+/// Whether the client connected with *https*, cached in the request.
+struct Https(bool);
+
+/// Makes requests that came through a trusted proxy (see [`ProxyConfig`]) look like they came from the client:
+///
+/// * Replaces the request's remote address with the client's, so that [`Request::client_ip()`]
+///   (used for logging and rate limiting) is the real client instead of the proxy.
+/// * Marks all cookies sent to the client as `Secure` when the client connected with *https*,
+///   either to a proxy or to the server's own TLS.
+///   Otherwise they are not `Secure`, since clients would not send them back over plain *http*.
+pub struct ProxyFairing(pub ProxyConfig);
+#[rocket::async_trait]
+impl Fairing for ProxyFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Reverse proxy headers",
+            kind: Kind::Request | Kind::Response
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let remote = match req.remote() {
+            Some(remote) => remote,
+            None => return
+        };
+
+        let https = if self.0.is_trusted(remote.ip()) {
+            let forwarded_for = req.headers().get("X-Forwarded-For")
+                .flat_map(|header| header.split(','))
+                .collect::<Vec<_>>();
+            let client = self.0.client_ip(remote.ip(), forwarded_for.into_iter());
+            let https = req.headers().get_one("X-Forwarded-Proto")
+                .and_then(|proto| proto.split(',').next())
+                .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"));
+
+            req.set_remote(SocketAddr::new(client, remote.port()));
+            https
+        } else {
+            req.rocket().config().tls_enabled()
+        };
+        req.local_cache(|| Https(https));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if !is_https(req) {
+            return
+        }
+
+        let cookies = res.headers().get("Set-Cookie")
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if cookies.is_empty() {
+            return
+        }
+        res.remove_header("Set-Cookie");
+        for cookie in cookies {
+            match Cookie::parse(cookie.clone()) {
+                Ok(mut cookie) => {
+                    cookie.set_secure(true);
+                    res.adjoin_header(cookie);
+                },
+                Err(_) => res.adjoin_header(Header::new("Set-Cookie", cookie))
+            }
+        }
+    }
+}
+
+/// Whether the client of the **req**uest connected with *https*. Requires the [`ProxyFairing`].
+pub fn is_https(req: &Request<'_>) -> bool {
+    req.local_cache(|| Https(req.rocket().config().tls_enabled())).0
+}
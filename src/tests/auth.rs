@@ -3,7 +3,7 @@
 use crate::rocket_server;
 use std::process::Command;
 use rocket::{
-    http::{Status, ContentType},
+    http::{Status, ContentType, Header},
     local::blocking::Client,
 };
 
@@ -76,11 +76,27 @@ fn auth() {
     // Set up admin user so we are able to go to /login and /register.
     response = client.get("/admin-register").dispatch();
     assert_eq!(response.status(), Status::Ok);
-    // Send (POST) new password for admin
+    // The page's form has the client's CSRF token
+    let csrf = client.cookies().get_private(crate::auth::csrf::CSRF_COOKIE)
+        .expect("Client wasn't sent a CSRF token")
+        .value().to_string();
+    assert!(response.into_string().unwrap().contains(&csrf));
+    // Forms without the CSRF token are rejected
     response = client.post("/admin-register")
         .header(ContentType::Form)
         .body("password=password")
         .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    response = client.post("/admin-register")
+        .header(ContentType::Form)
+        .body("csrf_token=wrong&password=password")
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    // Send (POST) new password for admin
+    response = client.post("/admin-register")
+        .header(ContentType::Form)
+        .body(format!("csrf_token={csrf}&password=password"))
+        .dispatch();
     assert_eq!(response.status(), Status::SeeOther);
     assert_eq!(response.headers().get_one("Location"), Some("/login"));
 
@@ -90,13 +106,23 @@ fn auth() {
     // Send (POST) user credentials
     response = client.post("/login")
         .header(ContentType::Form)
-        .body("username=admin&password=password")
+        .body(format!("csrf_token={csrf}&username=admin&password=password"))
         .dispatch();
     // Check the client was sent a "session_uuid" cookie
     assert!(client.cookies().get(crate::auth::SESSION_COOKIE).is_some());
     // After logging in, Redirects to root (/)
     assert_eq!(response.status(), Status::SeeOther);
     assert_eq!(response.headers().get_one("Location"), Some("/"));
+    // Logged in users are sent back where they were going, but only within the site
+    response = client.get("/login?next=%2Fadmin").dispatch();
+    assert_eq!(response.headers().get_one("Location"), Some("/admin"));
+    response = client.get("/login?next=%2F%2Fevil.com").dispatch();
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+    // A client with a session can't skip the CSRF check with an Authorization header
+    response = client.post("/account/sessions/revoke-others")
+        .header(Header::new("Authorization", "Bearer forged"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
 
     // Admin can register new users
     response = client.get("/register").dispatch();
@@ -0,0 +1,112 @@
+use std::{cell::Cell, fs, path::{Path, PathBuf}, process::Command};
+use serde::{Serialize, Deserialize};
+use crate::archives::{FromDir, INFO_FILE_NAME, index::Index};
+
+thread_local! {
+    /// How many times [`Item::read_dir()`] was called in this test.
+    static READS: Cell<usize> = const { Cell::new(0) };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Item(String);
+impl FromDir for Item {
+    type Error = String;
+    fn read_dir(dir: &Path) -> Result<Self, Self::Error> {
+        READS.set(READS.get() + 1);
+        let info = fs::read_to_string(dir.join(INFO_FILE_NAME)).map_err(|error| error.to_string())?;
+        info.strip_prefix("item ")
+            .map(|name| Self(name.to_string()))
+            .ok_or_else(|| format!("Invalid info: {info}"))
+    }
+}
+
+fn temp_dir() -> PathBuf {
+    let mut stdout = Command::new("mktemp").arg("-d")
+        .output().unwrap()
+        .stdout;
+    stdout.pop();
+    PathBuf::from(String::from_utf8(stdout).unwrap())
+}
+
+fn write_item(dir: &Path, name: &str, info: &str) {
+    fs::create_dir_all(dir.join(name)).unwrap();
+    fs::write(dir.join(name).join(INFO_FILE_NAME), info).unwrap();
+}
+
+fn items(names: &[&str]) -> Vec<Item> {
+    names.iter().map(|name| Item(name.to_string())).collect()
+}
+
+#[test]
+fn read_only_changed() {
+    let root = temp_dir();
+    let dir = root.join("items");
+    let index_path = root.join("index").join("items.json");
+    write_item(&dir, "a", "item a");
+    write_item(&dir, "b", "item b");
+    write_item(&dir, "broken", "not an item");
+
+    let index = Index::<Item>::load(index_path.clone());
+    let (read, errors) = index.read_all_dirs(&dir).unwrap();
+    assert_eq!(read, items(&["a", "b"]));
+    assert_eq!(errors, vec![("broken".to_string(), "Invalid info: not an item".to_string())]);
+    assert_eq!(READS.get(), 3);
+
+    // Nothing changed, errors included
+    let (read, errors) = index.read_all_dirs(&dir).unwrap();
+    assert_eq!(read, items(&["a", "b"]));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(READS.get(), 3);
+
+    // Changed, added and deleted items
+    write_item(&dir, "b", "item b2");
+    write_item(&dir, "c", "item c");
+    fs::remove_dir_all(dir.join("broken")).unwrap();
+    let (read, errors) = index.read_all_dirs(&dir).unwrap();
+    assert_eq!(read, items(&["a", "b2", "c"]));
+    assert!(errors.is_empty());
+    assert_eq!(READS.get(), 5);
+
+    // The index is kept when the server restarts
+    let index = Index::<Item>::load(index_path.clone());
+    let (read, _) = index.read_all_dirs(&dir).unwrap();
+    assert_eq!(read, items(&["a", "b2", "c"]));
+    assert_eq!(READS.get(), 5);
+
+    // An invalid index is built again
+    fs::write(&index_path, "not json").unwrap();
+    let index = Index::<Item>::load(index_path);
+    let (read, _) = index.read_all_dirs(&dir).unwrap();
+    assert_eq!(read, items(&["a", "b2", "c"]));
+    assert_eq!(READS.get(), 8);
+
+    // A directory that doesn't exist (anymore) is an error instead of a panic
+    assert!(index.read_all_dirs(&root.join("removed")).is_err());
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn invalidate() {
+    let root = temp_dir();
+    let dir = root.join("items");
+    write_item(&dir, "a", "item a");
+    write_item(&dir, "b", "item b");
+
+    let index = Index::<Item>::load(root.join("items.json"));
+    assert_eq!(index.read_all_dirs(&dir).unwrap().0, items(&["a", "b"]));
+
+    // Replaced by a file with the same stamp, which only the watcher notices
+    let info = dir.join("b").join(INFO_FILE_NAME);
+    let modified = fs::metadata(&info).unwrap().modified().unwrap();
+    fs::write(&info, "item c").unwrap();
+    fs::File::options().write(true).open(&info).unwrap().set_modified(modified).unwrap();
+    assert_eq!(index.read_all_dirs(&dir).unwrap().0, items(&["a", "b"]));
+
+    // Clones share the items
+    index.clone().invalidate(&dir.join("b"));
+    assert_eq!(index.read_all_dirs(&dir).unwrap().0, items(&["a", "c"]));
+    assert_eq!(READS.get(), 3);
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,66 @@
+use std::net::{IpAddr, SocketAddr};
+use rocket::{
+    Config,
+    http::{Cookie, CookieJar, Header},
+    local::blocking::Client,
+};
+use crate::proxy::{ProxyConfig, ProxyFairing};
+
+/// Responds with the client's IP, and sets a cookie.
+#[get("/")]
+fn client_ip(jar: &CookieJar<'_>, ip: Option<IpAddr>) -> String {
+    jar.add(Cookie::new("test", "cookie"));
+    ip.map(|ip| ip.to_string()).unwrap_or_default()
+}
+
+fn client() -> Client {
+    let config = Config {
+        ip_header: None,
+        ..Config::debug_default()
+    };
+    let proxy = ProxyConfig {
+        trusted: vec![IpAddr::from([127, 0, 0, 1]), IpAddr::from([10, 0, 0, 1])]
+    };
+    Client::untracked(rocket::custom(config)
+        .mount("/", routes![client_ip])
+        .attach(ProxyFairing(proxy))
+    ).expect("Can't create Rocket instance")
+}
+
+#[test]
+fn proxy_headers() {
+    let client = client();
+    let proxy: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+    let stranger: SocketAddr = "198.51.100.1:4000".parse().unwrap();
+
+    // Only a trusted proxy can tell who the client is
+    let response = client.get("/")
+        .remote(stranger)
+        .header(Header::new("X-Forwarded-For", "203.0.113.7"))
+        .header(Header::new("X-Forwarded-Proto", "https"))
+        .dispatch();
+    assert!(!response.headers().get_one("Set-Cookie").unwrap().contains("Secure"));
+    assert_eq!(response.into_string().unwrap(), "198.51.100.1");
+
+    // The client is the last address that wasn't added by a trusted proxy
+    let response = client.get("/")
+        .remote(proxy)
+        .header(Header::new("X-Forwarded-For", "192.0.2.5, 203.0.113.7, 10.0.0.1"))
+        .header(Header::new("X-Forwarded-Proto", "https"))
+        .dispatch();
+    assert!(response.headers().get_one("Set-Cookie").unwrap().contains("Secure"));
+    assert_eq!(response.into_string().unwrap(), "203.0.113.7");
+
+    // Plain http
+    let response = client.get("/")
+        .remote(proxy)
+        .header(Header::new("X-Forwarded-For", "203.0.113.7:5000"))
+        .header(Header::new("X-Forwarded-Proto", "http"))
+        .dispatch();
+    assert!(!response.headers().get_one("Set-Cookie").unwrap().contains("Secure"));
+    assert_eq!(response.into_string().unwrap(), "203.0.113.7");
+
+    // Without the header the proxy is the client
+    let response = client.get("/").remote(proxy).dispatch();
+    assert_eq!(response.into_string().unwrap(), "127.0.0.1");
+}
@@ -0,0 +1,272 @@
+use std::io::Cursor;
+use data_encoding::BASE64;
+use crate::archives::tags::{Tags, TagError, FRONT_COVER};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot really an image";
+
+fn read(file: Vec<u8>, pictures: bool) -> Tags {
+    Tags::read_from(&mut Cursor::new(file), pictures).unwrap()
+}
+
+fn assert_duration(tags: &Tags, secs: f64) {
+    let duration = tags.duration.expect("No duration").as_secs_f64();
+    assert!((duration - secs).abs() < 0.01, "duration is {duration}, expected {secs}");
+}
+
+/// An ID3v2.3 frame.
+fn id3_frame(id: &str, data: &[u8]) -> Vec<u8> {
+    [id.as_bytes(), &(data.len() as u32).to_be_bytes(), &[0, 0], data].concat()
+}
+fn id3_tag(frames: &[Vec<u8>]) -> Vec<u8> {
+    let frames = frames.concat();
+    // Padding
+    let size = frames.len() as u32 + 16;
+    let syncsafe = [(size >> 21) as u8 & 0x7F, (size >> 14) as u8 & 0x7F, (size >> 7) as u8 & 0x7F, size as u8 & 0x7F];
+    [b"ID3\x03\x00\x00".as_slice(), &syncsafe, &frames, &[0; 16]].concat()
+}
+/// MPEG 1 layer 3 frames at 128kbps and 44.1kHz, which are 417 bytes long.
+fn mp3_frames(count: usize) -> Vec<u8> {
+    let mut frame = vec![0; 417];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+    frame.repeat(count)
+}
+
+#[test]
+fn id3_mp3() {
+    let utf16 = [&[1, 0xFF, 0xFE][..], &"Ärtist".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>()].concat();
+    let file = [
+        id3_tag(&[
+            id3_frame("TIT2", b"\x03Song title"),
+            id3_frame("TPE1", &utf16),
+            id3_frame("TALB", b"\x00Album"),
+            id3_frame("TYER", b"\x001998"),
+            id3_frame("TRCK", b"\x002/12"),
+            id3_frame("APIC", &[b"\x00image/png\x00\x03Cover\x00".as_slice(), PNG].concat()),
+        ]),
+        mp3_frames(100)
+    ].concat();
+
+    let tags = read(file.clone(), true);
+    assert_eq!(tags.title.as_deref(), Some("Song title"));
+    assert_eq!(tags.artist.as_deref(), Some("Ärtist"));
+    assert_eq!(tags.album.as_deref(), Some("Album"));
+    assert_eq!(tags.album_artist, None);
+    assert_eq!(tags.release_year(), Some(1998));
+    assert_eq!(tags.track_number(), Some(2));
+    // Constant bitrate: 100 frames of 417 bytes at 128kbps
+    assert_duration(&tags, 100.0 * 417.0 * 8.0 / 128000.0);
+
+    let cover = tags.cover().unwrap();
+    assert_eq!((cover.mime.as_str(), cover.kind, cover.data.as_slice()), ("image/png", FRONT_COVER, PNG));
+    assert_eq!(cover.extension(), "png");
+    assert!(read(file, false).pictures.is_empty());
+}
+
+#[test]
+fn mp3_vbr() {
+    // A Xing header with the number of frames, after the side information of a stereo MPEG 1 frame
+    let mut file = mp3_frames(2);
+    file[36..48].copy_from_slice(&[b"Xing".as_slice(), &1u32.to_be_bytes(), &1000u32.to_be_bytes()].concat());
+
+    let tags = read(file, false);
+    assert_eq!(tags.title, None);
+    assert_duration(&tags, 1000.0 * 1152.0 / 44100.0);
+}
+
+/// A FLAC metadata block.
+fn flac_block(kind: u8, last: bool, data: &[u8]) -> Vec<u8> {
+    let len = (data.len() as u32).to_be_bytes();
+    [&[kind | if last { 0x80 } else { 0 }, len[1], len[2], len[3]], data].concat()
+}
+fn picture_block(mime: &str, data: &[u8]) -> Vec<u8> {
+    [
+        &3u32.to_be_bytes()[..], &(mime.len() as u32).to_be_bytes(), mime.as_bytes(),
+        &0u32.to_be_bytes(), &[0; 16], &(data.len() as u32).to_be_bytes(), data
+    ].concat()
+}
+fn vorbis_comments(comments: &[&str]) -> Vec<u8> {
+    let mut data = [&4u32.to_le_bytes()[..], b"test", &(comments.len() as u32).to_le_bytes()].concat();
+    for comment in comments {
+        data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        data.extend_from_slice(comment.as_bytes());
+    }
+    data
+}
+
+#[test]
+fn flac() {
+    // 10 seconds at 44.1kHz
+    let mut streaminfo = vec![0; 34];
+    let bits: u64 = 44100 << 44 | 1 << 41 | 15 << 36 | 441000;
+    streaminfo[10..18].copy_from_slice(&bits.to_be_bytes());
+
+    let file = [
+        b"fLaC".to_vec(),
+        flac_block(0, false, &streaminfo),
+        // Padding
+        flac_block(1, false, &[0; 100]),
+        flac_block(4, false, &vorbis_comments(&[
+            "TITLE=Flac song", "artist=First", "ARTIST=Second", "ALBUMARTIST=Composer", "DATE=2001-05-06", "TRACKNUMBER=3"
+        ])),
+        flac_block(6, true, &picture_block("image/jpeg", b"jpeg data")),
+    ].concat();
+
+    let tags = read(file, true);
+    assert_eq!(tags.title.as_deref(), Some("Flac song"));
+    // Keys are case insensitive, and only the first value is kept
+    assert_eq!(tags.artist.as_deref(), Some("First"));
+    assert_eq!(tags.album_artist.as_deref(), Some("Composer"));
+    assert_eq!(tags.release_year(), Some(2001));
+    assert_eq!(tags.track_number(), Some(3));
+    assert_duration(&tags, 10.0);
+    assert_eq!(tags.cover().unwrap().extension(), "jpg");
+    assert_eq!(tags.pictures[0].data, b"jpeg data");
+}
+
+fn ogg_page(granule: u64, sequence: u32, packet: &[u8]) -> Vec<u8> {
+    let mut lacing = vec![255; packet.len() / 255];
+    lacing.push((packet.len() % 255) as u8);
+    [
+        b"OggS\x00\x00".as_slice(), &granule.to_le_bytes(), &1234u32.to_le_bytes(), &sequence.to_le_bytes(), &[0; 4],
+        &[lacing.len() as u8], &lacing, packet
+    ].concat()
+}
+
+#[test]
+fn ogg_opus() {
+    let head = [b"OpusHead\x01\x02".as_slice(), &312u16.to_le_bytes(), &48000u32.to_le_bytes(), &[0, 0, 0]].concat();
+    let image = PNG.repeat(20);
+    let picture = format!("METADATA_BLOCK_PICTURE={}", BASE64.encode(&picture_block("image/png", &image)));
+    let comments = [b"OpusTags".as_slice(), &vorbis_comments(&["TITLE=Opus song", "ALBUM=Album", &picture])].concat();
+    // Spans multiple segments
+    assert!(comments.len() > 255);
+
+    let file = [
+        ogg_page(0, 0, &head),
+        ogg_page(0, 1, &comments),
+        ogg_page(48000 * 5 + 312, 2, &[0; 300]),
+    ].concat();
+
+    let tags = read(file.clone(), true);
+    assert_eq!(tags.title.as_deref(), Some("Opus song"));
+    assert_eq!(tags.album.as_deref(), Some("Album"));
+    assert_duration(&tags, 5.0);
+    assert_eq!(tags.cover().unwrap().data, image);
+    assert!(read(file, false).pictures.is_empty());
+}
+
+fn mp4_box(name: &[u8], content: &[u8]) -> Vec<u8> {
+    [&(content.len() as u32 + 8).to_be_bytes()[..], name, content].concat()
+}
+fn mp4_item(name: &[u8], kind: u32, value: &[u8]) -> Vec<u8> {
+    mp4_box(name, &mp4_box(b"data", &[&kind.to_be_bytes()[..], &[0; 4], value].concat()))
+}
+
+#[test]
+fn mp4() {
+    // 4.5 seconds
+    let mvhd = [&[0; 12][..], &1000u32.to_be_bytes(), &4500u32.to_be_bytes(), &[0; 80]].concat();
+    let ilst = [
+        mp4_item(b"\xA9nam", 1, "Títle".as_bytes()),
+        mp4_item(b"\xA9ART", 1, b"Artist"),
+        mp4_item(b"aART", 1, b"Album artist"),
+        mp4_item(b"\xA9day", 1, b"2010"),
+        mp4_item(b"trkn", 0, &[0, 0, 0, 7, 0, 9, 0, 0]),
+        mp4_item(b"covr", 14, PNG),
+    ].concat();
+    let meta = [&[0; 4][..], &mp4_box(b"hdlr", &[0; 25]), &mp4_box(b"ilst", &ilst)].concat();
+    let moov = [mp4_box(b"mvhd", &mvhd), mp4_box(b"udta", &mp4_box(b"meta", &meta))].concat();
+
+    let file = [
+        mp4_box(b"ftyp", b"M4A \x00\x00\x00\x00"),
+        mp4_box(b"mdat", &[0; 1000]),
+        mp4_box(b"moov", &moov),
+    ].concat();
+
+    let tags = read(file, true);
+    assert_eq!(tags.title.as_deref(), Some("Títle"));
+    assert_eq!(tags.artist.as_deref(), Some("Artist"));
+    assert_eq!(tags.album_artist.as_deref(), Some("Album artist"));
+    assert_eq!(tags.release_year(), Some(2010));
+    assert_eq!(tags.track.as_deref(), Some("7/9"));
+    assert_duration(&tags, 4.5);
+    assert_eq!(tags.cover().unwrap().mime, "image/png");
+}
+
+/// An EBML element, with its size in 8 bytes.
+fn ebml(id: u32, data: &[u8]) -> Vec<u8> {
+    let id = id.to_be_bytes();
+    let id = &id[id.iter().position(|&byte| byte != 0).unwrap()..];
+    [id, &[0x01], &(data.len() as u64).to_be_bytes()[1..], data].concat()
+}
+fn simple_tag(name: &str, value: &str) -> Vec<u8> {
+    ebml(0x67C8, &[ebml(0x45A3, name.as_bytes()), ebml(0x4487, value.as_bytes())].concat())
+}
+
+#[test]
+fn webm() {
+    let header = ebml(0x1A45DFA3, &ebml(0x4282, b"webm"));
+    let info = ebml(0x1549A966, &[
+        ebml(0x2AD7B1, &[0x0F, 0x42, 0x40]),
+        ebml(0x4489, &7000f64.to_be_bytes()),
+    ].concat());
+    let cluster = ebml(0x1F43B675, &[0; 500]);
+    let tags = ebml(0x1254C367, &[
+        ebml(0x7373, &[
+            ebml(0x63C0, &ebml(0x68CA, &[50])),
+            simple_tag("TITLE", "Album"),
+            simple_tag("ARTIST", "Album artist"),
+        ].concat()),
+        ebml(0x7373, &[
+            simple_tag("TITLE", "Song"),
+            simple_tag("ARTIST", "Artist"),
+            simple_tag("PART_NUMBER", "4"),
+            simple_tag("DATE_RELEASED", "2020-01-01"),
+        ].concat()),
+    ].concat());
+    let attachments = ebml(0x1941A469, &ebml(0x61A7, &[
+        ebml(0x466E, b"cover.png"),
+        ebml(0x4660, b"image/png"),
+        ebml(0x465C, PNG),
+    ].concat()));
+
+    // Segment of unknown size, like in live streams
+    let file = [
+        header,
+        vec![0x18, 0x53, 0x80, 0x67, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+        info, cluster, tags, attachments
+    ].concat();
+
+    let tags = read(file, true);
+    assert_eq!(tags.title.as_deref(), Some("Song"));
+    assert_eq!(tags.artist.as_deref(), Some("Artist"));
+    assert_eq!(tags.album.as_deref(), Some("Album"));
+    assert_eq!(tags.album_artist.as_deref(), Some("Album artist"));
+    assert_eq!(tags.track_number(), Some(4));
+    assert_eq!(tags.release_year(), Some(2020));
+    assert_duration(&tags, 7.0);
+    let cover = tags.cover().unwrap();
+    assert_eq!((cover.kind, cover.data.as_slice()), (FRONT_COVER, PNG));
+}
+
+#[test]
+fn unknown_format() {
+    let result = Tags::read_from(&mut Cursor::new(b"just some text, not audio".to_vec()), false);
+    assert!(matches!(result, Err(TagError::UnknownFormat)));
+    // Truncated files are errors instead of panics
+    assert!(Tags::read_from(&mut Cursor::new(b"fLaC\x00\x00\x00\x22".to_vec()), false).is_err());
+    // And so are sizes that overflow
+    let file = [&mp4_box(b"ftyp", b"M4A \x00\x00\x00\x00")[..], &1u32.to_be_bytes(), b"moov", &u64::MAX.to_be_bytes()].concat();
+    assert!(matches!(Tags::read_from(&mut Cursor::new(file), false), Err(TagError::Invalid("MP4"))));
+    // or that end inside their own header
+    let file = [&mp4_box(b"ftyp", b"M4A \x00\x00\x00\x00")[..], &mp4_box(b"moov", &[&4u32.to_be_bytes()[..], b"mvhd"].concat())].concat();
+    assert!(matches!(Tags::read_from(&mut Cursor::new(file), false), Err(TagError::Invalid("MP4"))));
+    let file = [&mp4_box(b"ftyp", b"M4A \x00\x00\x00\x00")[..], &1u32.to_be_bytes(), b"moov", &12u64.to_be_bytes()].concat();
+    assert!(matches!(Tags::read_from(&mut Cursor::new(file), false), Err(TagError::Invalid("MP4"))));
+    // A duration too long for a `Duration` is unknown
+    let info = ebml(0x1549A966, &ebml(0x4489, &1e300f64.to_be_bytes()));
+    let file = [ebml(0x1A45DFA3, &ebml(0x4282, b"webm")), ebml(0x18538067, &info)].concat();
+    assert_eq!(read(file, false).duration, None);
+    // Only 4 digit years
+    assert_eq!(Tags { date: Some("98".to_string()), ..Tags::default() }.release_year(), None);
+}
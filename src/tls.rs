@@ -0,0 +1,124 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use rocket::{
+    Config, Rocket, Orbit,
+    http::uri::{Host, Origin},
+    response::Redirect,
+    figment::Figment,
+    fairing::{Fairing, Info, Kind},
+};
+use serde::Deserialize;
+
+
+/// Serving *https* directly from the server (with the `tls` feature), instead of from a proxy like in `nginx.conf`.
+/// Set in the `https` table of the server's config (e.g. `Rocket.toml`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpsConfig {
+    /// Certificate chain in *PEM* format.
+    pub certs: PathBuf,
+    /// Private key of the certificate in *PEM* format.
+    pub key: PathBuf,
+    /// Creates a self-signed certificate for `localhost` if there is none at [`Self::certs`].
+    /// Browsers don't trust such a certificate, so it is only for development.
+    pub self_signed: bool,
+    /// Port of a plain *http* listener that redirects everything to the server's *https* port.
+    pub redirect_port: Option<u16>,
+}
+impl Default for HttpsConfig {
+    fn default() -> Self {
+        Self {
+            certs: PathBuf::from("./.secrets/tls/cert.pem"),
+            key: PathBuf::from("./.secrets/tls/key.pem"),
+            self_signed: cfg!(debug_assertions),
+            redirect_port: None,
+        }
+    }
+}
+
+/// Makes rocket serve *https* with the certificate of the [`HttpsConfig`] in the **figment**.
+pub fn configure(figment: Figment) -> Figment {
+    let config = figment.extract_inner::<HttpsConfig>("https")
+        .unwrap_or_default();
+
+    if config.self_signed && !config.certs.exists() {
+        if let Err(error) = self_signed_cert(&config.certs, &config.key) {
+            panic!("Can't create a self-signed certificate at {:?}: {error}", config.certs)
+        }
+    }
+
+    figment
+        .merge(("tls.certs", config.certs))
+        .merge(("tls.key", config.key))
+}
+
+/// Uses `openssl` to create a certificate for `localhost` that is valid for a year.
+/// `openssl` is not in `deps.json`, since it is only needed here.
+fn self_signed_cert(certs: &Path, key: &Path) -> io::Result<()> {
+    for dir in [certs.parent(), key.parent()].into_iter().flatten() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let status = Command::new("openssl")
+        .args(["req", "-x509", "-newkey", "rsa:2048", "-nodes", "-days", "365"])
+        .args(["-subj", "/CN=localhost", "-addext", "subjectAltName=DNS:localhost,IP:127.0.0.1,IP:::1"])
+        .arg("-keyout").arg(key)
+        .arg("-out").arg(certs)
+        .status()
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => io::Error::other("Missing \"openssl\" command. Installation:\n\tsudo dnf install openssl"),
+            _ => error
+        })?;
+    if !status.success() {
+        return Err(io::Error::other(format!("openssl exited with {status}")))
+    }
+    println!("Created a self-signed certificate at {certs:?}");
+    Ok(())
+}
+
+/// The port that [`redirect()`] sends clients to.
+struct HttpsPort(u16);
+
+#[get("/<_..>")]
+fn redirect(host: Option<&Host<'_>>, uri: &Origin<'_>, port: &rocket::State<HttpsPort>) -> Option<Redirect> {
+    let domain = host?.domain();
+    Some(Redirect::permanent(match port.0 {
+        443 => format!("https://{domain}{uri}"),
+        port => format!("https://{domain}:{port}{uri}"),
+    }))
+}
+
+/// Launches the listener on the [`HttpsConfig::redirect_port`] with the server,
+/// which redirects *http* requests to the same page with *https*.
+pub struct RedirectFairing(pub u16);
+#[rocket::async_trait]
+impl Fairing for RedirectFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "HTTP to HTTPS redirect",
+            kind: Kind::Liftoff
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let config = Config {
+            port: self.0,
+            tls: None,
+            ..rocket.config().clone()
+        };
+        let https_port = rocket.config().port;
+
+        rocket::tokio::spawn(async move {
+            let result = rocket::custom(config)
+                .manage(HttpsPort(https_port))
+                .mount("/", routes![redirect])
+                .launch().await;
+            if let Err(error) = result {
+                eprintln!("The http redirect listener failed: {error}");
+            }
+        });
+    }
+}